@@ -1,14 +1,346 @@
 #!/usr/bin/env node
 import { program } from 'commander';
-import { run } from '../lib/sopsify.js';
+import { run } from '../lib/index.js';
+import { tfSource } from '../lib/tf-source.js';
+import { dockerSecrets } from '../lib/docker-secrets.js';
+import { systemdCreds } from '../lib/systemd-creds.js';
+import { drift } from '../lib/drift.js';
+import { importSops } from '../lib/import-sops.js';
+import { init } from '../lib/init.js';
+import { migrateHelmSecrets } from '../lib/helm-migrate.js';
+import { scan } from '../lib/scan.js';
+import { verify } from '../lib/verify.js';
+import { check } from '../lib/check.js';
+import { doctor } from '../lib/doctor.js';
+import { lint } from '../lib/lint.js';
+import { diff } from '../lib/diff.js';
+import { decrypt } from '../lib/decrypt.js';
+import { docs } from '../lib/docs.js';
+import { bench } from '../lib/bench.js';
+import { list } from '../lib/list.js';
+import { tree } from '../lib/tree.js';
+import { rotateKeys } from '../lib/rotate.js';
+import { edit } from '../lib/edit.js';
+import { explain } from '../lib/explain.js';
+import { renameNamespace } from '../lib/rename-namespace.js';
+import { renameKey } from '../lib/rename-key.js';
+import { diffRunReports, mergeRunReports } from '../lib/run-report.js';
+import { applyUserDefaults } from '../lib/user-config.js';
+import { useProject, resolveInvocationPaths, fromInvocation, forwardProgramOptions } from '../lib/config.js';
+import { presetOptions } from '../lib/presets.js';
+import { useSopsBinary, useSopsArgs } from '../lib/backends.js';
+
+// Repeatable options collect their values
+const collect = (value, previous) => [...previous, value];
 
 program
 	.version('1.0.0')
-	.option('-t, --templates <FOLDER>', 'A folder containing template files to encrypt')
-	.parse();
+	.option('-c, --config <FILE>', 'Config file (default: the nearest .sopsify.yaml in this or a parent folder)')
+	.option('--sops-bin <PATH>', 'sops binary to use for every command (default: $SOPSIFY_SOPS_BIN or sops from PATH)')
+	.option('--sops-arg <ARG>', 'Extra argument for every sops call, e.g. --sops-arg=--aws-profile=prod; repeatable, after sops_args of .sopsify.yaml', collect, [])
+	.hook('preAction', async (thisCommand, actionCommand) => {
+		forwardProgramOptions(program, actionCommand);
+		await applyUserDefaults(actionCommand);
+		// Options of the program, such as --sops-bin, apply to every command
+		if (actionCommand !== program) {
+			await applyUserDefaults(program);
+		}
+		// Commands creating a config start a project of their own
+		if (!['init', 'import-sops', 'migrate-helm-secrets'].includes(actionCommand.name())) {
+			useProject(program.opts().config);
+		}
+		resolveInvocationPaths(actionCommand);
+		// `run <PRESET>` renders with the options of the program
+		if (actionCommand !== program) {
+			resolveInvocationPaths(program);
+		}
+		// A bare name is looked up in PATH
+		const { sopsBin, sopsArg } = program.opts();
+		if (sopsBin) {
+			useSopsBinary(sopsBin.includes('/') ? fromInvocation(sopsBin) : sopsBin);
+		}
+		useSopsArgs(sopsArg);
+	})
+	.option('-t, --templates <FOLDER>', 'A folder (or .tar, .tar.gz, .tgz, .zip archive) containing template files to encrypt; repeatable', collect, [])
+	.option('-f, --file <FILE>', 'A single template file to encrypt; repeatable, may be combined with -t', collect, [])
+	.option('--include <GLOB>', 'Only read template files of the -t folders matching this glob, e.g. \'apps/**/*.yaml\'; repeatable', collect, [])
+	.option('--exclude <GLOB>', 'Skip template files of the -t folders matching this glob, e.g. \'**/README*\'; repeatable', collect, [])
+	.option('--backend <BACKEND>', 'Encryption backend: sops, ansible-vault or rops (sops files for age keys, no sops binary; alias builtin)', 'sops')
+	.option('--vault-password-file <FILE>', 'Password file for the ansible-vault backend')
+	.option('--vault-id <ID>', 'Vault ID label for the ansible-vault backend')
+	.option('-n, --namespace <NAME>', 'Only render this namespace; repeatable, globs like team-* are allowed', collect, [])
+	.option('--namespaces-from-cluster', 'Only render namespaces that exist in the live cluster (via kubectl)')
+	.option('--selector <SELECTOR>', 'Label selector for --namespaces-from-cluster, e.g. team=payments')
+	.option('--shard <INDEX/COUNT>', 'Only render this share of the template/namespace pairs, e.g. 2/5 in the second of five CI jobs')
+	.option('--server-dry-run', 'Validate rendered manifests with `kubectl apply --dry-run=server` before encrypting')
+	.option('--kube-context <CONTEXT>', 'kubectl context to use (default: current context)')
+	.option('--allow-tracked-plaintext', 'Allow writing intermediate plaintext files to paths git does not ignore')
+	.option('--matrix <FILE>', 'Also read values from a CSV matrix (rows are keys, columns namespaces), e.g. a spreadsheet export')
+	.option('--allow-duplicate-keys', 'Warn instead of failing when entries give a key more than one value for a namespace; the last entry wins')
+	.option('--fix-perms', 'Restrict group/world-readable config and key files to the owner')
+	.option('--clean-temp', 'Remove temporary plaintext and staged files crashed runs left below the output roots')
+	.option('--split-documents', 'Write non-Secret documents of multi-document templates unencrypted to clusters/<cluster>/manifests/')
+	.option('--no-encrypt', 'Render-only: write plaintext outputs without calling the backend, e.g. to preview templates')
+	.option('--strict', 'Fail listing every unresolved placeholder, ignoring on_unresolved')
+	.option('--on-all-skipped <POLICY>', 'When every template/namespace is skipped, or there is none, and nothing is written: error (default), warn or ignore; overrides on_all_skipped')
+	.option('--no-namespace-injection', 'Leave metadata.namespace of the templates as it is instead of setting the namespace')
+	.option('--global', 'Write templates without placeholders once per cluster (clusters/<cluster>/global/) instead of per namespace')
+	.option('-j, --jobs <N>', 'Encrypt up to N outputs at once', '1')
+	.option('--provenance', 'Annotate rendered documents with their template, the git revision of config and template, and the sopsify version')
+	.option('--variable-snapshots', 'Also write an encrypted snapshot of the resolved values per namespace to clusters/<cluster>/snapshots/')
+	.option('--verify', 'Decrypt every output right after encrypting it and fail unless it matches the rendered plaintext')
+	.option('--check', 'Write nothing; fail listing the outputs a run would change (decrypted and compared), e.g. in CI')
+	.option('--skip-unchanged', 'Leave existing outputs untouched when their decrypted content would not change')
+	.option('--audit-log <FILE>', 'Append a JSONL audit record for every encrypted output (overrides audit_log.path)')
+	.option('--gitlab-dotenv <FILE>', 'Write non-secret run results (output paths, hashes, namespaces) as a GitLab dotenv artifact')
+	.option('--report <FILE>', 'Write a JSON run report (outputs, skipped namespaces, failures; no values), also when the run fails')
+	.action(() => run(program.opts()));
+
+program
+	.command('run')
+	.description('Render and encrypt with the options of a preset defined in .sopsify.yaml')
+	.argument('<PRESET>', 'Name of the preset')
+	.action(async preset => run(await presetOptions(preset, program)));
+
+program
+	.command('tf-source')
+	.description('Terraform external data source: reads a JSON query on stdin, prints resolved values as JSON')
+	.action(() => tfSource());
+
+program
+	.command('docker-secrets')
+	.description('Create or update Docker Swarm / Podman secrets from the resolved values of a namespace')
+	.option('--cluster <NAME>', 'Cluster from .sopsify.yaml')
+	.option('--namespace <NAME>', 'Namespace whose values are synced')
+	.option('--template <NAME>', 'Only use values of this template')
+	.option('--engine <ENGINE>', 'docker or podman', 'docker')
+	.option('--prefix <PREFIX>', 'Prefix prepended to every secret name')
+	.action(opts => dockerSecrets(opts));
+
+program
+	.command('systemd-creds')
+	.description('Write the resolved values of a namespace as systemd credentials')
+	.option('--cluster <NAME>', 'Cluster from .sopsify.yaml')
+	.option('--namespace <NAME>', 'Namespace whose values are written')
+	.option('--template <NAME>', 'Only use values of this template')
+	.option('-o, --output <FOLDER>', 'Target folder (default: credentials/<cluster>/<namespace>)')
+	.option('--no-encrypt', 'Write plain files for LoadCredential= instead of `systemd-creds encrypt`')
+	.option('--allow-tracked-plaintext', 'Allow plain credential files in paths git does not ignore')
+	.action(opts => systemdCreds(opts));
+
+program
+	.command('drift')
+	.description('Report keys whose live Secret values differ from the encrypted outputs (values redacted)')
+	.option('--namespace <NAME>', 'Namespace to check')
+	.option('--cluster <NAME>', 'Only check outputs of this cluster')
+	.option('--kube-context <CONTEXT>', 'kubectl context to use (default: current context)')
+	.option('--backend <BACKEND>', 'Backend the outputs were encrypted with', 'sops')
+	.option('--vault-password-file <FILE>', 'Password file for the ansible-vault backend')
+	.action(opts => drift(opts));
+
+program
+	.command('init')
+	.description('Scaffold a new project: a starter .sopsify.yaml, an example template and optionally a .sops.yaml, asking for what is not given')
+	.option('--cluster <NAME>', 'Cluster name used in the config (asked for in a terminal, default: default)')
+	.option('-n, --namespace <NAME>', 'Namespace of the cluster; repeatable (asked for in a terminal, default: default)', collect, [])
+	.option('--sops-config', 'Also write a .sops.yaml with a creation rule for the outputs, with placeholders for the recipients')
+	.option('--age <RECIPIENT>', 'age recipient of the .sops.yaml creation rule (implies --sops-config); repeatable', collect, [])
+	.option('--kms <ARN>', 'KMS key of the .sops.yaml creation rule (implies --sops-config); repeatable', collect, [])
+	.option('-o, --output <FILE>', 'Config file to write', '.sopsify.yaml')
+	.option('--templates-out <FOLDER>', 'Folder for the example template', 'templates')
+	.option('--force', 'Overwrite existing files')
+	.action(opts => init(opts));
+
+program
+	.command('import-sops')
+	.description('Generate a starter .sopsify.yaml and templates from existing per-namespace sops files')
+	.option('--dir <FOLDER>', 'Folder laid out as <namespace>/<file>.yaml')
+	.option('--cluster <NAME>', 'Cluster name used in the generated config', 'default')
+	.option('-o, --output <FILE>', 'Config file to write', '.sopsify.yaml')
+	.option('--templates-out <FOLDER>', 'Folder for the generated templates', 'templates')
+	.option('--force', 'Overwrite existing files')
+	.action(opts => importSops(opts));
+
+program
+	.command('migrate-helm-secrets')
+	.description('Convert a helm-secrets repository (per-environment secrets.yaml) into sopsify config and a template')
+	.option('--dir <FOLDER>', 'Root of the helm-secrets repository')
+	.option('--cluster <NAME>', 'Cluster name used in the generated config', 'default')
+	.option('--name <NAME>', 'Name of the generated Secret and template', 'helm-secrets')
+	.option('-o, --output <FILE>', 'Config file to write', '.sopsify.yaml')
+	.option('--templates-out <FOLDER>', 'Folder for the generated template', 'templates')
+	.option('--force', 'Overwrite existing files')
+	.action(opts => migrateHelmSecrets(opts));
+
+program
+	.command('scan')
+	.description('Fail if known secret values or high-entropy strings appear unencrypted in the outputs')
+	.option('--dir <FOLDER>', 'Output tree to scan', 'clusters')
+	.option('--staged', 'Also scan files staged in git')
+	.option('--entropy-threshold <BITS>', 'Minimum Shannon entropy (bits/char) to flag a string', '4.5')
+	.option('--no-entropy', 'Only look for known values, skip the entropy check')
+	.action(opts => scan(opts));
+
+program
+	.command('verify')
+	.description('Check that every output is encrypted for all recipients required by .sopsify.yaml')
+	.argument('[FOLDER]', 'Output tree to verify (default: clusters)')
+	.option('--round-trip', 'Also decrypt every output in memory and check it parses as YAML')
+	.option('--backend <BACKEND>', 'Backend the outputs were encrypted with (sops or rops)', 'sops')
+	.action((dir, opts) => verify({ ...opts, dir: dir ? fromInvocation(dir) : 'clusters' }));
+
+program
+	.command('edit')
+	.description('Edit an encrypted output with sops and report (or with --sync write back to .sopsify.yaml) the values that changed')
+	.argument('<FILE>', 'Encrypted output to edit')
+	.option('--sync', 'Write changed values back to the entries of .sopsify.yaml defining them for the namespace')
+	.option('-t, --templates <FOLDER>', 'Templates folder, to map the changed fields to keys (needed for --sync)')
+	.option('--backend <BACKEND>', 'Backend of the run, for outputs of templates without a template group backend; only sops outputs can be edited', 'sops')
+	.action((file, opts) => edit(fromInvocation(file), opts));
+
+program
+	.command('rotate')
+	.description('Re-key every encrypted output for the keys of .sops.yaml and encryption_keys (sops updatekeys), reporting which files changed')
+	.option('-o, --output <FOLDER>', 'Output tree to re-key', 'clusters')
+	.option('--backend <BACKEND>', 'Backend the outputs were encrypted with (sops or rops)', 'sops')
+	.option('--allow-tracked-plaintext', 'Allow intermediate plaintext files in paths git does not ignore')
+	.action(opts => rotateKeys(opts));
+
+program
+	.command('check')
+	.description('Report orphaned outputs and templates that produce no output (for CI)')
+	.option('--dir <FOLDER>', 'Output tree to check', 'clusters')
+	.option('-t, --templates <FOLDER>', 'Also report templates whose file is missing')
+	.option('--global', 'Expect the outputs of a run with --global (templates without placeholders once per cluster)')
+	.action(opts => check(opts));
 
-run(program.opts()).catch(err => {
+program
+	.command('doctor')
+	.description('Check sops, keys, config files, templates and output folders, with hints how to fix problems')
+	.option('-t, --templates <FOLDER>', 'Also check template discovery in this folder')
+	.action(opts => doctor(opts));
+
+program
+	.command('lint')
+	.description('Check .sopsify.yaml and the templates for malformed entries and placeholders and keys without values, without sops or writing files')
+	.option('-t, --templates <FOLDER>', 'Also lint the templates of this folder; repeatable', collect, [])
+	.option('--deny-deprecated', 'Fail when a template uses a key marked deprecated, instead of warning')
+	.action(opts => lint(opts));
+
+program
+	.command('diff')
+	.description('Show a redacted structural diff of two encrypted files, two output trees or an output tree at two git refs, or of fresh renders against the outputs in place (--render)')
+	.argument('[A]', 'File or folder (with --git: output folder, default clusters)')
+	.argument('[B]', 'File or folder to compare with')
+	.option('--git <RANGE>', 'Compare the output folder at two git refs, e.g. --git origin/main..HEAD')
+	.option('--render', 'Render the templates and print a unified diff against the decrypted outputs in place, writing nothing')
+	.option('-t, --templates <FOLDER>', 'Templates folder to render with --render; repeatable', collect, [])
+	.option('-f, --file <FILE>', 'Template file to render with --render; repeatable', collect, [])
+	.option('-n, --namespace <NAME>', 'With --render, only namespaces matching this name or glob; repeatable', collect, [])
+	.option('--split-documents', 'With --render, as for a run with --split-documents')
+	.option('--global', 'With --render, as for a run with --global')
+	.option('--matrix <FILE>', 'With --render, also read values from a CSV matrix, as for a run with --matrix')
+	.option('--allow-duplicate-keys', 'With --render, as for a run with --allow-duplicate-keys')
+	.option('--provenance', 'With --render, as for a run with --provenance')
+	.option('--show-values', 'Show values instead of hashes')
+	.option('--backend <BACKEND>', 'Backend the outputs were encrypted with', 'sops')
+	.option('--vault-password-file <FILE>', 'Password file for the ansible-vault backend')
+	.action((a, b, opts) => diff(fromInvocation(a), fromInvocation(b), opts));
+
+program
+	.command('decrypt')
+	.description('Write decrypted plaintext copies of every output to another folder, keeping the layout')
+	.option('-t, --tree <FOLDER>', 'Output tree to decrypt', 'clusters')
+	.option('-o, --output <FOLDER>', 'Folder for the plaintext copies (outside the output tree)')
+	.option('--backend <BACKEND>', 'Backend the outputs were encrypted with', 'sops')
+	.option('--vault-password-file <FILE>', 'Password file for the ansible-vault backend')
+	.option('--allow-tracked-plaintext', 'Allow plaintext copies in paths git does not ignore')
+	.action(opts => decrypt(opts));
+
+program
+	.command('docs')
+	.description('Generate a Markdown/HTML report of all managed keys (values are never included)')
+	.option('-t, --templates <FOLDER>', 'Read templates to report which ones consume each key')
+	.option('--format <FORMAT>', 'markdown or html', 'markdown')
+	.option('-o, --output <FILE>', 'Write the report to a file instead of stdout')
+	.action(opts => docs(opts));
+
+program
+	.command('list')
+	.description('List the managed keys per cluster with owner, description and namespaces (no values)')
+	.option('--cluster <NAME>', 'Only list keys of this cluster')
+	.option('--owner <OWNER>', 'Only list keys owned by this owner')
+	.action(opts => list(opts));
+
+program
+	.command('tree')
+	.description('Print clusters, namespaces and their templates as a tree with key counts per namespace and where keys come from (no values)')
+	.option('--cluster <NAME>', 'Only show this cluster')
+	.option('-n, --namespace <NAME>', 'Only show namespaces matching this name or glob; repeatable', collect, [])
+	.action(opts => tree(opts));
+
+program
+	.command('explain')
+	.description('Explain which keys a template needs for a namespace, where each value comes from and what is missing')
+	.option('--template <NAME>', 'Template file name as used in .sopsify.yaml')
+	.option('--namespace <NAME>', 'Namespace to explain')
+	.option('--cluster <NAME>', 'Only explain this cluster (default: every cluster using the template)')
+	.option('-t, --templates <FOLDER>', 'Folder containing the templates')
+	.option('--strict', 'Explain the outcome of a --strict run')
+	.action(opts => explain(opts));
+
+program
+	.command('rename-namespace')
+	.description('Rename a namespace in .sopsify.yaml, its variable files, rotation history and re-encrypted outputs')
+	.argument('<OLD>', 'Current namespace name')
+	.argument('<NEW>', 'New namespace name')
+	.option('--dry-run', 'Only show what would be changed')
+	.option('--backend <BACKEND>', 'Backend the outputs were encrypted with', 'sops')
+	.option('--vault-password-file <FILE>', 'Password file for the ansible-vault backend')
+	.option('--allow-tracked-plaintext', 'Allow intermediate plaintext files in paths git does not ignore')
+	.action((oldName, newName, opts) => renameNamespace(oldName, newName, opts));
+
+program
+	.command('rename-key')
+	.description('Rename a key in .sopsify.yaml, namespace variable files and every template placeholder')
+	.argument('<OLD>', 'Current key name')
+	.argument('<NEW>', 'New key name')
+	.option('-t, --templates <FOLDER>', 'Templates folder whose placeholders are rewritten')
+	.option('--dry-run', 'Only show the changed lines')
+	.action((oldKey, newKey, opts) => renameKey(oldKey, newKey, opts));
+
+const report = program
+	.command('report')
+	.description('Work with JSON run reports written with --report');
+
+report
+	.command('diff')
+	.description('Compare two run reports: newly failing templates, newly skipped namespaces and changes of the output set')
+	.argument('<OLD>', 'Earlier run report')
+	.argument('<NEW>', 'Later run report')
+	.option('--fail-on-regression', 'Fail if the later run has new failures or skipped template/namespace pairs')
+	.action((oldReport, newReport, opts) => diffRunReports(fromInvocation(oldReport), fromInvocation(newReport), opts));
+
+report
+	.command('merge')
+	.description('Merge the run reports of all shards of a run split with --shard into one')
+	.argument('<REPORTS...>', 'Run reports of the shards')
+	.requiredOption('-o, --output <FILE>', 'Write the merged report to this file')
+	.action((reports, opts) => mergeRunReports(reports.map(fromInvocation), opts));
+
+program
+	.command('bench')
+	.description('Benchmark the render+encrypt pipeline on synthetic Secrets with varying concurrency')
+	.option('--namespaces <N>', 'Number of synthetic namespaces', '20')
+	.option('--templates <N>', 'Number of synthetic templates per namespace', '3')
+	.option('--keys <N>', 'Number of keys per template', '10')
+	.option('--jobs <LIST>', 'Comma-separated concurrency levels to compare', '1,2,4,8')
+	.option('--cluster <NAME>', 'Cluster name used in the synthetic output paths (must match a creation rule)', 'bench')
+	.option('--backend <BACKEND>', 'Backend to benchmark', 'sops')
+	.option('--vault-password-file <FILE>', 'Password file for the ansible-vault backend')
+	.action(opts => bench(opts));
+
+program.parseAsync().catch(err => {
 	console.error(`❌ ${err.message}`);
 	process.exit(1);
 });
-
@@ -0,0 +1,227 @@
+import crypto from 'crypto';
+
+// The parts of age (https://age-encryption.org/v1) sops uses for age keys:
+// X25519 recipients (`age1...`) and identities (`AGE-SECRET-KEY-1...`), and
+// ASCII-armored files holding a small payload, sops' data key.
+const HEADER = 'age-encryption.org/v1';
+const ARMOR_BEGIN = '-----BEGIN AGE ENCRYPTED FILE-----';
+const ARMOR_END = '-----END AGE ENCRYPTED FILE-----';
+const X25519_INFO = 'age-encryption.org/v1/X25519';
+const CHUNK_SIZE = 64 * 1024;
+const X25519_SPKI = Buffer.from('302a300506032b656e032100', 'hex');
+const X25519_PKCS8 = Buffer.from('302e020100300506032b656e04220420', 'hex');
+
+export function parseRecipient(recipient) {
+	return bech32Decode(recipient.trim(), 'age');
+}
+
+// Identities of a key file or $SOPS_AGE_KEY: one per line, `#` comments
+export function parseIdentities(content) {
+	return content.split('\n')
+		.map(line => line.trim())
+		.filter(line => line !== '' && !line.startsWith('#'))
+		.map(line => {
+			const privateKey = crypto.createPrivateKey({ key: Buffer.concat([X25519_PKCS8, bech32Decode(line, 'age-secret-key-')]), format: 'der', type: 'pkcs8' });
+			return { privateKey, publicKey: rawPublicKey(crypto.createPublicKey(privateKey)) };
+		});
+}
+
+// Encrypts `plaintext` to the recipients (from parseRecipient), armored
+export function encryptArmored(plaintext, recipients) {
+	const fileKey = crypto.randomBytes(16);
+	let header = `${HEADER}\n`;
+	for (const recipient of recipients) {
+		const ephemeral = crypto.generateKeyPairSync('x25519');
+		const share = rawPublicKey(ephemeral.publicKey);
+		const wrapKey = hkdf(x25519(ephemeral.privateKey, recipient), Buffer.concat([share, recipient]), X25519_INFO);
+		header += `-> X25519 ${base64(share)}\n${base64(seal(wrapKey, Buffer.alloc(12), fileKey))}\n`;
+	}
+	header += '---';
+
+	const nonce = crypto.randomBytes(16);
+	const binary = Buffer.concat([
+		Buffer.from(`${header} ${base64(headerMac(fileKey, header))}\n`),
+		nonce,
+		streamSeal(hkdf(fileKey, nonce, 'payload'), plaintext),
+	]);
+	const lines = binary.toString('base64').match(/.{1,64}/g);
+	return `${ARMOR_BEGIN}\n${lines.join('\n')}\n${ARMOR_END}\n`;
+}
+
+// Decrypts an armored file with the first identity it was encrypted to;
+// undefined if none of them was
+export function decryptArmored(armored, identities) {
+	const text = armored.trim();
+	if (!text.startsWith(ARMOR_BEGIN) || !text.endsWith(ARMOR_END)) {
+		throw new Error('not an armored age file');
+	}
+	const binary = Buffer.from(text.slice(ARMOR_BEGIN.length, -ARMOR_END.length).replace(/\s+/g, ''), 'base64');
+
+	const macStart = binary.indexOf('\n--- ');
+	const macEnd = binary.indexOf('\n', macStart + 1);
+	if (macStart < 0 || macEnd < 0) {
+		throw new Error('age header is incomplete');
+	}
+	const header = binary.subarray(0, macStart + 4).toString();
+	const mac = Buffer.from(binary.subarray(macStart + 5, macEnd).toString(), 'base64');
+	const [version, ...lines] = header.split('\n');
+	if (version !== HEADER) {
+		throw new Error(`unsupported age version '${version}'`);
+	}
+
+	const fileKey = stanzas(lines.slice(0, -1))
+		.filter(stanza => stanza.type === 'X25519' && stanza.args.length === 1)
+		.flatMap(stanza => identities.map(identity => unwrap(stanza, identity)))
+		.find(Boolean);
+	if (!fileKey) {
+		return undefined;
+	}
+	if (!crypto.timingSafeEqual(headerMac(fileKey, header), mac.length === 32 ? mac : Buffer.alloc(32))) {
+		throw new Error('age header MAC mismatch');
+	}
+	const nonce = binary.subarray(macEnd + 1, macEnd + 17);
+	return streamOpen(hkdf(fileKey, nonce, 'payload'), binary.subarray(macEnd + 17));
+}
+
+// `-> <type> <args...>` lines, each followed by a base64 body whose last line
+// is shorter than 64 characters
+function stanzas(lines) {
+	const result = [];
+	for (let i = 0; i < lines.length; i++) {
+		if (!lines[i].startsWith('-> ')) {
+			throw new Error('malformed age header');
+		}
+		const [type, ...args] = lines[i].slice(3).split(' ');
+		let body = '';
+		while (++i < lines.length) {
+			body += lines[i];
+			if (lines[i].length < 64) {
+				break;
+			}
+		}
+		result.push({ type, args, body: Buffer.from(body, 'base64') });
+	}
+	return result;
+}
+
+function unwrap(stanza, identity) {
+	const share = Buffer.from(stanza.args[0], 'base64');
+	try {
+		const wrapKey = hkdf(x25519(identity.privateKey, share), Buffer.concat([share, identity.publicKey]), X25519_INFO);
+		return open(wrapKey, Buffer.alloc(12), stanza.body);
+	} catch {
+		return undefined; // encrypted to another identity
+	}
+}
+
+function x25519(privateKey, publicKey) {
+	const shared = crypto.diffieHellman({
+		privateKey,
+		publicKey: crypto.createPublicKey({ key: Buffer.concat([X25519_SPKI, publicKey]), format: 'der', type: 'spki' }),
+	});
+	if (shared.every(byte => byte === 0)) {
+		throw new Error('invalid X25519 share');
+	}
+	return shared;
+}
+
+function rawPublicKey(keyObject) {
+	return keyObject.export({ format: 'der', type: 'spki' }).subarray(X25519_SPKI.length);
+}
+
+function headerMac(fileKey, header) {
+	return crypto.createHmac('sha256', hkdf(fileKey, Buffer.alloc(0), 'header')).update(header).digest();
+}
+
+function hkdf(key, salt, info) {
+	return Buffer.from(crypto.hkdfSync('sha256', key, salt, info, 32));
+}
+
+// STREAM: 64 KiB chunks, nonce = 11 byte big-endian counter and a final flag
+function streamNonce(counter, last) {
+	const nonce = Buffer.alloc(12);
+	nonce.writeUInt32BE(counter, 7);
+	nonce[11] = last ? 1 : 0;
+	return nonce;
+}
+
+function streamSeal(key, plaintext) {
+	const chunks = [];
+	for (let counter = 0, offset = 0; ; counter++, offset += CHUNK_SIZE) {
+		const last = offset + CHUNK_SIZE >= plaintext.length;
+		chunks.push(seal(key, streamNonce(counter, last), plaintext.subarray(offset, offset + CHUNK_SIZE)));
+		if (last) {
+			return Buffer.concat(chunks);
+		}
+	}
+}
+
+function streamOpen(key, payload) {
+	const chunks = [];
+	for (let counter = 0, offset = 0; ; counter++, offset += CHUNK_SIZE + 16) {
+		const last = offset + CHUNK_SIZE + 16 >= payload.length;
+		chunks.push(open(key, streamNonce(counter, last), payload.subarray(offset, offset + CHUNK_SIZE + 16)));
+		if (last) {
+			return Buffer.concat(chunks);
+		}
+	}
+}
+
+function seal(key, nonce, plaintext) {
+	const cipher = crypto.createCipheriv('chacha20-poly1305', key, nonce, { authTagLength: 16 });
+	return Buffer.concat([cipher.update(plaintext), cipher.final(), cipher.getAuthTag()]);
+}
+
+function open(key, nonce, sealed) {
+	const decipher = crypto.createDecipheriv('chacha20-poly1305', key, nonce, { authTagLength: 16 });
+	decipher.setAuthTag(sealed.subarray(-16));
+	return Buffer.concat([decipher.update(sealed.subarray(0, -16)), decipher.final()]);
+}
+
+function base64(buffer) {
+	return buffer.toString('base64').replace(/=+$/, '');
+}
+
+const BECH32_CHARSET = 'qpzry9x8gf2tvdw0s3jn54khce6mua7l';
+
+function bech32Decode(string, prefix) {
+	const lower = string.toLowerCase();
+	const separator = lower.lastIndexOf('1');
+	const data = [...lower.slice(separator + 1)].map(char => BECH32_CHARSET.indexOf(char));
+	const hrp = lower.slice(0, separator);
+	if ((string !== lower && string !== string.toUpperCase()) || hrp !== prefix || data.length < 6 || data.includes(-1)
+		|| bech32Polymod([...[...hrp].map(c => c.charCodeAt(0) >> 5), 0, ...[...hrp].map(c => c.charCodeAt(0) & 31), ...data]) !== 1) {
+		throw new Error(`'${prefix === 'age' ? string : `${string.slice(0, 18)}...`}' is not a valid ${prefix === 'age' ? 'age recipient' : 'age identity'}`);
+	}
+
+	let bits = 0;
+	let value = 0;
+	const bytes = [];
+	for (const group of data.slice(0, -6)) {
+		value = ((value << 5) | group) & 0xfff;
+		bits += 5;
+		if (bits >= 8) {
+			bits -= 8;
+			bytes.push((value >> bits) & 0xff);
+		}
+	}
+	if (bytes.length !== 32) {
+		throw new Error(`${prefix === 'age' ? 'age recipient' : 'age identity'} has ${bytes.length} bytes, expected 32`);
+	}
+	return Buffer.from(bytes);
+}
+
+function bech32Polymod(values) {
+	const generators = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+	let checksum = 1;
+	for (const value of values) {
+		const top = checksum >>> 25;
+		checksum = ((checksum & 0x1ffffff) << 5) ^ value;
+		generators.forEach((generator, i) => {
+			if ((top >>> i) & 1) {
+				checksum ^= generator;
+			}
+		});
+	}
+	return checksum;
+}
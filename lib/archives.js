@@ -0,0 +1,119 @@
+import fs from 'fs/promises';
+import { gunzipSync, inflateRawSync } from 'zlib';
+import { posix } from 'path';
+
+const ARCHIVE = /\.(tar|tar\.gz|tgz|zip)$/;
+
+// `-t bundle.tar.gz`: templates are read from a tar (optionally gzipped) or
+// zip archive in memory, so a bundle published by another team is used as it
+// is and never extracted
+export function isArchive(path) {
+	return ARCHIVE.test(path);
+}
+
+// The regular files of an archive as relative path -> content, e.g.
+// `apps/db.yaml`. Hidden files and folders are skipped, as in template
+// folders; entries with absolute paths or `..` are refused.
+export async function readArchive(path) {
+	const data = await fs.readFile(path);
+	const entries = path.endsWith('.zip') ? zipEntries(data, path) : tarEntries(data[0] === 0x1f && data[1] === 0x8b ? gunzipSync(data) : data, path);
+	const files = new Map();
+	for (const [name, content] of entries) {
+		const normalized = posix.normalize(name.replace(/^\.\//, ''));
+		if (normalized.startsWith('/') || normalized.split('/').includes('..')) {
+			throw new Error(`Archive '${path}' has an entry outside of it: '${name}'`);
+		}
+		if (!normalized.split('/').some(segment => segment.startsWith('.'))) {
+			files.set(normalized, content.toString('utf-8'));
+		}
+	}
+	return files;
+}
+
+// ustar with pax (`path=`) and GNU (`././@LongLink`) long names
+function tarEntries(data, path) {
+	const entries = [];
+	let longName;
+	for (let offset = 0; offset + 512 <= data.length;) {
+		const header = data.subarray(offset, offset + 512);
+		if (header.every(byte => byte === 0)) {
+			break;
+		}
+		const size = parseInt(text(header, 124, 12).trim() || '0', 8);
+		if (Number.isNaN(size)) {
+			throw new Error(`'${path}' is not a valid tar archive`);
+		}
+		const type = String.fromCharCode(header[156] || 0x30);
+		const prefix = text(header, 345, 155);
+		const body = data.subarray(offset + 512, offset + 512 + size);
+		offset += 512 + Math.ceil(size / 512) * 512;
+
+		if (type === 'x') {
+			const record = body.toString('utf-8').split('\n').map(line => /^\d+ path=(.*)$/.exec(line)).find(Boolean);
+			longName = record ? record[1] : longName;
+		} else if (type === 'L') {
+			longName = text(body, 0, body.length);
+		} else if (type === 'g') {
+			continue;
+		} else {
+			const name = longName ?? (prefix ? `${prefix}/${text(header, 0, 100)}` : text(header, 0, 100));
+			longName = undefined;
+			if (type === '0' || type === '\0') {
+				entries.push([name, body]);
+			}
+		}
+	}
+	return entries;
+}
+
+function text(buffer, start, length) {
+	const field = buffer.subarray(start, start + length);
+	const end = field.indexOf(0);
+	return field.subarray(0, end === -1 ? field.length : end).toString('utf-8');
+}
+
+// Stored and deflated entries, read through the central directory
+function zipEntries(data, path) {
+	let end = -1;
+	for (let offset = data.length - 22; offset >= Math.max(0, data.length - 22 - 0xffff); offset--) {
+		if (data.readUInt32LE(offset) === 0x06054b50) {
+			end = offset;
+			break;
+		}
+	}
+	if (end === -1) {
+		throw new Error(`'${path}' is not a valid zip archive`);
+	}
+	const count = data.readUInt16LE(end + 10);
+	let offset = data.readUInt32LE(end + 16);
+	if (count === 0xffff || offset === 0xffffffff) {
+		throw new Error(`'${path}' is a zip64 archive, which is not supported`);
+	}
+
+	const entries = [];
+	for (let index = 0; index < count; index++) {
+		if (data.readUInt32LE(offset) !== 0x02014b50) {
+			throw new Error(`'${path}' has a damaged zip central directory`);
+		}
+		const method = data.readUInt16LE(offset + 10);
+		const compressedSize = data.readUInt32LE(offset + 20);
+		const nameLength = data.readUInt16LE(offset + 28);
+		const local = data.readUInt32LE(offset + 42);
+		const name = data.subarray(offset + 46, offset + 46 + nameLength).toString('utf-8');
+		offset += 46 + nameLength + data.readUInt16LE(offset + 30) + data.readUInt16LE(offset + 32);
+		if (name.endsWith('/')) {
+			continue;
+		}
+
+		const start = local + 30 + data.readUInt16LE(local + 26) + data.readUInt16LE(local + 28);
+		const compressed = data.subarray(start, start + compressedSize);
+		if (method === 0) {
+			entries.push([name, compressed]);
+		} else if (method === 8) {
+			entries.push([name, inflateRawSync(compressed)]);
+		} else {
+			throw new Error(`Entry '${name}' of '${path}' uses zip compression method ${method}; only stored and deflated entries are supported`);
+		}
+	}
+	return entries;
+}
@@ -0,0 +1,82 @@
+import fs from 'fs/promises';
+import os from 'os';
+import { dirname } from 'path';
+import yaml from 'js-yaml';
+import { sopsRecipients } from './verify.js';
+
+const DEFAULT_MAX_BYTES = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES = 5;
+
+// Append-only JSONL audit log with one line per encrypted output. The log is
+// rotated (`<path>.1` ... `<path>.<maxFiles>`) at the start of a run once it
+// exceeds `maxBytes`, so a single run is never split across files.
+export async function openAuditLog({ path, maxBytes = DEFAULT_MAX_BYTES, maxFiles = DEFAULT_MAX_FILES }) {
+	await fs.mkdir(dirname(path), { recursive: true, mode: 0o700 });
+	await rotate(path, maxBytes, maxFiles);
+	const identity = currentIdentity();
+	const runId = `${Date.now()}-${process.pid}`;
+
+	return {
+		async record(entry) {
+			const line = JSON.stringify({ timestamp: new Date().toISOString(), run: runId, ...identity, ...entry });
+			await fs.appendFile(path, `${line}\n`, { encoding: 'utf-8', mode: 0o600 });
+		},
+	};
+}
+
+async function rotate(path, maxBytes, maxFiles) {
+	let size;
+	try {
+		size = (await fs.stat(path)).size;
+	} catch {
+		return;
+	}
+	if (size < maxBytes) {
+		return;
+	}
+
+	await fs.rm(`${path}.${maxFiles}`, { force: true });
+	for (let i = maxFiles - 1; i >= 1; i--) {
+		await fs.rename(`${path}.${i}`, `${path}.${i + 1}`).catch(() => {});
+	}
+	await fs.rename(path, `${path}.1`);
+}
+
+function currentIdentity() {
+	const env = process.env;
+	const identity = { user: currentUser(), host: os.hostname() };
+
+	if (env.GITLAB_CI) {
+		identity.ci = { provider: 'gitlab', actor: env.GITLAB_USER_LOGIN, pipeline: env.CI_PIPELINE_ID, job: env.CI_JOB_URL, commit: env.CI_COMMIT_SHA };
+	} else if (env.GITHUB_ACTIONS) {
+		identity.ci = { provider: 'github', actor: env.GITHUB_ACTOR, run: env.GITHUB_RUN_ID, repository: env.GITHUB_REPOSITORY, commit: env.GITHUB_SHA };
+	} else if (env.CI) {
+		identity.ci = { provider: 'unknown' };
+	}
+
+	return identity;
+}
+
+function currentUser() {
+	try {
+		return os.userInfo().username;
+	} catch {
+		// No passwd entry, e.g. arbitrary UIDs in containers
+		return process.env.USER || String(process.getuid());
+	}
+}
+
+// Recipients as recorded in the sops metadata of an encrypted output
+export async function outputRecipients(path, backend) {
+	if (backend.format !== 'sops') {
+		return undefined;
+	}
+	// Multi-document outputs carry the same metadata in every document
+	const [document] = yaml.loadAll(await fs.readFile(path, 'utf-8'));
+	const recipients = sopsRecipients(document.sops || {});
+	return Object.fromEntries(
+		Object.entries(recipients)
+			.filter(([, keys]) => keys.size > 0)
+			.map(([type, keys]) => [type, [...keys]])
+	);
+}
@@ -0,0 +1,194 @@
+import fs from 'fs/promises';
+import { existsSync, readFileSync } from 'fs';
+import { execFileSync, execFile } from 'child_process';
+import { promisify } from 'util';
+import yaml from 'js-yaml';
+import { builtinBackend } from './sops-builtin.js';
+import { configFile } from './config.js';
+import { sopsKeyArgs } from './encryption-keys.js';
+
+const execFileAsync = promisify(execFile);
+
+// Oldest sops release sopsify is tested with
+const MIN_SOPS_VERSION = [3, 7, 0];
+// Arguments sopsify passes itself; the mode, the files, their types (those of
+// the template engine) and the `.sops.yaml` sopsify reads are not up to `sops_args`
+const RESERVED_SOPS_ARGS = [
+	'-e', '--encrypt', '-d', '--decrypt', '-i', '--in-place', '--output', '--filename-override',
+	'--input-type', '--output-type', '--config',
+];
+let sopsBin;
+let cliSopsArgs = [];
+let configSopsArgs;
+
+// `--sops-bin <PATH>`, else $SOPSIFY_SOPS_BIN, else `sops` from PATH
+export function useSopsBinary(path) {
+	sopsBin = path;
+}
+
+export function sopsBinary() {
+	return sopsBin || process.env.SOPSIFY_SOPS_BIN || 'sops';
+}
+
+// Every `--sops-arg`
+export function useSopsArgs(args = []) {
+	cliSopsArgs = args;
+}
+
+// `sops_args` of .sopsify.yaml, then every `--sops-arg`: extra arguments for
+// every sops call, e.g. `--aws-profile prod`. The config is read once, on the
+// first call.
+export function sopsArgs() {
+	if (configSopsArgs === undefined) {
+		const config = existsSync(configFile()) ? yaml.load(readFileSync(configFile(), 'utf-8')) || {} : {};
+		const args = config.sops_args ?? [];
+		if (!Array.isArray(args) || !args.every(arg => ['string', 'number'].includes(typeof arg))) {
+			throw new Error(`'sops_args' in ${configFile()} must be a list of arguments, e.g. ['--aws-profile', 'prod']`);
+		}
+		configSopsArgs = args.map(String);
+	}
+	const args = [...configSopsArgs, ...cliSopsArgs];
+	const reserved = args.filter(arg => RESERVED_SOPS_ARGS.includes(arg.split('=')[0]));
+	if (reserved.length > 0) {
+		throw new Error(`sops arguments ${reserved.join(', ')} are set by sopsify and cannot be passed with sops_args / --sops-arg`);
+	}
+	return args;
+}
+
+// The version line of `sops --version` and the parsed [major, minor, patch];
+// fails for a missing binary and releases older than MIN_SOPS_VERSION
+export function sopsVersion(bin = sopsBinary()) {
+	let output;
+	try {
+		output = execFileSync(bin, ['--version'], { stdio: 'pipe', encoding: 'utf-8' });
+	} catch (err) {
+		if (err.code === 'ENOENT' || err.code === 'EACCES') {
+			throw new Error(bin === 'sops'
+				? 'sops is not installed or not in PATH; install it or pass --sops-bin <PATH>'
+				: `sops binary '${bin}' does not exist or is not executable (--sops-bin / SOPSIFY_SOPS_BIN)`);
+		}
+		throw new Error(`\`${bin} --version\` failed: ${(err.stderr || '').toString().trim() || err.message}`);
+	}
+
+	const line = output.split('\n')[0].trim();
+	const match = line.match(/(\d+)\.(\d+)\.(\d+)/);
+	if (!match) {
+		throw new Error(`Could not read the sops version from \`${bin} --version\`: ${line}`);
+	}
+	const version = match.slice(1).map(Number);
+	const older = version.findIndex((part, i) => part !== MIN_SOPS_VERSION[i]);
+	if (older >= 0 && version[older] < MIN_SOPS_VERSION[older]) {
+		throw new Error(`${line} is not supported, sopsify needs sops ${MIN_SOPS_VERSION.join('.')} or newer`);
+	}
+	return { line, version };
+}
+
+// An encryption backend encrypts a rendered plaintext file in place (async, so
+// several files can be encrypted concurrently) and can decrypt an output (by
+// path or content) back to a plaintext string, which is never written to disk.
+// `suffix` replaces the template's `.yaml`/`.yml` extension in the output name
+// (see outputFileName of engines.js for other template engines); `engines`,
+// when set, lists the only template engines a backend encrypts.
+// `encrypt` gets the final output path too: the plaintext is written next to
+// it under a temporary name, which creation rules keyed on the output layout
+// would not match. The optional `env` holds extra environment variables for
+// the invocation (`sops_env`). For sops formats, `encrypt` may also get
+// `settings` overriding the creation rules: `keys` to encrypt for
+// (`encryption_keys`) and `encryptedRegex` (`encrypted_regex`), and the
+// sops `type` of the file (`yaml`, `dotenv`, `binary`). `decryptContent`
+// takes that type too.
+// `format: 'sops'` marks outputs carrying sops metadata, whose recipients are
+// checked.
+const backends = {
+	sops: opts => ({
+		name: 'sops',
+		format: 'sops',
+		suffix: '.enc.yaml',
+		requiredFiles: ['.sops.yaml'],
+		filenameOverride: false,
+		bin: opts.sopsBin || sopsBinary(),
+		check() {
+			const { line, version: [major, minor] } = sopsVersion(this.bin);
+			sopsArgs();
+
+			// `--filename-override` exists since sops 3.8.0
+			this.filenameOverride = major > 3 || (major === 3 && minor >= 8);
+			if (!this.filenameOverride) {
+				console.warn(`   ⚠️ ${line} does not support --filename-override; creation rules are matched against the plaintext path`);
+			}
+		},
+		async encrypt(path, outputPath, env, { keys, encryptedRegex, type } = {}) {
+			const override = this.filenameOverride && outputPath ? ['--filename-override', outputPath] : [];
+			const rules = [...(keys ? sopsKeyArgs(keys) : []), ...(encryptedRegex !== undefined ? ['--encrypted-regex', encryptedRegex] : [])];
+			// The temporary plaintext always has a .yaml name
+			const types = type && type !== 'yaml' ? ['--input-type', type, '--output-type', type] : [];
+			await execFileAsync(this.bin, [...sopsArgs(), ...rules, ...types, '-e', '-i', ...override, path], { env: withEnv(env) });
+		},
+		decrypt(path, env) {
+			return execFileSync(this.bin, [...sopsArgs(), '-d', path], { stdio: 'pipe', encoding: 'utf-8', env: withEnv(env) });
+		},
+		decryptContent(content, env, type = 'yaml') {
+			return execFileSync(this.bin, [...sopsArgs(), '-d', '--input-type', type, '--output-type', type, '/dev/stdin'], { input: content, stdio: 'pipe', encoding: 'utf-8', env: withEnv(env) });
+		},
+		// Re-encrypts the data key of a file in place for the keys of the
+		// creation rule matching its path (`sops updatekeys`)
+		updateKeys(path, env) {
+			execFileSync(this.bin, ['updatekeys', '-y', ...sopsArgs(), path], { stdio: 'pipe', env: withEnv(env) });
+		},
+	}),
+
+	'ansible-vault': opts => ({
+		name: 'ansible-vault',
+		format: 'ansible-vault',
+		suffix: '.vault.yaml',
+		requiredFiles: [],
+		async check() {
+			if (!opts.vaultPasswordFile) {
+				throw new Error('--backend ansible-vault requires --vault-password-file');
+			}
+			await fs.access(opts.vaultPasswordFile);
+			try {
+				execFileSync('ansible-vault', ['--version'], { stdio: 'pipe' });
+			} catch {
+				throw new Error('ansible-vault is not installed or not in PATH');
+			}
+		},
+		async encrypt(path, outputPath, env) {
+			const args = ['encrypt', '--vault-password-file', opts.vaultPasswordFile];
+			if (opts.vaultId) {
+				args.push('--encrypt-vault-id', opts.vaultId);
+			}
+			await execFileAsync('ansible-vault', [...args, path], { env: withEnv(env) });
+		},
+		decrypt(path, env) {
+			return execFileSync('ansible-vault', ['view', '--vault-password-file', opts.vaultPasswordFile, path], { stdio: 'pipe', encoding: 'utf-8', env: withEnv(env) });
+		},
+		decryptContent(content, env) {
+			return execFileSync('ansible-vault', ['decrypt', '--vault-password-file', opts.vaultPasswordFile, '--output', '-', '-'], { input: content, stdio: 'pipe', encoding: 'utf-8', env: withEnv(env) });
+		},
+	}),
+
+	// sops files for age recipients, written without the sops binary
+	rops: builtinBackend,
+	// Former name of `rops`
+	builtin: builtinBackend,
+};
+
+// Without extra variables the child inherits the environment as it is
+function withEnv(env) {
+	return env ? { ...process.env, ...env } : undefined;
+}
+
+// `opts.encryptor` is an object implementing the interface above, used in
+// place of a built-in backend when sopsify is embedded as a library
+export function getBackend(opts) {
+	if (opts.encryptor) {
+		return opts.encryptor;
+	}
+	const name = opts.backend || 'sops';
+	const factory = backends[name];
+	if (!factory) {
+		throw new Error(`Unknown backend '${name}', expected one of: ${Object.keys(backends).join(', ')}`);
+	}
+	return factory(opts);
+}
@@ -0,0 +1,93 @@
+import fs from 'fs/promises';
+import os from 'os';
+import yaml from 'js-yaml';
+import { join, resolve } from 'path';
+import { randomBytes } from 'crypto';
+import { performance } from 'perf_hooks';
+import { getBackend } from './backends.js';
+import { validatePathSegment } from './sopsify.js';
+import { runPool, positiveInt } from './pool.js';
+import { temporaryPlaintextPath } from './staging.js';
+
+// Runs the render+encrypt pipeline on synthetic Secrets in a scratch folder,
+// once per `--jobs` value. Only the backend's config (`.sops.yaml`) is copied,
+// so the real keys and creation rules are used but no real values are touched.
+export async function bench(opts) {
+	const namespaces = positiveInt(opts.namespaces, '--namespaces');
+	const templates = positiveInt(opts.templates, '--templates');
+	const keys = positiveInt(opts.keys, '--keys');
+	const jobsList = String(opts.jobs).split(',').map(j => positiveInt(j.trim(), '--jobs'));
+	validatePathSegment(opts.cluster, 'Cluster');
+
+	const backend = getBackend({
+		...opts,
+		vaultPasswordFile: opts.vaultPasswordFile && resolve(opts.vaultPasswordFile),
+	});
+	await backend.check();
+
+	const items = [];
+	for (let n = 1; n <= namespaces; n++) {
+		for (let t = 1; t <= templates; t++) {
+			items.push({ namespace: `bench-ns-${String(n).padStart(3, '0')}`, template: `bench-${t}.yaml` });
+		}
+	}
+
+	const root = await fs.mkdtemp(join(os.tmpdir(), 'sopsify-bench-'));
+	const cwd = process.cwd();
+	for (const file of backend.requiredFiles) {
+		await fs.copyFile(file, join(root, file));
+	}
+
+	console.log(`⏱️ Benchmarking ${items.length} outputs (${namespaces} namespaces × ${templates} templates, ${keys} keys each) with ${backend.name}\n`);
+
+	const results = [];
+	try {
+		process.chdir(root);
+		for (const jobs of jobsList) {
+			await fs.rm('clusters', { recursive: true, force: true });
+			const start = performance.now();
+			await runPool(items, jobs, item => renderAndEncrypt(item, opts.cluster, keys, backend));
+			const seconds = (performance.now() - start) / 1000;
+			results.push({ jobs, seconds });
+
+			const speedup = results.length > 1 ? `  (${(results[0].seconds / seconds).toFixed(1)}x)` : '';
+			console.log(`   jobs ${String(jobs).padStart(2)}: ${seconds.toFixed(2).padStart(7)}s  ${(items.length / seconds).toFixed(1).padStart(6)} outputs/s${speedup}`);
+		}
+	} finally {
+		process.chdir(cwd);
+		await fs.rm(root, { recursive: true, force: true });
+	}
+
+	const best = results.reduce((a, b) => (b.seconds < a.seconds ? b : a));
+	console.log(`\n✅ Fastest: --jobs ${best.jobs}`);
+}
+
+async function renderAndEncrypt({ namespace, template }, cluster, keys, backend) {
+	const stringData = {};
+	for (let k = 1; k <= keys; k++) {
+		stringData[`key-${k}`] = randomBytes(24).toString('base64');
+	}
+	const manifest = yaml.dump({
+		apiVersion: 'v1',
+		kind: 'Secret',
+		metadata: { name: template.replace(/\.yaml$/, ''), namespace },
+		stringData,
+	});
+
+	const targetDir = join('clusters', cluster, 'secrets', namespace);
+	await fs.mkdir(targetDir, { recursive: true });
+	const plaintextPath = temporaryPlaintextPath(targetDir, template);
+	const outputPath = join(targetDir, template.replace(/\.yaml$/, backend.suffix));
+	await fs.writeFile(plaintextPath, manifest, 'utf-8');
+
+	try {
+		await backend.encrypt(plaintextPath, outputPath);
+	} catch (err) {
+		await fs.rm(plaintextPath, { force: true });
+		throw new Error(
+			`Encrypting '${outputPath}' failed: ${(err.stderr || err.message).toString().trim()}. ` +
+			`Use --cluster to pick a cluster name the creation rules in .sops.yaml match`
+		);
+	}
+	await fs.rename(plaintextPath, outputPath);
+}
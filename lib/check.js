@@ -0,0 +1,176 @@
+import fs from 'fs/promises';
+import { join } from 'path';
+import { loadConfig, loadTemplates, findTemplate, templateScope } from './sopsify.js';
+import { outputDirs, overriddenOutputDirs } from './output-roots.js';
+import { SNAPSHOT_FILE } from './snapshots.js';
+import { filesBelow } from './files.js';
+import { groupSettings } from './template-groups.js';
+import { engineTemplateName } from './engines.js';
+
+const OUTPUT_EXTENSION = /(\.enc\.ya?ml|\.vault\.ya?ml|\.ya?ml)$/;
+
+// Compares the output tree with .sopsify.yaml and reports two categories:
+// orphaned outputs no template/namespace produces any more (candidates for
+// pruning) and templates that produce no output at all. Nothing is decrypted.
+export async function check(opts) {
+	const config = await loadConfig();
+	const templates = opts.templates ? await loadTemplates(opts.templates, [], { config }) : undefined;
+	const expected = expectedOutputs(config, templates, opts);
+
+	const files = await listOutputFiles(opts.dir, config, expected);
+	const produced = new Set();
+	const orphans = [];
+	// Variable snapshots belong to any namespace that still has outputs
+	const namespaces = new Set([...expected.keys()].map(id => id.split('/').slice(0, 2).join('/')));
+	const snapshotStem = SNAPSHOT_FILE.replace(OUTPUT_EXTENSION, '');
+	for (const file of files) {
+		// Parts of a split output (`<template>.part-<i>`) belong to the template;
+		// outputs of other template engines keep the template's extension
+		const stem = engineTemplateName(file.name) ?? file.name.replace(OUTPUT_EXTENSION, '').replace(/\.part-\d+$/, '');
+		const id = `${file.cluster}/${file.namespace}/${stem}`;
+		// Outputs only count in the folder the template writes them to: its
+		// group's `output_root`, else the namespace's (snapshots), else the default layout
+		const def = expected.get(id);
+		if (def && file.kind !== 'snapshots' && file.root === outputRoot(config, file.cluster, file.namespace, def.template)) {
+			produced.add(def);
+		} else if (stem !== snapshotStem || ['secrets', 'manifests'].includes(file.kind) || file.root !== outputRoot(config, file.cluster, file.namespace)
+			|| !namespaces.has(`${file.cluster}/${file.namespace}`)) {
+			orphans.push(file.path);
+		}
+	}
+
+	const skipped = [];
+	for (const templateDef of new Set(expected.values())) {
+		if (templates && !findTemplate(templates, templateDef.template)) {
+			skipped.push(`${templateDef.cluster}/${templateDef.template} (template file not found)`);
+		} else if (!produced.has(templateDef)) {
+			skipped.push(`${templateDef.cluster}/${templateDef.template} (no output in any namespace)`);
+		}
+	}
+	for (const { cluster, template } of emptyTemplates(config)) {
+		skipped.push(`${cluster}/${template} (no namespaces defined)`);
+	}
+
+	report('🗑️ Orphaned outputs (no template/namespace produces them)', orphans);
+	report('⏭️ Templates without outputs', skipped);
+
+	if (orphans.length > 0 || skipped.length > 0) {
+		throw new Error(`Check failed: ${orphans.length} orphaned outputs, ${skipped.length} templates without outputs`);
+	}
+	console.log(`✅ ${files.length} outputs match .sopsify.yaml`);
+}
+
+// cluster/namespace/template stem -> template definition; global templates
+// have an empty namespace
+function expectedOutputs(config, templates, opts) {
+	const expected = new Map();
+	for (const clusterItem of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		for (const templateDef of templateDefs) {
+			const def = { cluster, template: templateDef.template };
+			const stem = templateDef.template.replace(/\.ya?ml$/, '');
+			const scopes = templateScopes(templateDef, templates, cluster, opts);
+			if (scopes.includes('global')) {
+				expected.set(`${cluster}//${stem}`, def);
+			}
+			if (!scopes.includes('namespace')) {
+				continue;
+			}
+			(templateDef.namespaces || []).forEach(ns => expected.set(`${cluster}/${ns}/${stem}`, def));
+			for (const entry of templateDef.values ?? []) {
+				(entry.namespaces || []).forEach(ns => expected.set(`${cluster}/${ns}/${stem}`, def));
+			}
+		}
+	}
+	return expected;
+}
+
+// The scopes a run writes a template in. Whether `--global` writes a template
+// without a `scope` globally depends on its placeholders, so without the
+// template file (no -t) outputs of both scopes are expected.
+function templateScopes(templateDef, templates, cluster, opts) {
+	if (templateDef.scope !== undefined || !opts.global) {
+		return [templateDef.scope === 'global' ? 'global' : 'namespace'];
+	}
+	const path = templates && findTemplate(templates, templateDef.template);
+	return path ? [templateScope(templateDef, templates[path], cluster, opts)] : ['global', 'namespace'];
+}
+
+function emptyTemplates(config) {
+	const empty = [];
+	for (const clusterItem of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		for (const templateDef of templateDefs) {
+			const namespaces = [...(templateDef.namespaces || []), ...(templateDef.values ?? []).flatMap(entry => entry.namespaces || [])];
+			if (templateDef.scope !== 'global' && namespaces.length === 0) {
+				empty.push({ cluster, template: templateDef.template });
+			}
+		}
+	}
+	return empty;
+}
+
+// Outputs are laid out as <dir>/<cluster>/{secrets,manifests,snapshots}/<namespace>/<file>
+// and <dir>/<cluster>/global/<file> for global templates, plus the folders of
+// namespaces and template groups with an output root of their own (`root`;
+// outputs left in the default layout then count as orphans); hidden files
+// (staged outputs of a running or aborted run) are ignored
+async function listOutputFiles(dir, config, expected) {
+	const files = [];
+	for (const cluster of await readDirs(dir)) {
+		for (const kind of ['secrets', 'manifests', 'snapshots']) {
+			const kindDir = join(dir, cluster, kind);
+			for (const namespace of await readDirs(kindDir)) {
+				files.push(...(await outputFiles(join(kindDir, namespace), cluster, namespace)).map(file => ({ ...file, kind })));
+			}
+		}
+		files.push(...(await outputFiles(join(dir, cluster, 'global'), cluster, '')).map(file => ({ ...file, kind: 'global' })));
+	}
+
+	// A folder shared by a group and its namespace is listed once
+	const roots = new Map(overriddenOutputDirs(config).map(root => [root.dir, root]));
+	for (const [id, def] of expected) {
+		const [cluster, namespace] = id.split('/');
+		const root = outputRoot(config, cluster, namespace, def.template);
+		if (root !== undefined && !roots.has(root)) {
+			roots.set(root, { cluster, namespace, dir: root });
+		}
+	}
+	for (const { cluster, namespace, dir: nsDir } of roots.values()) {
+		files.push(...(await outputFiles(nsDir, cluster, namespace)).map(file => ({ ...file, root: nsDir })));
+	}
+	return files;
+}
+
+// The output root of a namespace (of the template's group, when given);
+// undefined for the default layout
+function outputRoot(config, cluster, namespace, template) {
+	if (!namespace) {
+		return undefined;
+	}
+	const groupRoot = template === undefined ? undefined : groupSettings(config, template).output_root;
+	const { secrets, overridden } = outputDirs(config, cluster, namespace, groupRoot);
+	return overridden ? secrets : undefined;
+}
+
+async function outputFiles(nsDir, cluster, namespace) {
+	const names = await filesBelow(nsDir, name => OUTPUT_EXTENSION.test(name) || engineTemplateName(name) !== undefined);
+	return names.map(name => ({ cluster, namespace, name, path: join(nsDir, name) }));
+}
+
+async function readDirs(dir) {
+	try {
+		const entries = await fs.readdir(dir, { withFileTypes: true });
+		return entries.filter(e => e.isDirectory() && !e.name.startsWith('.')).map(e => e.name);
+	} catch {
+		return [];
+	}
+}
+
+function report(title, items) {
+	if (items.length === 0) {
+		return;
+	}
+	console.log(`${title}: ${items.length}`);
+	items.forEach(item => console.log(`   - ${item}`));
+}
@@ -0,0 +1,131 @@
+import yaml from 'js-yaml';
+import { positiveInt } from './pool.js';
+import { configFile } from './config.js';
+
+// Kubernetes rejects Secrets and ConfigMaps larger than 1 MiB
+export const KUBERNETES_OBJECT_LIMIT = 1024 * 1024;
+const SECTIONS = ['data', 'stringData', 'binaryData'];
+
+// `max_secret_bytes` of the template definition, else the top-level one
+export function maxSecretBytes(config, templateDef) {
+	const value = templateDef.max_secret_bytes ?? config.max_secret_bytes;
+	if (value === undefined) {
+		return undefined;
+	}
+	const where = templateDef.max_secret_bytes !== undefined ? `'max_secret_bytes' of template '${templateDef.template}'` : `'max_secret_bytes' in ${configFile()}`;
+	return positiveInt(value, where);
+}
+
+export function documentBytes(document) {
+	return Buffer.byteLength(yaml.dump(document));
+}
+
+// Splits the rendered documents of one output into files of at most
+// `maxBytes` (of plaintext YAML): documents larger than that are split into
+// several Secrets/ConfigMaps `<name>-part-<i>` carrying some of the keys each,
+// annotated with `sopsify.io/part: <i>/<n>` and `sopsify.io/split-from:
+// <name>`. Returns the documents of every file; one file when nothing had to
+// be split.
+export function splitDocuments(documents, maxBytes, where) {
+	const total = Buffer.byteLength(documents.map(doc => yaml.dump(doc)).join('---\n'));
+	if (maxBytes === undefined || total <= maxBytes) {
+		return [documents];
+	}
+
+	const files = [];
+	let current = [];
+	let size = 0;
+	for (const document of documents.flatMap(doc => splitDocument(doc, maxBytes, where))) {
+		// Documents are joined with '---\n'
+		const bytes = documentBytes(document);
+		if (current.length > 0 && size + 4 + bytes > maxBytes) {
+			files.push(current);
+			current = [];
+			size = 0;
+		}
+		size += (current.length > 0 ? 4 : 0) + bytes;
+		current.push(document);
+	}
+	files.push(current);
+	return files;
+}
+
+function splitDocument(document, maxBytes, where) {
+	if (documentBytes(document) <= maxBytes) {
+		return [document];
+	}
+	const name = (document.metadata || {}).name;
+	if (!SECTIONS.some(section => document[section]) || !name) {
+		throw new Error(`A ${document.kind || 'document'} of ${where} is larger than max_secret_bytes (${maxBytes}) and has no named data to split`);
+	}
+
+	const base = Object.fromEntries(Object.entries(document).filter(([field]) => !SECTIONS.includes(field)));
+	const entries = SECTIONS.flatMap(section => Object.entries(document[section] || {}).map(([key, value]) => [section, key, value]));
+	// Keys are added to the last part while it stays small enough; the size is
+	// measured with the annotations of a part
+	const fits = candidate => documentBytes(annotate(candidate, name, 99, 99)) <= maxBytes;
+	const parts = [];
+	for (const [section, key, value] of entries) {
+		const candidate = parts.length > 0 && withEntry(parts[parts.length - 1], section, key, value);
+		if (candidate && fits(candidate)) {
+			parts[parts.length - 1] = candidate;
+			continue;
+		}
+		const part = withEntry(JSON.parse(JSON.stringify(base)), section, key, value);
+		if (!fits(part)) {
+			throw new Error(`Key '${key}' of ${document.kind} '${name}' in ${where} alone is larger than max_secret_bytes (${maxBytes})`);
+		}
+		parts.push(part);
+	}
+	return parts.map((candidate, index) => annotate(candidate, name, index + 1, parts.length));
+}
+
+// The name of the document a part was split from, undefined for other documents
+export function splitFrom(document) {
+	const annotations = (document.metadata && document.metadata.annotations) || {};
+	return annotations['sopsify.io/split-from'];
+}
+
+// Joins the parts of split documents back into the documents they were split
+// from, at the place of their first part, so outputs compare equal however
+// their keys are spread over the parts
+export function joinParts(documents) {
+	const joined = [];
+	const wholes = new Map();
+	for (const document of documents) {
+		const name = splitFrom(document);
+		if (name === undefined) {
+			joined.push(document);
+			continue;
+		}
+		if (!wholes.has(name)) {
+			const { 'sopsify.io/part': part, 'sopsify.io/split-from': from, ...annotations } = document.metadata.annotations;
+			const { annotations: partAnnotations, ...metadata } = document.metadata;
+			const whole = Object.fromEntries(Object.entries(document).filter(([field]) => !SECTIONS.includes(field)));
+			whole.metadata = { ...metadata, name, ...(Object.keys(annotations).length > 0 ? { annotations } : {}) };
+			wholes.set(name, whole);
+			joined.push(whole);
+		}
+		const whole = wholes.get(name);
+		for (const section of SECTIONS.filter(candidate => document[candidate])) {
+			whole[section] = { ...(whole[section] || {}), ...document[section] };
+		}
+	}
+	return joined;
+}
+
+function withEntry(document, section, key, value) {
+	return { ...document, [section]: { ...(document[section] || {}), [key]: value } };
+}
+
+function annotate(document, name, index, count) {
+	const metadata = document.metadata || {};
+	return {
+		...document,
+		metadata: {
+			...metadata,
+			name: `${name}-part-${index}`,
+			annotations: { ...(metadata.annotations || {}), 'sopsify.io/part': `${index}/${count}`, 'sopsify.io/split-from': name },
+		},
+	};
+}
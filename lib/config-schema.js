@@ -0,0 +1,325 @@
+import yaml from 'js-yaml';
+import { editDistance } from './sopsify.js';
+
+const SETTINGS = [
+	'sopsify', 'strict', 'on_unresolved', 'on_all_skipped', 'namespace_names', 'known_namespaces', 'recipients',
+	'rotation', 'sops_env', 'sops_args', 'output_roots', 'outputs', 'encryption_keys', 'encrypted_regex',
+	'max_secret_bytes', 'permissions', 'audit_log', 'template_groups', 'source_limits', 'hooks', 'key_aliases', 'presets', 'groups',
+	'defaults', 'environments',
+];
+const TEMPLATE_FIELDS = ['template', 'values', 'namespaces', 'scope', 'on_unresolved', 'encrypt', 'encrypted_regex', 'max_secret_bytes'];
+const ENTRY_FIELDS = ['key', 'value', 'value_from', 'namespaces', 'match', 'secret', 'type', 'transform', 'description', 'owner', 'deprecated', 'replaced_by'];
+const DEFAULT_FIELDS = ['value', 'value_from', 'secret', 'type', 'transform', 'description', 'owner', 'deprecated', 'replaced_by'];
+
+// Parses the text of a config file and checks it: every problem, in the
+// order of the file, with where it is (`<path>:<line>:<column>`) and the
+// field it is about
+export function checkConfig(text, path) {
+	const config = yaml.load(text);
+	const problems = configProblems(config)
+		.map(({ path: fieldPath, message }) => ({ ...locate(text, fieldPath), fieldPath, message }))
+		.sort((a, b) => a.line - b.line || a.column - b.column)
+		.map(({ line, column, fieldPath, message }) => ({
+			where: `${path}:${line}:${column}`,
+			message: fieldPath.length > 0 ? `${describePath(fieldPath)}: ${message}` : message,
+		}));
+	return { config, problems };
+}
+
+// Checks the structure of .sopsify.yaml: unknown fields, the shape of the
+// cluster list, template definitions, value entries and defaults, empty
+// `namespaces` lists and empty or non-string values. Returns every problem as
+// { path, message }, `path` being the keys and list indices leading to it.
+// The settings are checked in depth where they are used.
+function configProblems(config) {
+	const problems = [];
+	const problem = (path, message) => problems.push({ path, message });
+	if (!isMap(config)) {
+		problem([], 'expected a map');
+		return problems;
+	}
+	unknownFields(config, SETTINGS, [], problem);
+	if (config.groups !== undefined) {
+		groupsProblems(config.groups, problem);
+	}
+	if (config.defaults !== undefined) {
+		defaultsProblems(config.defaults, problem);
+	}
+	if (config.environments !== undefined) {
+		environmentsProblems(config.environments, problem);
+	}
+	if (!Array.isArray(config.sopsify)) {
+		problem(config.sopsify === undefined ? [] : ['sopsify'], "'sopsify' must be a list of clusters, each a map of one cluster name to its templates");
+		return problems;
+	}
+
+	config.sopsify.forEach((clusterItem, index) => {
+		if (!isMap(clusterItem) || Object.keys(clusterItem).length !== 1) {
+			problem(['sopsify', index], 'must be a map of one cluster name to its templates');
+			return;
+		}
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		const clusterPath = ['sopsify', index, cluster];
+		if (!Array.isArray(templateDefs)) {
+			problem(clusterPath, 'must be a list of template definitions');
+			return;
+		}
+		templateDefs.forEach((templateDef, defIndex) => templateProblems(templateDef, [...clusterPath, defIndex], problem));
+	});
+	return problems;
+}
+
+function templateProblems(templateDef, path, problem) {
+	if (!isMap(templateDef)) {
+		problem(path, "must be a template definition with a 'template'");
+		return;
+	}
+	unknownFields(templateDef, TEMPLATE_FIELDS, path, problem);
+	if (typeof templateDef.template !== 'string' || templateDef.template === '') {
+		problem(templateDef.template === undefined ? path : [...path, 'template'], "needs 'template', the name of its template file");
+	}
+	if (templateDef.namespaces !== undefined) {
+		namespacesProblems(templateDef.namespaces, [...path, 'namespaces'], problem);
+	}
+	if (templateDef.values === undefined) {
+		return;
+	}
+	if (!Array.isArray(templateDef.values)) {
+		problem([...path, 'values'], 'must be a list of key entries');
+		return;
+	}
+	templateDef.values.forEach((entry, index) => entryProblems(entry, [...path, 'values', index], problem));
+}
+
+function entryProblems(entry, path, problem) {
+	if (!isMap(entry)) {
+		problem(path, "must be a key entry with 'key' and 'value'");
+		return;
+	}
+	unknownFields(entry, ENTRY_FIELDS, path, problem);
+	if (typeof entry.key !== 'string' || entry.key === '') {
+		problem(entry.key === undefined ? path : [...path, 'key'], "needs 'key', the name of the placeholder");
+	}
+	fieldProblems(entry, path, problem);
+	if (entry.namespaces !== undefined) {
+		namespacesProblems(entry.namespaces, [...path, 'namespaces'], problem);
+	}
+}
+
+// The fields value entries and defaults share
+function fieldProblems(entry, path, problem) {
+	if ('value' in entry) {
+		valueProblems(entry.value, [...path, 'value'], 'value', problem);
+	}
+	for (const field of ['description', 'owner']) {
+		if (entry[field] !== undefined && typeof entry[field] !== 'string') {
+			problem([...path, field], 'must be a string');
+		}
+	}
+	if (entry.secret !== undefined && typeof entry.secret !== 'boolean') {
+		problem([...path, 'secret'], 'must be true or false');
+	}
+	if (entry.deprecated !== undefined && typeof entry.deprecated !== 'boolean') {
+		problem([...path, 'deprecated'], 'must be true or false');
+	}
+	if (entry.replaced_by !== undefined && (typeof entry.replaced_by !== 'string' || entry.replaced_by === '')) {
+		problem([...path, 'replaced_by'], 'must be the name of the key replacing it');
+	} else if (entry.replaced_by !== undefined && entry.deprecated !== true) {
+		problem([...path, 'replaced_by'], "only goes with 'deprecated: true'");
+	}
+}
+
+function valueProblems(value, path, field, problem) {
+	if (value === null || value === '') {
+		problem(path, 'is empty');
+	} else if (typeof value === 'number' || typeof value === 'boolean') {
+		problem(path, `must be a string; quote it: ${field}: "${value}"`);
+	} else if (typeof value !== 'string') {
+		problem(path, `must be a string, not a ${Array.isArray(value) ? 'list' : 'map'}`);
+	}
+}
+
+function namespacesProblems(namespaces, path, problem) {
+	if (!Array.isArray(namespaces)) {
+		problem(path, 'must be a list of namespace names');
+	} else if (namespaces.length === 0) {
+		problem(path, 'is empty; list at least one namespace or remove it');
+	} else {
+		namespaces.forEach((ns, index) => {
+			if (typeof ns !== 'string' || ns === '') {
+				problem([...path, index], `must be a namespace name, not ${ns === null || ns === '' ? 'empty' : JSON.stringify(ns)}`);
+			}
+		});
+	}
+}
+
+function groupsProblems(groups, problem) {
+	if (!isMap(groups)) {
+		problem(['groups'], 'must be a map of group names to lists of namespaces');
+		return;
+	}
+	for (const [name, namespaces] of Object.entries(groups)) {
+		if (!/^[a-zA-Z0-9][a-zA-Z0-9_.-]*$/.test(name)) {
+			problem(['groups', name], `'${name}' is not a valid group name (letters, digits, '_', '.' and '-')`);
+		}
+		namespacesProblems(namespaces, ['groups', name], problem);
+	}
+}
+
+function defaultsProblems(defaults, problem) {
+	if (!isMap(defaults)) {
+		problem(['defaults'], 'must be a map of keys to their value for every namespace');
+		return;
+	}
+	for (const [key, spec] of Object.entries(defaults)) {
+		if (!isMap(spec)) {
+			valueProblems(spec, ['defaults', key], key, problem);
+			continue;
+		}
+		unknownFields(spec, DEFAULT_FIELDS, ['defaults', key], problem);
+		fieldProblems(spec, ['defaults', key], problem);
+	}
+}
+
+function environmentsProblems(environments, problem) {
+	if (!isMap(environments)) {
+		problem(['environments'], 'must be a map of environment names to lists of clusters');
+		return;
+	}
+	const seen = new Map();
+	for (const [name, clusters] of Object.entries(environments)) {
+		if (!/^[a-zA-Z0-9][a-zA-Z0-9_.-]*$/.test(name)) {
+			problem(['environments', name], `'${name}' is not a valid environment name (letters, digits, '_', '.' and '-')`);
+		}
+		if (!Array.isArray(clusters) || clusters.length === 0 || !clusters.every(cluster => typeof cluster === 'string' && cluster !== '')) {
+			problem(['environments', name], 'must be a list of cluster names');
+			continue;
+		}
+		for (const cluster of clusters) {
+			if (seen.has(cluster)) {
+				problem(['environments', name], `cluster '${cluster}' is in environment '${seen.get(cluster)}' as well; a cluster is in one environment`);
+			}
+			seen.set(cluster, name);
+		}
+	}
+}
+
+function unknownFields(map, known, path, problem) {
+	for (const field of Object.keys(map).filter(name => !known.includes(name))) {
+		const similar = known.find(candidate => editDistance(field, candidate) <= 2);
+		problem([...path, field], `unknown field '${field}'${similar ? ` (did you mean '${similar}'?)` : ''}`);
+	}
+}
+
+function isMap(value) {
+	return Boolean(value) && typeof value === 'object' && !Array.isArray(value);
+}
+
+// `sopsify[0].production[1].values[2].namespaces`
+// Where the value entries of a checked config are in its text, as the path
+// of checkConfig problems. Expanding the config keeps them for the entries it
+// copies (copyEntryPath), so problems found afterwards point at the entry
+// that was written.
+const entryPaths = new WeakMap();
+
+export function recordEntryPaths(config) {
+	config.sopsify.forEach((clusterItem, item) => {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		templateDefs.forEach((templateDef, def) => {
+			(templateDef.values ?? []).forEach((entry, index) => entryPaths.set(entry, ['sopsify', item, cluster, def, 'values', index]));
+		});
+	});
+}
+
+export function entryPath(entry) {
+	return entryPaths.get(entry);
+}
+
+export function copyEntryPath(from, to) {
+	if (entryPaths.has(from)) {
+		entryPaths.set(to, entryPaths.get(from));
+	}
+}
+
+export function describePath(path) {
+	return path.map((segment, index) => (typeof segment === 'number' ? `[${segment}]` : `${index > 0 ? '.' : ''}${segment}`)).join('');
+}
+
+// Line and column (from 1) of the node at `path` in the text of a YAML file,
+// or of its closest ancestor that can be found. Block mappings and sequences
+// are followed by their indentation; nodes inside flow collections
+// (`[a, b]`) resolve to the collection.
+export function locate(text, path) {
+	const lines = text.split('\n').map(markers);
+	let node = { line: -1, marker: { col: -1, type: 'root' } };
+	for (const segment of path) {
+		const child = childNodes(lines, node).filter(candidate => (typeof segment === 'number'
+			? candidate.marker.type === 'item'
+			: candidate.marker.type === 'key' && candidate.marker.name === String(segment)))[typeof segment === 'number' ? segment : 0];
+		if (!child) {
+			break;
+		}
+		node = child;
+	}
+	return node.line === -1 ? { line: 1, column: 1 } : { line: node.line + 1, column: node.marker.col + 1 };
+}
+
+// The `- ` items and `key:` starts of a line with their columns, e.g.
+// `  - key: a` -> [{item, 2}, {key 'key', 4}]; plain keys may hold colons not
+// followed by a space (`environment:prod:`). Comments and document markers
+// have none
+function markers(line) {
+	if (/^\s*(#|$)/.test(line) || /^(---|\.\.\.)(\s|$)/.test(line)) {
+		return [];
+	}
+	const found = [];
+	let col = line.search(/\S/);
+	for (;;) {
+		const rest = line.slice(col);
+		if (/^-(\s|$)/.test(rest)) {
+			found.push({ type: 'item', col });
+			const next = rest.slice(1).search(/\S/);
+			if (next === -1) {
+				return found;
+			}
+			col += 1 + next;
+			continue;
+		}
+		const key = /^("(?:[^"\\]|\\.)*"|'(?:[^']|'')*'|[^\s#'"[{](?:[^:#]|:(?!\s|$))*?)\s*:(\s|$)/.exec(rest);
+		if (key) {
+			const raw = key[1];
+			found.push({ type: 'key', col, name: raw.startsWith('"') ? JSON.parse(raw) : raw.startsWith("'") ? raw.slice(1, -1).replace(/''/g, "'") : raw });
+		}
+		return found;
+	}
+}
+
+// The nodes directly below a node: the rest of its own line for an item,
+// then the lines indented deeper than it (a key also owns the `- ` items of
+// a list at its own indentation), all at the column of the first of them
+function childNodes(lines, { line, marker }) {
+	const candidates = [];
+	if (line >= 0) {
+		const own = lines[line];
+		const index = own.indexOf(marker);
+		if (marker.type === 'item' && own[index + 1]) {
+			candidates.push({ line, marker: own[index + 1] });
+		}
+	}
+	for (let next = line + 1; next < lines.length; next++) {
+		const [first] = lines[next];
+		if (!first) {
+			continue;
+		}
+		if (first.col < marker.col || (first.col === marker.col && !(marker.type === 'key' && first.type === 'item'))) {
+			break;
+		}
+		candidates.push({ line: next, marker: first });
+	}
+	if (candidates.length === 0) {
+		return [];
+	}
+	const col = candidates[0].marker.col;
+	const type = candidates[0].marker.type;
+	return candidates.filter(candidate => candidate.marker.col === col && (candidate.marker.type === type || candidate.line === line));
+}
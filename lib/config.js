@@ -0,0 +1,87 @@
+import { existsSync } from 'fs';
+import { basename, dirname, isAbsolute, join, relative, resolve } from 'path';
+
+export const DEFAULT_CONFIG_FILE = '.sopsify.yaml';
+
+let configPath = DEFAULT_CONFIG_FILE;
+let invocationDir;
+
+// The config file, relative to the project root (the working directory once
+// the project has been located)
+export function configFile() {
+	return configPath;
+}
+
+// The project root is the folder of the config file: `--config <FILE>`, or
+// the nearest .sopsify.yaml in the working directory or one of its parents.
+// sopsify changes into it, so `clusters/`, `namespaces/` and `.sops.yaml`
+// resolve as usual when run from a subfolder. Without any config file the
+// working directory stays as it is.
+export function useProject(explicit) {
+	const start = process.cwd();
+	const path = explicit ? resolve(explicit) : findUpwards(start);
+	if (explicit && !existsSync(path)) {
+		throw new Error(`Config file '${explicit}' does not exist`);
+	}
+	if (!path) {
+		return;
+	}
+
+	const root = dirname(path);
+	configPath = basename(path);
+	if (root !== start) {
+		process.chdir(root);
+		invocationDir = start;
+		console.log(`📂 Using ${join(relative(start, root) || '.', configPath)}`);
+	}
+}
+
+// Paths given on the command line are meant relative to where sopsify was
+// started, not to the project root
+export function fromInvocation(path) {
+	if (!invocationDir || typeof path !== 'string' || isAbsolute(path)) {
+		return path;
+	}
+	return resolve(invocationDir, path);
+}
+
+// Options taking a <FILE> or <FOLDER> that were given on the command line
+export function resolveInvocationPaths(command) {
+	for (const option of command.options) {
+		const key = option.attributeName();
+		if (/<(FILE|FOLDER)>/.test(option.flags) && command.getOptionValueSource(key) === 'cli') {
+			const value = command.getOptionValue(key);
+			command.setOptionValueWithSource(key, Array.isArray(value) ? value.map(fromInvocation) : fromInvocation(value), 'cli');
+		}
+	}
+}
+
+// commander assigns options the program knows to the program, even when
+// given after a subcommand; a subcommand with an option of the same name
+// (`check -t`, `bench --jobs`, `drift --namespace`) gets the value instead
+export function forwardProgramOptions(program, command) {
+	if (command === program) {
+		return;
+	}
+	for (const option of command.options) {
+		const key = option.attributeName();
+		if (program.options.some(o => o.attributeName() === key) && program.getOptionValueSource(key) === 'cli') {
+			const value = program.getOptionValue(key);
+			// Repeatable options of the subcommand (a list default) keep every value
+			const repeatable = option.variadic || Array.isArray(command.getOptionValue(key));
+			command.setOptionValueWithSource(key, Array.isArray(value) && !repeatable ? value[value.length - 1] : value, 'cli');
+		}
+	}
+}
+
+function findUpwards(dir) {
+	for (let current = dir; ; current = dirname(current)) {
+		const candidate = join(current, DEFAULT_CONFIG_FILE);
+		if (existsSync(candidate)) {
+			return candidate;
+		}
+		if (dirname(current) === current) {
+			return undefined;
+		}
+	}
+}
@@ -0,0 +1,54 @@
+import fs from 'fs/promises';
+import { createHash } from 'crypto';
+
+const DEFAULT_MAX_ENTRIES = 256;
+
+// Keeps decrypted outputs in memory for the duration of a run, keyed by the
+// sha256 of the encrypted content, so the same file is only decrypted once.
+// Plaintext is held in Buffers which are zero-filled when evicted (least
+// recently used first) or when the cache is cleared. `env` is passed on to the
+// backend (`sops_env`), as is the sops `type` of the content (engines.js); the
+// same content decrypts to the same plaintext.
+export function createDecryptCache(backend, { maxEntries = DEFAULT_MAX_ENTRIES } = {}) {
+	const entries = new Map();
+
+	function remember(hash, plaintext) {
+		entries.set(hash, Buffer.from(plaintext, 'utf-8'));
+		while (entries.size > maxEntries) {
+			const [oldest, buffer] = entries.entries().next().value;
+			buffer.fill(0);
+			entries.delete(oldest);
+		}
+	}
+
+	const cache = {
+		// Decrypts encrypted content
+		decryptContent(content, env, type) {
+			const hash = createHash('sha256').update(content).digest('hex');
+			const cached = entries.get(hash);
+			if (cached) {
+				// Refresh the entry so it is evicted last
+				entries.delete(hash);
+				entries.set(hash, cached);
+				return cached.toString('utf-8');
+			}
+
+			const plaintext = backend.decryptContent(content, env, type);
+			remember(hash, plaintext);
+			return plaintext;
+		},
+
+		// Decrypts a file; the content read is what gets decrypted and hashed
+		async decrypt(path, env, type) {
+			return cache.decryptContent(await fs.readFile(path, 'utf-8'), env, type);
+		},
+
+		clear() {
+			for (const buffer of entries.values()) {
+				buffer.fill(0);
+			}
+			entries.clear();
+		},
+	};
+	return cache;
+}
@@ -0,0 +1,75 @@
+import fs from 'fs/promises';
+import { join, relative, dirname, isAbsolute, sep } from 'path';
+import { getBackend } from './backends.js';
+import { assertPlaintextPathIgnored } from './git.js';
+import { sopsEnv } from './sops-env.js';
+import { loadYamlFile } from './sopsify.js';
+import { configFile } from './config.js';
+
+// Reverses a run for inspection: decrypts every output below the tree into
+// plaintext copies in another folder, keeping the layout, e.g.
+// `clusters/production/secrets/gitlab/db.enc.yaml` becomes
+// `<output>/production/secrets/gitlab/db.yaml`. Staged (hidden) files are
+// skipped, and the plaintext never lands inside the encrypted tree.
+export async function decrypt(opts) {
+	if (!opts.output) {
+		throw new Error('decrypt requires --output <FOLDER>');
+	}
+	const tree = opts.tree;
+	const fromTree = relative(tree, opts.output);
+	if (fromTree === '' || (!isAbsolute(fromTree) && fromTree !== '..' && !fromTree.startsWith(`..${sep}`))) {
+		throw new Error(`Output folder '${opts.output}' must not be inside '${tree}'`);
+	}
+
+	const backend = getBackend(opts);
+	await backend.check();
+	const config = await loadConfig();
+
+	const files = await encryptedFiles(tree, backend);
+	console.log(`🔄 Decrypting ${files.length} outputs from ${tree} to ${opts.output}`);
+
+	for (const path of files) {
+		const rel = relative(tree, path);
+		const target = join(opts.output, rel.slice(0, -backend.suffix.length) + '.yaml');
+		assertPlaintextPathIgnored(target, opts);
+
+		// <cluster>/secrets/<namespace>/<file> gets the namespace's sops_env
+		const parts = rel.split(sep);
+		const env = parts.length === 4 ? sopsEnv(config, parts[0], parts[2]) : undefined;
+
+		const plaintext = backend.decrypt(path, env);
+		await fs.mkdir(dirname(target), { recursive: true });
+		await fs.writeFile(target, plaintext, { encoding: 'utf-8', mode: 0o600 });
+		console.log(`   🔓 Decrypted: ${rel}`);
+	}
+
+	console.log(`\n✅ ${files.length} plaintext copies written to ${opts.output}; delete them when you are done`);
+}
+
+// Without .sopsify.yaml (e.g. a checkout of the outputs only) nothing has a sops_env
+async function loadConfig() {
+	try {
+		return await loadYamlFile(configFile()) || {};
+	} catch (err) {
+		if (err.code === 'ENOENT') {
+			return {};
+		}
+		throw err;
+	}
+}
+
+async function encryptedFiles(dir, backend) {
+	const files = [];
+	for (const entry of await fs.readdir(dir, { withFileTypes: true })) {
+		if (entry.name.startsWith('.')) {
+			continue;
+		}
+		const path = join(dir, entry.name);
+		if (entry.isDirectory()) {
+			files.push(...await encryptedFiles(path, backend));
+		} else if (entry.name.endsWith(backend.suffix)) {
+			files.push(path);
+		}
+	}
+	return files.sort();
+}
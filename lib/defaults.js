@@ -0,0 +1,55 @@
+// `defaults` in .sopsify.yaml give keys a value for every namespace, so a key
+// that is the same almost everywhere is not listed under every namespace:
+//
+//   defaults:
+//     log-level: info
+//     registry-token:
+//       value_from: { env: REGISTRY_TOKEN }
+//       description: Pull token of the registry
+//
+//   - key: log-level
+//     value: debug
+//     namespaces: [staging]
+//
+// A default is a value, or the fields of a value entry without `key`,
+// `namespaces` and `match`. Entries for a namespace, namespace variable files
+// and `match` rules win over it: every template definition gets the defaults
+// as rules after its own, matching every namespace.
+export function applyDefaults(config) {
+	const defaults = defaultEntries(config).map(entry => ({ ...entry, default: true }));
+	if (defaults.length === 0) {
+		return;
+	}
+	for (const clusterItem of config.sopsify) {
+		for (const templateDef of Object.values(clusterItem)[0]) {
+			templateDef.values = [...(templateDef.values ?? []), ...defaults];
+		}
+	}
+}
+
+// The value entries of a template definition without the defaults the
+// template does not use, so their `value_from` is not resolved for nothing. A
+// default is used when one of the placeholders or required keys of the template
+// names it or may be one of its `key_aliases`, which contain the key.
+export function usedDefaults(values, keys) {
+	const names = keys.map(aliasForm);
+	return values.filter(entry => !isDefaultEntry(entry) || names.some(name => name.includes(aliasForm(entry.key))));
+}
+
+// Aliases may change the case and `-` to `_`
+function aliasForm(name) {
+	return String(name).toLowerCase().replace(/_/g, '-');
+}
+
+// The defaults as rule entries
+export function defaultEntries(config) {
+	return Object.entries(config.defaults ?? {}).map(([key, spec]) => ({ key, ...(isMap(spec) ? spec : { value: spec }), match: '.*' }));
+}
+
+export function isDefaultEntry(entry) {
+	return entry.default === true;
+}
+
+function isMap(value) {
+	return Boolean(value) && typeof value === 'object' && !Array.isArray(value);
+}
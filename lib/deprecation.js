@@ -0,0 +1,29 @@
+// Keys are deprecated on their entries, or their default, while templates
+// move off them:
+//
+//   - key: db-pass
+//     value: s3cr3t
+//     namespaces: [backend]
+//     deprecated: true
+//     replaced_by: db-password
+//
+// A key is deprecated for a template when any of its entries is. Runs warn
+// for every template still using one, `lint --deny-deprecated` fails.
+export function deprecatedKeys(values) {
+	const deprecated = new Map();
+	for (const entry of values.filter(entry => entry.deprecated === true)) {
+		if (!deprecated.has(entry.key) || entry.replaced_by !== undefined) {
+			deprecated.set(entry.key, entry.replaced_by);
+		}
+	}
+	return deprecated;
+}
+
+// The deprecated keys the placeholders of a template use, directly or by a
+// key alias, as `deprecated key 'db-pass' (replaced by 'db-password')`
+export function deprecationWarnings(values, placeholders, aliases = new Map()) {
+	const deprecated = deprecatedKeys(values);
+	const used = [...new Set([...placeholders].map(placeholder => aliases.get(placeholder) ?? placeholder))];
+	return used.filter(key => deprecated.has(key))
+		.map(key => `deprecated key '${key}'${deprecated.get(key) ? ` (replaced by '${deprecated.get(key)}')` : ''}`);
+}
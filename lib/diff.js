@@ -0,0 +1,264 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { join, relative } from 'path';
+import { execFileSync } from 'child_process';
+import { createHmac, randomBytes } from 'crypto';
+import { getBackend } from './backends.js';
+import { createDecryptCache } from './decrypt-cache.js';
+import { previewOutputs } from './sopsify.js';
+import { joinParts } from './chunking.js';
+
+const SECRET_SECTIONS = ['data', 'stringData', 'binaryData'];
+const CONTEXT_LINES = 3;
+
+// Decrypts two encrypted files, two output trees or an output tree at two git
+// refs in memory and prints a structural diff of the changed keys. Values are
+// redacted unless `showValues` is set.
+export async function diff(a, b, opts) {
+	if (opts.render) {
+		return renderDiff(opts);
+	}
+	const backend = getBackend(opts);
+	const decryptCache = createDecryptCache(backend);
+	let pairs;
+
+	if (opts.git) {
+		const [refA, refB] = opts.git.split('..');
+		if (!refA || !refB) {
+			throw new Error(`--git expects a range like 'origin/main..HEAD', got '${opts.git}'`);
+		}
+		const dir = a || 'clusters';
+		pairs = pairFiles(gitFiles(refA, dir, backend), gitFiles(refB, dir, backend), backend);
+		console.log(`🔍 Comparing ${dir} at ${refA} and ${refB}`);
+	} else {
+		if (!a || !b) {
+			throw new Error('diff requires two files or folders, or --git <REF_A>..<REF_B>');
+		}
+		const [statA, statB] = await Promise.all([fs.stat(a), fs.stat(b)]);
+		if (statA.isDirectory() !== statB.isDirectory()) {
+			throw new Error(`Cannot compare a file with a folder: '${a}', '${b}'`);
+		}
+		pairs = statA.isDirectory()
+			? pairFiles(await treeFiles(a, backend), await treeFiles(b, backend), backend)
+			: [{ name: `${a} → ${b}`, a: async () => [await fs.readFile(a, 'utf-8')], b: async () => [await fs.readFile(b, 'utf-8')] }];
+		console.log(`🔍 Comparing ${a} and ${b}`);
+	}
+
+	let changed = 0;
+	for (const pair of pairs) {
+		if (!pair.a) {
+			changed++;
+			console.log(`\n+ ${pair.name} (added)`);
+			continue;
+		}
+		if (!pair.b) {
+			changed++;
+			console.log(`\n- ${pair.name} (removed)`);
+			continue;
+		}
+
+		const [before, after] = flattenDocuments(decrypt(await pair.a(), decryptCache), decrypt(await pair.b(), decryptCache));
+		const lines = diffFlat(before, after, opts.showValues);
+		if (lines.length > 0) {
+			changed++;
+			console.log(`\n~ ${pair.name}`);
+			lines.forEach(line => console.log(`   ${line}`));
+		}
+	}
+
+	decryptCache.clear();
+	console.log(changed === 0 ? '\n✅ No differences' : `\n📝 ${changed} of ${pairs.length} files differ`);
+}
+
+// `--render`: renders the templates as a run would, without writing, and
+// prints a unified diff of every output against the file in place (decrypted
+// in memory). Values of data/stringData/binaryData in encrypted outputs are
+// replaced by a keyed hash, equal on both sides for equal values, unless
+// `showValues` is set.
+async function renderDiff(opts) {
+	const outputs = await previewOutputs(opts);
+	const redact = opts.showValues ? undefined : redactor();
+	console.log(`\n🔍 Comparing ${outputs.length} rendered outputs with the files in place`);
+
+	let changed = 0;
+	for (const output of outputs) {
+		let current;
+		try {
+			current = await fs.readFile(output.path, 'utf-8');
+		} catch (err) {
+			if (err.code !== 'ENOENT') {
+				throw err;
+			}
+			changed++;
+			console.log(`\n+ ${output.path} (added)`);
+			continue;
+		}
+		const { engine } = output;
+		if (output.encrypted) {
+			current = output.backend.decryptContent(current, output.env, engine.sopsType);
+		}
+
+		const normalize = content => {
+			const documents = engine.parse(content, output.path);
+			return engine.dump(documents.map(({ sops, ...document }) => (output.encrypted && redact ? redact(document) : document)));
+		};
+		const lines = content => normalize(content).replace(/\n$/, '').split('\n');
+		const hunks = unifiedDiff(lines(current), lines(output.manifest));
+		if (hunks.length > 0) {
+			changed++;
+			console.log(`\n--- ${output.path}\n+++ ${output.path} (rendered)`);
+			hunks.forEach(line => console.log(line));
+		}
+	}
+
+	console.log(changed === 0 ? '\n✅ No differences' : `\n📝 ${changed} of ${outputs.length} outputs differ`);
+}
+
+// The key is random per run, so the hashes cannot be looked up across runs
+function redactor() {
+	const key = randomBytes(32);
+	const hash = value => `<redacted:${createHmac('sha256', key).update(String(value)).digest('hex').slice(0, 6)}>`;
+	return document => ({
+		...document,
+		...Object.fromEntries(SECRET_SECTIONS.filter(section => document[section] && typeof document[section] === 'object')
+			.map(section => [section, Object.fromEntries(Object.entries(document[section]).map(([k, v]) => [k, hash(v)]))])),
+	});
+}
+
+// Lines of a unified diff (`@@ -a,b +c,d @@` hunks with three lines of
+// context), empty when both sides are equal
+function unifiedDiff(before, after) {
+	// Longest common subsequence, from the end
+	const lengths = Array.from({ length: before.length + 1 }, () => new Array(after.length + 1).fill(0));
+	for (let i = before.length - 1; i >= 0; i--) {
+		for (let j = after.length - 1; j >= 0; j--) {
+			lengths[i][j] = before[i] === after[j] ? lengths[i + 1][j + 1] + 1 : Math.max(lengths[i + 1][j], lengths[i][j + 1]);
+		}
+	}
+	const edits = [];
+	let i = 0;
+	let j = 0;
+	while (i < before.length || j < after.length) {
+		if (i < before.length && j < after.length && before[i] === after[j]) {
+			edits.push({ op: ' ', line: before[i], a: i++, b: j++ });
+		} else if (i < before.length && (j === after.length || lengths[i + 1][j] >= lengths[i][j + 1])) {
+			edits.push({ op: '-', line: before[i], a: i++, b: j });
+		} else {
+			edits.push({ op: '+', line: after[j], a: i, b: j++ });
+		}
+	}
+
+	const lines = [];
+	const changes = edits.map((edit, index) => (edit.op !== ' ' ? index : -1)).filter(index => index >= 0);
+	let start = 0;
+	while (start < changes.length) {
+		// Changes closer than twice the context share a hunk
+		let end = start;
+		while (end + 1 < changes.length && changes[end + 1] - changes[end] <= 2 * CONTEXT_LINES) {
+			end++;
+		}
+		const hunk = edits.slice(Math.max(0, changes[start] - CONTEXT_LINES), Math.min(edits.length, changes[end] + CONTEXT_LINES + 1));
+		const countA = hunk.filter(edit => edit.op !== '+').length;
+		const countB = hunk.filter(edit => edit.op !== '-').length;
+		lines.push(`@@ -${hunk[0].a + (countA > 0 ? 1 : 0)},${countA} +${hunk[0].b + (countB > 0 ? 1 : 0)},${countB} @@`);
+		hunk.forEach(edit => lines.push(`${edit.op}${edit.line}`));
+		start = end + 1;
+	}
+	return lines;
+}
+
+// The documents of the files of an output, without their sops metadata and
+// with split documents joined again
+function decrypt(contents, decryptCache) {
+	return joinParts(contents.flatMap(content => yaml.loadAll(decryptCache.decryptContent(content)))
+		.filter(document => document !== null && typeof document === 'object')
+		.map(({ sops, ...document }) => document));
+}
+
+function diffFlat(before, after, showValues) {
+	const show = value => JSON.stringify(value);
+	const lines = [];
+	const keys = [...new Set([...Object.keys(before), ...Object.keys(after)])].sort();
+
+	for (const key of keys) {
+		if (!(key in before)) {
+			lines.push(`+ ${key}${showValues ? `: ${show(after[key])}` : ''}`);
+		} else if (!(key in after)) {
+			lines.push(`- ${key}${showValues ? `: ${show(before[key])}` : ''}`);
+		} else if (before[key] !== after[key]) {
+			lines.push(showValues ? `~ ${key}: ${show(before[key])} → ${show(after[key])}` : `~ ${key} (changed)`);
+		}
+	}
+	return lines;
+}
+
+// Both sides flattened; when either has several documents, their keys start
+// with the kind and name of the document (`Secret/db.data.password`), or its
+// position without a name (`[1].data.password`)
+function flattenDocuments(before, after) {
+	const several = before.length > 1 || after.length > 1;
+	const flat = documents => documents.reduce((result, document, index) => {
+		const name = document.metadata && document.metadata.name;
+		return flatten(document, several ? (name ? `${document.kind}/${name}` : `[${index}]`) : '', result);
+	}, {});
+	return [flat(before), flat(after)];
+}
+
+// `{ data: { password: x } }` → `{ 'data.password': x }`
+function flatten(value, prefix = '', result = {}) {
+	if (value !== null && typeof value === 'object') {
+		for (const [k, v] of Object.entries(value)) {
+			flatten(v, prefix ? `${prefix}.${k}` : k, result);
+		}
+	} else {
+		result[prefix] = value;
+	}
+	return result;
+}
+
+function pairFiles(filesA, filesB, backend) {
+	const outputsA = outputFiles(filesA, backend);
+	const outputsB = outputFiles(filesB, backend);
+	const names = [...new Set([...Object.keys(outputsA), ...Object.keys(outputsB)])].sort();
+	return names.map(name => ({ name, a: outputsA[name], b: outputsB[name] }));
+}
+
+// The part files of a split output (`<template>.part-<i>.enc.yaml`) are
+// compared as one output: output name -> loader of the contents of its files
+function outputFiles(files, backend) {
+	const outputs = {};
+	for (const name of Object.keys(files).sort()) {
+		const output = `${name.slice(0, -backend.suffix.length).replace(/\.part-\d+$/, '')}${backend.suffix}`;
+		(outputs[output] = outputs[output] || []).push(files[name]);
+	}
+	return Object.fromEntries(Object.entries(outputs).map(([name, loaders]) => [name, () => Promise.all(loaders.map(load => load()))]));
+}
+
+// relative path -> loader of the encrypted content
+async function treeFiles(dir, backend, root = dir, files = {}) {
+	for (const entry of await fs.readdir(dir, { withFileTypes: true })) {
+		const path = join(dir, entry.name);
+		if (entry.isDirectory()) {
+			await treeFiles(path, backend, root, files);
+		} else if (entry.name.endsWith(backend.suffix)) {
+			files[relative(root, path)] = () => fs.readFile(path, 'utf-8');
+		}
+	}
+	return files;
+}
+
+function gitFiles(ref, dir, backend) {
+	const git = args => execFileSync('git', args, { stdio: 'pipe', encoding: 'utf-8', maxBuffer: 64 * 1024 * 1024 });
+	let listing;
+	try {
+		listing = git(['ls-tree', '-r', '--name-only', '--full-tree', ref, '--', dir]);
+	} catch (err) {
+		throw new Error(`Could not list '${dir}' at git ref '${ref}': ${err.stderr ? err.stderr.toString().trim() : err.message}`);
+	}
+
+	const files = {};
+	for (const path of listing.split('\n').filter(p => p.endsWith(backend.suffix))) {
+		files[relative(dir, path)] = () => git(['show', `${ref}:${path}`]);
+	}
+	return files;
+}
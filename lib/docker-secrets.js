@@ -0,0 +1,67 @@
+import { execFileSync } from 'child_process';
+import { loadConfig, resolveNamespaceValues } from './sopsify.js';
+
+const ENGINES = ['docker', 'podman'];
+
+// Creates or updates Docker Swarm / Podman secrets from the resolved values of
+// one namespace. Values are passed on stdin so they never show up in `ps`.
+export async function dockerSecrets(opts) {
+	const engine = opts.engine || 'docker';
+	if (!ENGINES.includes(engine)) {
+		throw new Error(`Unsupported engine '${engine}', expected one of: ${ENGINES.join(', ')}`);
+	}
+	if (!opts.cluster || !opts.namespace) {
+		throw new Error(`docker-secrets requires --cluster and --namespace`);
+	}
+
+	const config = await loadConfig();
+	const values = await resolveNamespaceValues(config, opts.cluster, opts.namespace, opts.template);
+	const prefix = opts.prefix || '';
+
+	console.log(`🐳 Syncing ${engine} secrets for ${opts.cluster}/${opts.namespace}`);
+
+	for (const [key, value] of Object.entries(values)) {
+		const name = `${prefix}${key}`;
+		if (!/^[a-zA-Z0-9][a-zA-Z0-9_.-]*$/.test(name)) {
+			throw new Error(`Secret name '${name}' is not valid for ${engine}`);
+		}
+
+		const exists = secretExists(engine, name);
+		const labels = [
+			'--label', 'managed-by=sopsify',
+			'--label', `sopsify.cluster=${opts.cluster}`,
+			'--label', `sopsify.namespace=${opts.namespace}`,
+		];
+
+		if (exists && engine === 'podman') {
+			run(engine, ['secret', 'create', '--replace', ...labels, name, '-'], value);
+			console.log(`   🔁 Updated: ${name}`);
+		} else if (exists) {
+			// Swarm secrets are immutable; replacing fails while a service still uses it
+			run(engine, ['secret', 'rm', name]);
+			run(engine, ['secret', 'create', ...labels, name, '-'], value);
+			console.log(`   🔁 Updated: ${name}`);
+		} else {
+			run(engine, ['secret', 'create', ...labels, name, '-'], value);
+			console.log(`   ✅ Created: ${name}`);
+		}
+	}
+}
+
+function secretExists(engine, name) {
+	try {
+		execFileSync(engine, ['secret', 'inspect', name], { stdio: 'pipe' });
+		return true;
+	} catch {
+		return false;
+	}
+}
+
+function run(engine, args, input) {
+	try {
+		execFileSync(engine, args, { input, stdio: ['pipe', 'pipe', 'pipe'] });
+	} catch (err) {
+		const stderr = err.stderr ? err.stderr.toString().trim() : err.message;
+		throw new Error(`\`${engine} ${args.slice(0, 2).join(' ')}\` failed: ${stderr}`);
+	}
+}
@@ -0,0 +1,120 @@
+import fs from 'fs/promises';
+import { loadConfig, loadTemplates, templatePlaceholders, requiredKeys, isSecretEntry, findTemplate } from './sopsify.js';
+import { sourceNames } from './sources/index.js';
+import { isDefaultEntry } from './defaults.js';
+
+// Generates a human-readable report of every managed key: description, owner,
+// the namespaces defining it and the templates consuming it. Values are never
+// included, only where they come from.
+export async function docs(opts) {
+	const config = await loadConfig();
+	const consumers = opts.templates ? await templateConsumers(opts.templates, config) : undefined;
+	const clusters = collectKeys(config, consumers);
+
+	const format = opts.format || 'markdown';
+	let report;
+	if (format === 'markdown') {
+		report = renderMarkdown(clusters);
+	} else if (format === 'html') {
+		report = renderHtml(clusters);
+	} else {
+		throw new Error(`Unknown format '${format}', expected 'markdown' or 'html'`);
+	}
+
+	if (opts.output) {
+		await fs.writeFile(opts.output, report, 'utf-8');
+		console.log(`📝 Report written: ${opts.output}`);
+	} else {
+		process.stdout.write(report);
+	}
+}
+
+// template path -> placeholders and required keys it uses
+async function templateConsumers(dir, config) {
+	const templates = await loadTemplates(dir, [], { config, quiet: true });
+	return Object.fromEntries(Object.entries(templates)
+		.map(([path, documents]) => [path, new Set([...templatePlaceholders(documents), ...requiredKeys(documents)])]));
+}
+
+export function collectKeys(config, consumers) {
+	return config.sopsify.map(clusterItem => {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		const keys = {};
+
+		for (const templateDef of templateDefs) {
+			for (const entry of templateDef.values ?? []) {
+				const key = keys[entry.key] = keys[entry.key] || { namespaces: new Set(), templates: new Set(), sources: new Set(), secret: false };
+				key.description = key.description || entry.description;
+				key.owner = key.owner || entry.owner;
+				key.secret = key.secret || isSecretEntry(entry);
+				(entry.namespaces || [isDefaultEntry(entry) ? '*' : `/${entry.match}/`]).forEach(ns => key.namespaces.add(ns));
+				key.sources.add(entry.value_from ? sourceNames(entry.value_from).join(' → ') : 'literal');
+
+				// Without the template files, the configured template is the best guess
+				const consumer = consumers && consumers[findTemplate(consumers, templateDef.template)];
+				const consumes = consumers ? Boolean(consumer) && consumer.has(entry.key) : true;
+				if (consumes) {
+					key.templates.add(templateDef.template);
+				}
+			}
+		}
+
+		return { cluster, keys };
+	});
+}
+
+export function rows(keys) {
+	return Object.entries(keys)
+		.sort(([a], [b]) => a.localeCompare(b))
+		.map(([name, key]) => ({
+			name,
+			description: key.description || '',
+			owner: key.owner || '',
+			secret: key.secret ? 'yes' : 'no (public)',
+			namespaces: [...key.namespaces].sort().join(', '),
+			templates: key.templates.size > 0 ? [...key.templates].sort().join(', ') : '(unused)',
+			sources: [...key.sources].sort().join(', '),
+		}));
+}
+
+function renderMarkdown(clusters) {
+	const cell = text => String(text).replace(/\|/g, '\\|').replace(/\n/g, ' ');
+	const lines = ['# Managed secrets', '', '_Generated by `sopsify docs`. Values are never included._', ''];
+
+	for (const { cluster, keys } of clusters) {
+		lines.push(`## ${cluster}`, '', '| Key | Description | Owner | Secret | Namespaces | Templates | Source |', '|-----|-------------|-------|--------|------------|-----------|--------|');
+		for (const row of rows(keys)) {
+			lines.push(`| \`${cell(row.name)}\` | ${cell(row.description)} | ${cell(row.owner)} | ${cell(row.secret)} | ${cell(row.namespaces)} | ${cell(row.templates)} | ${cell(row.sources)} |`);
+		}
+		lines.push('');
+	}
+
+	return lines.join('\n');
+}
+
+function renderHtml(clusters) {
+	const escape = text => String(text).replace(/[&<>"']/g, c => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;' })[c]);
+	const sections = clusters.map(({ cluster, keys }) => `
+<h2>${escape(cluster)}</h2>
+<table>
+<thead><tr><th>Key</th><th>Description</th><th>Owner</th><th>Secret</th><th>Namespaces</th><th>Templates</th><th>Source</th></tr></thead>
+<tbody>
+${rows(keys).map(row => `<tr><td><code>${escape(row.name)}</code></td><td>${escape(row.description)}</td><td>${escape(row.owner)}</td><td>${escape(row.secret)}</td><td>${escape(row.namespaces)}</td><td>${escape(row.templates)}</td><td>${escape(row.sources)}</td></tr>`).join('\n')}
+</tbody>
+</table>`).join('\n');
+
+	return `<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Managed secrets</title>
+<style>body{font-family:sans-serif}table{border-collapse:collapse}th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}</style>
+</head>
+<body>
+<h1>Managed secrets</h1>
+<p><em>Generated by <code>sopsify docs</code>. Values are never included.</em></p>
+${sections}
+</body>
+</html>
+`;
+}
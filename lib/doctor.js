@@ -0,0 +1,190 @@
+import fs from 'fs/promises';
+import { constants, existsSync } from 'fs';
+import os from 'os';
+import yaml from 'js-yaml';
+import { join } from 'path';
+import { execFileSync } from 'child_process';
+import { defaultKeyFiles } from './permissions.js';
+import { sopsVersion } from './backends.js';
+import { outputDirs } from './output-roots.js';
+import { configFile } from './config.js';
+import { loadTemplates, findTemplate } from './sopsify.js';
+
+// Runs every preflight check instead of stopping at the first failure, so new
+// users see all problems (with a hint how to fix each) at once.
+export async function doctor(opts) {
+	const results = [];
+	const ok = message => results.push({ level: 'ok', message });
+	const warn = (message, hint) => results.push({ level: 'warn', message, hint });
+	const fail = (message, hint) => results.push({ level: 'error', message, hint });
+
+	checkSops(ok, fail);
+	const sopsConfig = await checkYaml('.sops.yaml', ok, fail, 'Create it with creation_rules for your keys, see https://github.com/getsops/sops#using-sops-yaml-conf-to-select-kms-pgp-and-age-for-new-files');
+	if (sopsConfig) {
+		await checkKeys(sopsConfig, ok, warn, fail);
+	}
+
+	const config = await checkYaml(configFile(), ok, fail, 'Create a .sopsify.yaml, see the README for an example');
+	if (config && !Array.isArray(config.sopsify)) {
+		fail('`.sopsify.yaml` has no `sopsify` list', 'Add a top-level `sopsify:` list of clusters');
+	} else if (config) {
+		await checkTemplates(config, opts.templates, ok, warn, fail);
+		await checkOutputDirs(config, ok, fail);
+	}
+
+	console.log('🩺 sopsify doctor\n');
+	for (const result of results) {
+		const icon = { ok: '✅', warn: '⚠️', error: '❌' }[result.level];
+		console.log(`   ${icon} ${result.message}`);
+		if (result.hint) {
+			console.log(`      💡 ${result.hint}`);
+		}
+	}
+
+	const errors = results.filter(r => r.level === 'error').length;
+	const warnings = results.filter(r => r.level === 'warn').length;
+	if (errors > 0) {
+		throw new Error(`${errors} problems found (${warnings} warnings)`);
+	}
+	console.log(`\n✅ All checks passed (${warnings} warnings)`);
+}
+
+function checkSops(ok, fail) {
+	try {
+		ok(`sops found: ${sopsVersion().line}`);
+	} catch (err) {
+		fail(err.message, 'Install sops from https://github.com/getsops/sops/releases');
+	}
+}
+
+async function checkYaml(path, ok, fail, missingHint) {
+	let content;
+	try {
+		content = await fs.readFile(path, 'utf-8');
+	} catch {
+		fail(`\`${path}\` not found`, missingHint);
+		return undefined;
+	}
+	try {
+		const parsed = yaml.load(content);
+		ok(`\`${path}\` is valid YAML`);
+		return parsed || {};
+	} catch (err) {
+		fail(`\`${path}\` is not valid YAML: ${err.message.split('\n')[0]}`, 'Fix the syntax error at the reported line');
+		return undefined;
+	}
+}
+
+async function checkKeys(sopsConfig, ok, warn, fail) {
+	const rules = sopsConfig.creation_rules || [];
+	if (rules.length === 0) {
+		fail('`.sops.yaml` has no creation_rules', 'Add at least one creation rule with age, pgp or kms recipients');
+		return;
+	}
+
+	const types = new Set(rules.flatMap(rule => [
+		...Object.keys(rule),
+		...(rule.key_groups || []).flatMap(group => Object.keys(group)),
+	]));
+
+	if (types.has('age')) {
+		const keyFile = defaultKeyFiles().find(path => path && existsSync(path));
+		if (process.env.SOPS_AGE_KEY || keyFile) {
+			ok(`age key available${keyFile ? ` in ${keyFile}` : ' via SOPS_AGE_KEY'}`);
+		} else {
+			warn('No age private key found, encryption works but existing outputs cannot be decrypted', 'Set SOPS_AGE_KEY_FILE or place your key in ~/.config/sops/age/keys.txt');
+		}
+	}
+
+	if (types.has('pgp')) {
+		const fingerprints = rules.flatMap(rule => [rule.pgp, ...(rule.key_groups || []).map(g => g.pgp)])
+			.filter(Boolean)
+			.flatMap(pgp => Array.isArray(pgp) ? pgp : String(pgp).split(','))
+			.map(fp => (typeof fp === 'object' ? fp.fp || fp.fingerprint : fp).trim());
+		for (const fp of new Set(fingerprints)) {
+			if (!gpgHasKey(['--list-keys', fp])) {
+				fail(`PGP public key ${fp} is not in the gpg keyring`, `Import it with \`gpg --import\` or \`gpg --recv-keys ${fp}\``);
+			} else if (!gpgHasKey(['--list-secret-keys', fp])) {
+				warn(`PGP key ${fp} has no secret key, existing outputs cannot be decrypted`, 'Import the private key if you need drift, diff or --skip-unchanged');
+			} else {
+				ok(`PGP key ${fp} available`);
+			}
+		}
+	}
+
+	const cloud = [
+		['kms', 'AWS KMS', ['AWS_ACCESS_KEY_ID', 'AWS_PROFILE', 'AWS_WEB_IDENTITY_TOKEN_FILE', 'AWS_CONTAINER_CREDENTIALS_FULL_URI'], [join(os.homedir(), '.aws', 'credentials'), join(os.homedir(), '.aws', 'config')], 'Run `aws configure` / `aws sso login` or export AWS_PROFILE'],
+		['gcp_kms', 'GCP KMS', ['GOOGLE_APPLICATION_CREDENTIALS', 'GOOGLE_CREDENTIALS'], [join(os.homedir(), '.config', 'gcloud', 'application_default_credentials.json')], 'Run `gcloud auth application-default login`'],
+		['azure_keyvault', 'Azure Key Vault', ['AZURE_CLIENT_ID', 'AZURE_TENANT_ID'], [join(os.homedir(), '.azure', 'msal_token_cache.json')], 'Run `az login` or export AZURE_CLIENT_ID/AZURE_TENANT_ID/AZURE_CLIENT_SECRET'],
+		['hc_vault_transit_uri', 'HashiCorp Vault', ['VAULT_TOKEN'], [join(os.homedir(), '.vault-token')], 'Run `vault login` or export VAULT_TOKEN'],
+	];
+	for (const [type, name, envVars, files, hint] of cloud) {
+		if (!types.has(type)) {
+			continue;
+		}
+		const env = envVars.find(v => process.env[v]);
+		const file = files.find(existsSync);
+		if (env || file) {
+			ok(`${name} credentials found (${env || file})`);
+		} else {
+			fail(`No ${name} credentials found`, hint);
+		}
+	}
+}
+
+async function checkTemplates(config, templatesDir, ok, warn, fail) {
+	if (!templatesDir) {
+		warn('Templates not checked', 'Pass -t <FOLDER> to also check template discovery');
+		return;
+	}
+
+	let templates;
+	try {
+		templates = await loadTemplates(templatesDir, [], { config, quiet: true });
+	} catch (err) {
+		fail(`Templates of \`${templatesDir}\` could not be read: ${err.message}`, 'Check the path passed to -t and the template files');
+		return;
+	}
+	const count = Object.keys(templates).length;
+	if (count === 0) {
+		fail(`No templates in \`${templatesDir}\``, 'Templates must have a .yaml or .yml extension, or the one of their template group\'s engine');
+		return;
+	}
+	ok(`${count} templates found in \`${templatesDir}\``);
+
+	const referenced = new Set(config.sopsify.flatMap(item => Object.values(item)[0].map(def => def.template)));
+	for (const template of referenced) {
+		if (!findTemplate(templates, template)) {
+			fail(`Template \`${template}\` referenced in .sopsify.yaml is not in \`${templatesDir}\``, 'Fix the template name or add the file');
+		}
+	}
+}
+
+async function checkOutputDirs(config, ok, fail) {
+	for (const item of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(item)[0];
+		const entries = templateDefs.flatMap(def => [...(def.values ?? []), ...(def.namespaces ? [{ namespaces: def.namespaces }] : [])]);
+		if (entries.length > 0 && entries.every(entry => entry.namespaces && entry.namespaces.every(ns => outputDirs(config, cluster, ns).overridden))) {
+			ok(`All namespaces of \`${cluster}\` have an output root`);
+			continue;
+		}
+
+		const dir = join('clusters', cluster);
+		try {
+			await fs.access(dir, constants.W_OK);
+			ok(`Output folder \`${dir}\` is writable`);
+		} catch (err) {
+			const hint = err.code === 'ENOENT' ? `Create it with \`mkdir -p ${dir}\`` : `Fix the permissions of \`${dir}\``;
+			fail(`Output folder \`${dir}\` is ${err.code === 'ENOENT' ? 'missing' : 'not writable'}`, hint);
+		}
+	}
+}
+
+function gpgHasKey(args) {
+	try {
+		execFileSync('gpg', ['--batch', ...args], { stdio: 'pipe' });
+		return true;
+	} catch {
+		return false;
+	}
+}
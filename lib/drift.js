@@ -0,0 +1,135 @@
+import yaml from 'js-yaml';
+import { join } from 'path';
+import { createHash } from 'crypto';
+import { getBackend } from './backends.js';
+import { kubectl } from './kubernetes.js';
+import { validateNamespaceName, validatePathSegment, loadConfig } from './sopsify.js';
+import { outputDirs } from './output-roots.js';
+import { sopsEnv } from './sops-env.js';
+import { filesBelow } from './files.js';
+import { joinParts, splitFrom } from './chunking.js';
+
+// Compares decrypted outputs with the Secrets in the live cluster. Values are
+// never printed, only key names and shortened hashes.
+export async function drift(opts) {
+	if (!opts.namespace) {
+		throw new Error('drift requires --namespace');
+	}
+
+	validateNamespaceName(opts.namespace);
+	if (opts.cluster) {
+		validatePathSegment(opts.cluster, 'Cluster');
+	}
+
+	const config = await loadConfig();
+	const backend = getBackend(opts);
+	const clusters = opts.cluster ? [opts.cluster] : config.sopsify.map(item => Object.keys(item)[0]);
+	let drifted = 0;
+	let checked = 0;
+
+	for (const clusterName of clusters) {
+		const outputDir = outputDirs(config, clusterName, opts.namespace).secrets;
+		const files = await filesBelow(outputDir, f => f.endsWith(backend.suffix));
+		if (files.length === 0) {
+			continue;
+		}
+
+		console.log(`\n🌍 Checking drift: ${clusterName}/${opts.namespace}`);
+
+		for (const [output, paths] of outputFiles(files, backend)) {
+			// Outputs of multi-document templates hold several Secrets, each checked on its own
+			const documents = paths.flatMap(file => yaml.loadAll(backend.decrypt(join(outputDir, file), sopsEnv(config, clusterName, opts.namespace))));
+			const parts = documents.filter(document => document && document.kind === 'Secret');
+			const secrets = joinParts(parts);
+			for (const expected of secrets) {
+				const name = expected.metadata && expected.metadata.name;
+				const label = secrets.length > 1 ? `${output} (${name})` : output;
+				checked++;
+
+				// A split Secret is compared with all of its parts in the cluster
+				const names = parts.filter(part => splitFrom(part) === name).map(part => part.metadata.name);
+				const live = (names.length > 0 ? names : [name]).map(liveName => ({ name: liveName, secret: getLiveSecret(liveName, opts.namespace, opts) }));
+				const missing = live.filter(part => !part.secret).map(part => `'${part.name}'`);
+				if (missing.length > 0) {
+					drifted++;
+					console.log(`   ❌ ${label}: Secret${missing.length > 1 ? 's' : ''} ${missing.join(', ')} ${missing.length > 1 ? 'do' : 'does'} not exist in the cluster`);
+					continue;
+				}
+
+				const liveData = Object.assign({}, ...live.map(part => part.secret.data || {}));
+				const differences = compareSecretData(secretData(expected), liveData);
+				if (differences.length === 0) {
+					console.log(`   ✅ ${label}: in sync`);
+					continue;
+				}
+
+				drifted++;
+				console.log(`   ⚠️ ${label}: Secret '${name}' has drifted`);
+				for (const diff of differences) {
+					console.log(`      - ${diff}`);
+				}
+			}
+		}
+	}
+
+	if (checked === 0) {
+		throw new Error(`No outputs found for namespace '${opts.namespace}'`);
+	}
+	if (drifted > 0) {
+		throw new Error(`Drift detected in ${drifted} of ${checked} secrets`);
+	}
+	console.log(`\n✅ No drift detected in ${checked} secrets`);
+}
+
+// The part files of a split output (`<template>.part-<i>.enc.yaml`) are
+// checked together: output name -> its files
+function outputFiles(files, backend) {
+	const outputs = new Map();
+	for (const file of files) {
+		const output = `${file.slice(0, -backend.suffix.length).replace(/\.part-\d+$/, '')}${backend.suffix}`;
+		outputs.set(output, [...(outputs.get(output) || []), file]);
+	}
+	return outputs;
+}
+
+function getLiveSecret(name, namespace, opts) {
+	try {
+		return JSON.parse(kubectl(['get', 'secret', name, '--namespace', namespace, '-o', 'json'], opts));
+	} catch (err) {
+		if (/NotFound/.test(err.message)) {
+			return undefined;
+		}
+		throw err;
+	}
+}
+
+// Live Secrets only expose base64 `data`, so `stringData` is encoded the same way
+function secretData(secret) {
+	const data = { ...(secret.data || {}) };
+	for (const [key, value] of Object.entries(secret.stringData || {})) {
+		data[key] = Buffer.from(String(value)).toString('base64');
+	}
+	return data;
+}
+
+function compareSecretData(expected, live) {
+	const differences = [];
+	const keys = new Set([...Object.keys(expected), ...Object.keys(live)]);
+
+	for (const key of keys) {
+		if (!(key in live)) {
+			differences.push(`${key}: missing in cluster`);
+		} else if (!(key in expected)) {
+			differences.push(`${key}: only in cluster`);
+		} else if (String(expected[key]) !== live[key]) {
+			differences.push(`${key}: hash mismatch (expected ${shortHash(expected[key])}, live ${shortHash(live[key])})`);
+		}
+	}
+
+	return differences;
+}
+
+function shortHash(value) {
+	const decoded = Buffer.from(String(value), 'base64');
+	return createHash('sha256').update(decoded).digest('hex').slice(0, 12);
+}
@@ -0,0 +1,89 @@
+import { isRuleEntry } from './rules.js';
+import { describePath, locate, entryPath } from './config-schema.js';
+
+// Value entries of one template definition that give the same key a value for
+// the same namespace, e.g.
+//
+//   - key: db-password
+//     value: one
+//     namespaces: [backend, prod]
+//   - key: db-password
+//     value: two
+//     namespaces: [prod]
+//
+// Returns one duplicate per key and set of entries, with the namespaces they
+// share and whether their values differ. Rule entries (`match`) only fill in
+// namespaces without a value and never conflict.
+export function duplicateKeys(config) {
+	const duplicates = [];
+	config.sopsify.forEach((clusterItem, clusterIndex) => {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		templateDefs.forEach((templateDef, defIndex) => {
+			const byNamespace = new Map();
+			(templateDef.values ?? []).forEach((entry, index) => {
+				if (isRuleEntry(entry) || !Array.isArray(entry.namespaces)) {
+					return;
+				}
+				for (const ns of new Set(entry.namespaces)) {
+					const id = JSON.stringify([entry.key, ns]);
+					byNamespace.set(id, [...(byNamespace.get(id) ?? []), index]);
+				}
+			});
+
+			const byEntries = new Map();
+			for (const [id, indices] of byNamespace) {
+				if (indices.length > 1) {
+					const [key, ns] = JSON.parse(id);
+					const group = JSON.stringify(indices);
+					if (!byEntries.has(group)) {
+						const values = new Set(indices.map(index => valueOf(templateDef.values[index])));
+						byEntries.set(group, { key, cluster, template: templateDef.template, namespaces: [], entries: indices, conflicting: values.size > 1 });
+					}
+					byEntries.get(group).namespaces.push(ns);
+				}
+			}
+			// Entries are located where they were written, before environments,
+			// the matrix and namespace lists were expanded
+			for (const duplicate of byEntries.values()) {
+				const entries = duplicate.entries.map(index => templateDef.values[index]);
+				duplicates.push({
+					...duplicate,
+					entries,
+					paths: duplicate.entries.map((index, i) => [...(entryPath(entries[i]) ?? ['sopsify', clusterIndex, cluster, defIndex, 'values', index]), 'key']),
+					matrixRows: entries.map(entry => entry.matrix),
+				});
+			}
+		});
+	});
+	return duplicates;
+}
+
+// What a key resolves to; entries with the same value, sources and transforms
+// do not conflict
+function valueOf({ value, value_from: valueFrom, transform }) {
+	return JSON.stringify([value, valueFrom, transform]);
+}
+
+// Values are never shown, only where the entries are: their line in the
+// config, or their row of the `--matrix`
+export function describeDuplicate({ key, cluster, template, namespaces, paths, matrixRows, conflicting }, text, path) {
+	const where = paths.map((entryPath, index) => {
+		if (matrixRows[index]) {
+			return `row ${matrixRows[index]}`;
+		}
+		const { line, column } = locate(text, entryPath);
+		return `${path}:${line}:${column} (${describePath(entryPath.slice(2, -1))})`;
+	});
+	const what = conflicting ? 'has conflicting values' : 'is given the same value more than once';
+	return `Key '${key}' of ${cluster}/${template} ${what} for namespace${namespaces.length > 1 ? 's' : ''} ${namespaces.join(', ')}: ${where.join(', ')}`;
+}
+
+// `--allow-duplicate-keys`: the last entry of a duplicate keeps the shared
+// namespaces, they are removed from the earlier ones
+export function keepLastEntries(duplicates) {
+	for (const { entries, namespaces } of duplicates) {
+		for (const entry of entries.slice(0, -1)) {
+			entry.namespaces = entry.namespaces.filter(ns => !namespaces.includes(ns));
+		}
+	}
+}
@@ -0,0 +1,242 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { spawnSync } from 'child_process';
+import { resolve } from 'path';
+import { loadConfig, loadTemplates, findTemplate, expandConfig } from './sopsify.js';
+import { getBackend, sopsArgs } from './backends.js';
+import { sopsEnv } from './sops-env.js';
+import { configFile } from './config.js';
+import { isRuleEntry } from './rules.js';
+import { checkConfig } from './config-schema.js';
+import { groupSettings } from './template-groups.js';
+import { templateEngine, outputFileName } from './engines.js';
+import { outputDirs, globalOutputDir } from './output-roots.js';
+import { splitFrom } from './chunking.js';
+
+const SECTIONS = ['data', 'stringData'];
+const PLACEHOLDER = /^\$\{([a-zA-Z_][a-zA-Z0-9_-]*)\}$/;
+
+// Opens an output in the sops editor and reports the values that changed,
+// which the next run would overwrite. With `sync`, values whose template field
+// is a plain `${key}` placeholder are written back to the entry of
+// .sopsify.yaml defining the key for that namespace alone. The config is
+// edited as text, so comments and formatting survive; it is parsed again and
+// compared with the structural change before it is written. Values are never
+// printed.
+export async function edit(file, opts) {
+	if (!file) {
+		throw new Error('edit requires the encrypted output to edit, e.g. clusters/production/secrets/payments/app.enc.yaml');
+	}
+	if (opts.sync && !opts.templates) {
+		throw new Error('--sync needs the templates to map fields to keys: pass -t <FOLDER>');
+	}
+	const config = await loadConfig();
+	const output = findOutput(config, file, opts);
+	if (!output) {
+		console.warn(`⚠️ '${file}' is not an output of ${configFile()}; edits can't be related to templates`);
+	}
+	// As a run encrypts it: with the backend of the template's group, else --backend
+	const backend = getBackend({ ...opts, backend: output?.backend ?? opts.backend });
+	if (backend.name !== 'sops') {
+		throw new Error(`'${file}' is encrypted with the ${backend.name} backend; edit opens outputs in the sops editor, so it only edits outputs of the sops backend`);
+	}
+	await backend.check();
+	const env = output && output.namespace !== undefined ? sopsEnv(config, output.cluster, output.namespace) : undefined;
+	const parse = content => (output ? output.engine : templateEngine()).parse(content, file);
+
+	const before = parse(backend.decrypt(file, env));
+	// sops exits with 200 when the file was saved unchanged
+	const result = spawnSync(backend.bin, [...sopsArgs(), file], { stdio: 'inherit', env: env ? { ...process.env, ...env } : undefined });
+	if (result.error) {
+		throw new Error(`Could not run sops: ${result.error.message}`);
+	}
+	if (result.status !== 0 && result.status !== 200) {
+		throw new Error(`sops exited with ${result.status}; '${file}' was not changed`);
+	}
+	const changes = changedFields(before, parse(backend.decrypt(file, env)));
+	if (changes.length === 0) {
+		console.log('✅ No values changed');
+		return;
+	}
+
+	console.log(`📝 ${changes.length} fields changed: ${changes.map(describe).join(', ')}`);
+	if (!output || !output.namespace) {
+		console.warn(`⚠️ The next run may overwrite them`);
+		return;
+	}
+	if (!opts.sync) {
+		console.warn(`⚠️ The next run overwrites them with the values of ${configFile()}; pass --sync -t <FOLDER> to write them back`);
+		return;
+	}
+	await syncBack(changes, output, opts);
+}
+
+// The output `file` is by the plan of the config: its cluster, namespace
+// (undefined for global templates), template, engine and the backend of the
+// template's group, found among the files the template definitions are
+// written to; split outputs (`<template>.part-<i>`) included. Namespaces only
+// rules (`match`) select are not known.
+function findOutput(config, file, opts) {
+	const part = /\.part-(\d+)\.[^/\\]+$/.exec(file);
+	for (const clusterItem of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		for (const templateDef of templateDefs) {
+			const group = groupSettings(config, templateDef.template);
+			const engine = templateEngine(group.engine);
+			const backend = group.backend ?? opts.backend;
+			const suffix = getBackend({ ...opts, backend }).suffix;
+			const written = [
+				outputFileName(templateDef.template, suffix, engine),
+				...(part ? [outputFileName(templateDef.template.replace(/\.ya?ml$/, `.part-${part[1]}$&`), suffix, engine)] : []),
+			];
+			const namespaces = new Set([
+				...(templateDef.namespaces ?? []),
+				...(templateDef.values ?? []).filter(entry => !isRuleEntry(entry)).flatMap(entry => entry.namespaces ?? []),
+			]);
+			const dirs = [
+				{ dir: globalOutputDir(cluster) },
+				...[...namespaces].map(namespace => ({ namespace, dir: outputDirs(config, cluster, namespace, group.output_root).secrets })),
+			];
+			const found = dirs.find(({ dir }) => written.some(name => resolve(dir, name) === resolve(file)));
+			if (found) {
+				return { cluster, namespace: found.namespace, template: templateDef.template, engine, backend };
+			}
+		}
+	}
+	return undefined;
+}
+
+// Fields of the data/stringData sections that differ, per document
+function changedFields(before, after) {
+	const changes = [];
+	after.forEach((document, index) => {
+		if (!document) {
+			return;
+		}
+		const previous = before[index] || {};
+		for (const section of SECTIONS) {
+			const was = previous[section] || {};
+			const now = document[section] || {};
+			for (const field of new Set([...Object.keys(was), ...Object.keys(now)])) {
+				if (was[field] !== now[field]) {
+					changes.push({ document, section, field, value: now[field], removed: !(field in now), added: !(field in was) });
+				}
+			}
+		}
+	});
+	return changes;
+}
+
+// Outputs of dotenv and tfvars templates have a single document without a name
+function describe({ document, section, field, added, removed }) {
+	const name = document.metadata ? `${document.kind}/${document.metadata.name} ${section}.${field}` : field;
+	return added ? `${name} (added)` : removed ? `${name} (removed)` : name;
+}
+
+async function syncBack(changes, output, opts) {
+	let text = await fs.readFile(configFile(), 'utf-8');
+	const { config, sources } = await entrySources(text, output.cluster);
+	const clusterItem = config.sopsify.find(item => Object.keys(item)[0] === output.cluster);
+	const templateDefs = clusterItem ? Object.values(clusterItem)[0] : [];
+	const templateDef = templateDefs.find(def => def.template === output.template);
+	if (!templateDef) {
+		throw new Error(`No template definition for '${output.template}' in cluster '${output.cluster}' of ${configFile()}`);
+	}
+	const templates = await loadTemplates(opts.templates, [], { config });
+	const templatePath = findTemplate(templates, templateDef.template);
+	if (!templatePath) {
+		throw new Error(`Template '${templateDef.template}' not found in '${opts.templates}'`);
+	}
+
+	const expected = yaml.load(text);
+	const synced = [];
+	for (const change of changes) {
+		const key = fieldKey(templates[templatePath], change);
+		const problem = key === undefined ? 'not a ${key} placeholder in the template' : change.removed || change.added ? 'added or removed fields are not synced' : undefined;
+		const entry = problem ? undefined : (templateDef.values ?? []).find(candidate => candidate.key === key && !isRuleEntry(candidate) && candidate.namespaces.includes(output.namespace));
+		const source = entry && sources.get(entry);
+		const why = problem
+			?? (!entry ? `key '${key}' has no entry for '${output.namespace}' in ${configFile()} (a rule, a default or a namespace variable file)` : undefined)
+			?? (!source ? `the entry of '${key}' for '${output.namespace}' is not in the item of cluster '${output.cluster}' (e.g. it comes from an environment:<name> item)` : undefined)
+			?? (entry.namespaces.length > 1 ? `the entry of '${key}' is shared with ${entry.namespaces.filter(ns => ns !== output.namespace).join(', ')}` : undefined)
+			?? (entry.value_from !== undefined || entry.transform !== undefined ? `'${key}' comes from value_from or a transform` : undefined)
+			?? (typeof change.value !== 'string' || change.value.includes('\n') ? `the new value of '${key}' is not a single-line string` : undefined);
+		if (why) {
+			console.warn(`   ⚠️ Not synced: ${describe(change)}: ${why}; the next run overwrites it`);
+			continue;
+		}
+
+		// The structural change, and the one line of the config making it
+		const expectedEntry = Object.values(expected.sopsify[source.item])[0][source.def].values[source.entry];
+		const previous = expectedEntry.value;
+		expectedEntry.value = change.value;
+		const edited = replaceValueLine(text, previous, change.value, expected);
+		if (!edited) {
+			expectedEntry.value = previous;
+			console.warn(`   ⚠️ Not synced: ${describe(change)}: its value can't be changed in ${configFile()} without changing its structure; edit it manually`);
+			continue;
+		}
+		text = edited;
+		synced.push(key);
+	}
+
+	if (synced.length > 0) {
+		await fs.writeFile(configFile(), text, 'utf-8');
+		console.log(`✅ Written back to ${configFile()}: ${synced.join(', ')}`);
+	}
+}
+
+// The config as runs see it, and where its entries of the cluster's own item
+// are in the config `text`: entry -> indexes of the item, the template
+// definition and the entry. Entries merged from `environment:<name>` items
+// and defaults have none, so they are not synced.
+async function entrySources(text, cluster) {
+	const { config } = checkConfig(text, configFile());
+	const sources = new Map();
+	config.sopsify.forEach((clusterItem, item) => {
+		if (Object.keys(clusterItem)[0] !== cluster) {
+			return;
+		}
+		Object.values(clusterItem)[0].forEach((templateDef, def) => {
+			(templateDef.values ?? []).forEach((entryDef, entry) => sources.set(entryDef, { item, def, entry }));
+		});
+	});
+	await expandConfig(config, text);
+	return { config, sources };
+}
+
+// The key of the `${key}` placeholder the template has for the changed field;
+// parts of split outputs (`<name>-part-<i>`) are documents of the template
+function fieldKey(documents, { document, section, field }) {
+	const name = splitFrom(document) ?? (document.metadata || {}).name;
+	const template = documents.find(candidate => candidate.kind === document.kind && (candidate.metadata || {}).name === name);
+	const value = template && (template[section] || {})[field];
+	const match = typeof value === 'string' && PLACEHOLDER.exec(value);
+	return match ? match[1] : undefined;
+}
+
+// Tries every `value:` line holding the previous value; the one whose
+// replacement parses to the expected config is it
+function replaceValueLine(text, previous, value, expected) {
+	const lines = text.split('\n');
+	for (let index = 0; index < lines.length; index++) {
+		const match = /^(\s*(?:-\s+)?value:\s*)(.*?)(\s+#.*)?$/.exec(lines[index]);
+		if (!match) {
+			continue;
+		}
+		let parsed;
+		try {
+			parsed = yaml.load(`v: ${match[2]}`).v;
+		} catch {
+			continue;
+		}
+		if (parsed !== previous) {
+			continue;
+		}
+		const candidate = [...lines.slice(0, index), `${match[1]}${JSON.stringify(value)}${match[3] ?? ''}`, ...lines.slice(index + 1)].join('\n');
+		if (JSON.stringify(yaml.load(candidate)) === JSON.stringify(expected)) {
+			return candidate;
+		}
+	}
+	return undefined;
+}
@@ -0,0 +1,62 @@
+import { configFile } from './config.js';
+import { sopsRecipients } from './verify.js';
+
+// sops flags per key type; the values are joined with commas as sops expects
+const KEY_FLAGS = { age: '--age', pgp: '--pgp', kms: '--kms', gcp_kms: '--gcp-kms', azure_kv: '--azure-kv', hc_vault: '--hc-vault-transit' };
+
+// `encryption_keys` in .sopsify.yaml selects the keys the outputs of a
+// namespace are encrypted for, in place of the creation rules of .sops.yaml,
+// e.g. a KMS key for production and an age key for development:
+//
+//   encryption_keys:
+//     dev:
+//       age: [age1...]
+//     production/payments:
+//       kms: [arn:aws:kms:eu-west-1:111122223333:key/payments]
+//       pgp: [85D77543B3D624B63CEA9E6DBC17301B491B3F21]
+//
+// Keys are a namespace or `<cluster>/<namespace>`, which wins as a whole: key
+// types are not merged. Returns undefined for namespaces without an entry.
+export function encryptionKeys(config, cluster, namespace) {
+	const settings = config.encryption_keys;
+	if (settings === undefined || namespace === undefined) {
+		return undefined;
+	}
+	if (!settings || typeof settings !== 'object' || Array.isArray(settings)) {
+		throw new Error(`'encryption_keys' in ${configFile()} must be a map of namespaces to keys`);
+	}
+	const key = settings[`${cluster}/${namespace}`] !== undefined ? `${cluster}/${namespace}` : namespace;
+	const keys = settings[key];
+	if (keys === undefined) {
+		return undefined;
+	}
+
+	if (!keys || typeof keys !== 'object' || Array.isArray(keys) || Object.keys(keys).length === 0) {
+		throw new Error(`'encryption_keys.${key}' in ${configFile()} must map key types (${Object.keys(KEY_FLAGS).join(', ')}) to lists of keys`);
+	}
+	for (const [type, list] of Object.entries(keys)) {
+		if (!KEY_FLAGS[type]) {
+			throw new Error(`Unknown key type '${type}' in 'encryption_keys.${key}', expected one of: ${Object.keys(KEY_FLAGS).join(', ')}`);
+		}
+		if (!Array.isArray(list) || list.length === 0 || !list.every(item => typeof item === 'string' && item.trim() !== '' && !item.includes(','))) {
+			throw new Error(`'encryption_keys.${key}.${type}' in ${configFile()} must be a non-empty list of keys`);
+		}
+	}
+	return keys;
+}
+
+// The sops flags selecting the keys, e.g. ['--age', 'age1...,age1...']
+export function sopsKeyArgs(keys) {
+	return Object.entries(keys).flatMap(([type, list]) => [KEY_FLAGS[type], list.join(',')]);
+}
+
+// Whether sops metadata has exactly the configured keys; Azure Key Vault keys
+// may be configured with their version
+export function encryptedFor(metadata, keys) {
+	const present = sopsRecipients(metadata || {});
+	return Object.entries(present).every(([type, recipients]) => {
+		const configured = keys[type] || [];
+		return recipients.size === configured.length
+			&& configured.every(key => recipients.has(key) || [...recipients].some(recipient => key.startsWith(`${recipient}/`)));
+	});
+}
@@ -0,0 +1,198 @@
+import yaml from 'js-yaml';
+
+// A template engine reads template files of one format into documents and
+// writes rendered documents back in it. `yaml` templates are Kubernetes
+// Secret or ConfigMap manifests; `dotenv` (`KEY=value` lines) and `tfvars`
+// (`name = value` lines) templates become a single Secret document whose
+// `stringData` holds their variables, so placeholders, `secret: false` and
+// the encryption work as for YAML templates:
+//
+//   # dotenv/app.env                   # terraform/db.tfvars
+//   DB_HOST=db.internal                 db_host = "db.internal"
+//   DB_PASSWORD=${db-password}          db_password = "${db-password}"
+//
+// `extension` tells the template files of an engine apart from other files
+// of the templates folder; `sopsType` is the sops input/output type of its
+// outputs.
+const engines = {
+	yaml: {
+		name: 'yaml',
+		extension: /\.ya?ml$/,
+		description: '.yaml/.yml',
+		sopsType: 'yaml',
+		parse(content) {
+			return yaml.loadAll(content).filter(document => document !== null && document !== undefined);
+		},
+		dump(documents) {
+			return documents.map(doc => yaml.dump(doc)).join('---\n');
+		},
+	},
+
+	// Values may be bare, 'single-quoted' (taken as they are) or
+	// "double-quoted" with JSON escapes. Rendered values stay bare when they
+	// only hold characters no shell or dotenv loader treats specially, and
+	// are single-quoted, so `$` is not expanded, unless they hold quotes or
+	// line breaks. Comments and blank lines are not kept.
+	dotenv: {
+		name: 'dotenv',
+		extension: /.\.env$/,
+		description: '.env',
+		sopsType: 'dotenv',
+		parse(content, path) {
+			return [secretDocument(lines(content, path, /^(?:export\s+)?([A-Za-z_][A-Za-z0-9_.]*)\s*=\s*(.*)$/, 'KEY=value', dotenvValue))];
+		},
+		dump(documents) {
+			return variables(documents).map(([name, value]) => {
+				const text = String(value);
+				if (/^[A-Za-z0-9_./:@%+,-]*$/.test(text)) {
+					return `${name}=${text}\n`;
+				}
+				return `${name}=${/['\r\n]/.test(text) ? JSON.stringify(text) : `'${text}'`}\n`;
+			}).join('');
+		},
+	},
+
+	// Single-line string, number and bool variables; lists, maps and heredocs
+	// are not supported. Rendered strings are escaped for HCL, so values
+	// holding `${` are not read as interpolations by Terraform. sops encrypts
+	// the outputs as binary files.
+	tfvars: {
+		name: 'tfvars',
+		extension: /.\.tfvars$/,
+		description: '.tfvars',
+		sopsType: 'binary',
+		parse(content, path) {
+			return [secretDocument(lines(content, path, /^([A-Za-z_][A-Za-z0-9_-]*)\s*=\s*(.*)$/, 'name = value', tfvarsValue))];
+		},
+		dump(documents) {
+			return variables(documents).map(([name, value]) => `${name} = ${typeof value === 'string' ? hclString(value) : String(value)}\n`).join('');
+		},
+	},
+};
+
+export const ENGINE_NAMES = Object.keys(engines);
+
+// The engine of a template group's `engine`, `yaml` without one
+export function templateEngine(name = 'yaml') {
+	const engine = engines[name];
+	if (!engine) {
+		throw new Error(`Unknown template engine '${name}', expected one of: ${ENGINE_NAMES.join(', ')}`);
+	}
+	return engine;
+}
+
+// The output file of a template: the backend's suffix (`.enc.yaml`) replaces
+// the extension of YAML templates, other extensions are kept after the
+// suffix's first part, e.g. `app.env` -> `app.enc.env`
+export function outputFileName(name, suffix, engine = engines.yaml) {
+	if (engine.name === 'yaml') {
+		return name.replace(/\.ya?ml$/, suffix);
+	}
+	return name.replace(/(\.[^./\\]+)$/, `${suffix.replace(/\.ya?ml$/, '')}$1`);
+}
+
+// The template an output of another engine than yaml was written for, e.g.
+// `app.enc.env` -> `app.env`; undefined for other files
+export function engineTemplateName(file) {
+	const engine = Object.values(engines).find(candidate => candidate.name !== 'yaml' && candidate.extension.test(file));
+	return engine ? file.replace(/(\.enc|\.vault)?(\.[^.]+)$/, '$2') : undefined;
+}
+
+function secretDocument(stringData) {
+	return { kind: 'Secret', stringData };
+}
+
+function variables(documents) {
+	const entries = documents.flatMap(document => Object.entries(document.stringData || {}));
+	const nested = entries.find(([, value]) => value !== null && typeof value === 'object');
+	if (nested) {
+		throw new Error(`Variable '${nested[0]}' has a list or map value; dotenv and tfvars outputs only hold strings, numbers and bools`);
+	}
+	return entries;
+}
+
+function lines(content, path, pattern, form, value) {
+	const values = {};
+	content.split(/\r?\n/).forEach((line, index) => {
+		const trimmed = line.trim();
+		if (trimmed === '' || trimmed.startsWith('#') || trimmed.startsWith('//')) {
+			return;
+		}
+		const where = `'${path}' line ${index + 1}`;
+		const match = trimmed.match(pattern);
+		if (!match) {
+			throw new Error(`Error in ${where}: expected ${form}`);
+		}
+		if (match[1] in values) {
+			throw new Error(`Error in ${where}: '${match[1]}' is set twice`);
+		}
+		values[match[1]] = value(match[2], where);
+	});
+	return values;
+}
+
+function dotenvValue(text, where) {
+	if (text.startsWith("'")) {
+		if (!/^'[^']*'(\s+#.*)?$/.test(text)) {
+			throw new Error(`Error in ${where}: unterminated '-quoted value`);
+		}
+		return text.slice(1, text.indexOf("'", 1));
+	}
+	if (text.startsWith('"')) {
+		return quotedString(text, where, JSON.parse);
+	}
+	return text.replace(/\s+#.*$/, '');
+}
+
+function tfvarsValue(text, where) {
+	const value = text.replace(/\s+(#|\/\/).*$/, '');
+	if (value.startsWith('"')) {
+		return quotedString(value, where, hclUnescape);
+	}
+	if (/^-?\d+(\.\d+)?$/.test(value)) {
+		return Number(value);
+	}
+	if (value === 'true' || value === 'false') {
+		return value === 'true';
+	}
+	throw new Error(`Error in ${where}: only single-line string, number and bool values are supported`);
+}
+
+// A "..." string at the start of the text, followed by nothing or a comment
+function quotedString(text, where, unescape) {
+	const match = text.match(/^"((?:[^"\\]|\\.)*)"(\s+(#|\/\/).*)?$/);
+	if (!match) {
+		throw new Error(`Error in ${where}: unterminated "-quoted value`);
+	}
+	try {
+		return unescape(`"${match[1]}"`);
+	} catch {
+		throw new Error(`Error in ${where}: invalid escape in "-quoted value`);
+	}
+}
+
+const HCL_ESCAPES = { n: '\n', r: '\r', t: '\t', '"': '"', '\\': '\\' };
+
+function hclUnescape(quoted) {
+	return quoted.slice(1, -1)
+		.replace(/\\(.)/g, (escape, char) => {
+			if (!(char in HCL_ESCAPES)) {
+				throw new Error(`invalid escape ${escape}`);
+			}
+			return HCL_ESCAPES[char];
+		})
+		.replace(/\$\$\{/g, () => '${')
+		.replace(/%%\{/g, '%{');
+}
+
+function hclString(value) {
+	const escaped = value
+		.replace(/\\/g, '\\\\')
+		.replace(/"/g, '\\"')
+		.replace(/\n/g, '\\n')
+		.replace(/\r/g, '\\r')
+		.replace(/\t/g, '\\t')
+		.replace(/\$\{/g, () => '$${')
+		.replace(/%\{/g, '%%{');
+	return `"${escaped}"`;
+}
@@ -0,0 +1,109 @@
+import { isRuleEntry } from './rules.js';
+import { configFile } from './config.js';
+import { editDistance } from './sopsify.js';
+import { copyEntryPath } from './config-schema.js';
+
+const PREFIX = 'environment:';
+
+// `environments` in .sopsify.yaml put clusters into environments, and
+// `environment:<name>` items of `sopsify` define template definitions for
+// every cluster of one, next to the clusters' own:
+//
+//   environments:
+//     prod: [eu-west, us-east]
+//
+//   sopsify:
+//     - environment:prod:
+//       - template: app-secret.yaml
+//         values:
+//           - key: log-level
+//             value: warn
+//             namespaces: [payments, billing]
+//     - eu-west:
+//       - template: app-secret.yaml
+//         values:
+//           - key: log-level
+//             value: debug
+//             namespaces: [payments]
+//
+// The more specific scope wins: an entry of the cluster replaces the
+// environment's for the namespaces it lists by name, and settings of the
+// cluster's template definition those of the environment's. The template's
+// `namespaces` are those of both. Clusters without an item of their own get
+// one.
+export function expandEnvironments(config) {
+	const environments = config.environments ?? {};
+	const items = config.sopsify.filter(isEnvironmentItem);
+	if (items.length === 0) {
+		return;
+	}
+	config.sopsify = config.sopsify.filter(item => !isEnvironmentItem(item));
+	for (const item of items) {
+		const [reference, templateDefs] = Object.entries(item)[0];
+		const name = reference.slice(PREFIX.length);
+		if (!Object.hasOwn(environments, name)) {
+			const similar = Object.keys(environments).find(candidate => editDistance(name, candidate) <= 2);
+			throw new Error(`Unknown environment '${name}' in 'sopsify' of ${configFile()}${similar ? ` (did you mean '${similar}'?)` : ''}; list its clusters under 'environments'`);
+		}
+		for (const cluster of environments[name]) {
+			let clusterItem = config.sopsify.find(candidate => Object.keys(candidate)[0] === cluster);
+			if (!clusterItem) {
+				clusterItem = { [cluster]: [] };
+				config.sopsify.push(clusterItem);
+			}
+			const own = clusterItem[cluster];
+			for (const templateDef of templateDefs) {
+				const existing = own.find(def => def.template === templateDef.template);
+				if (existing) {
+					mergeTemplateDef(existing, templateDef);
+				} else {
+					own.push({ ...structuredClone(templateDef), ...(templateDef.values ? { values: templateDef.values.map(entry => cloneEntry(entry)) } : {}) });
+				}
+			}
+		}
+	}
+}
+
+function mergeTemplateDef(own, shared) {
+	for (const [field, value] of Object.entries(shared)) {
+		if (field !== 'values' && field !== 'namespaces' && own[field] === undefined) {
+			own[field] = structuredClone(value);
+		}
+	}
+	if (shared.namespaces !== undefined) {
+		own.namespaces = [...new Set([...(own.namespaces ?? []), ...shared.namespaces])];
+	}
+	const values = own.values ?? [];
+	const named = key => values.filter(entry => entry.key === key && !isRuleEntry(entry)).flatMap(entry => entry.namespaces ?? []);
+	const merged = [...values];
+	for (const entry of shared.values ?? []) {
+		// Rules of the cluster come first, so they win as well
+		if (isRuleEntry(entry)) {
+			merged.push(cloneEntry(entry));
+			continue;
+		}
+		const namespaces = (entry.namespaces ?? []).filter(ns => !named(entry.key).includes(ns));
+		if (namespaces.length > 0) {
+			merged.push(cloneEntry(entry, { namespaces }));
+		}
+	}
+	own.values = merged;
+}
+
+// A copy of an entry of the environment's item, still located where that
+// entry is in the config text
+function cloneEntry(entry, fields = {}) {
+	const clone = { ...structuredClone(entry), ...fields };
+	copyEntryPath(entry, clone);
+	return clone;
+}
+
+// The environment a cluster is in, or undefined
+export function clusterEnvironment(config, cluster) {
+	const found = Object.entries(config.environments ?? {}).find(([, clusters]) => clusters.includes(cluster));
+	return found ? found[0] : undefined;
+}
+
+function isEnvironmentItem(item) {
+	return Object.keys(item)[0].startsWith(PREFIX);
+}
@@ -0,0 +1,108 @@
+import { loadConfig, loadTemplates, findTemplate, templatePlaceholders, requiredKeys, unresolvedPolicy, isStrict } from './sopsify.js';
+import { loadNamespaceVars, withNamespaceVars } from './namespace-vars.js';
+import { isRuleEntry, matchRule } from './rules.js';
+import { configFile } from './config.js';
+import { addKeyAliases } from './key-aliases.js';
+import { sourceNames } from './sources/index.js';
+import { isDefaultEntry } from './defaults.js';
+
+// Explains how the placeholders of one template resolve for a namespace: what
+// the template needs, where each value comes from (an entry in .sopsify.yaml,
+// a `match` rule, a secret manager or a namespace variable file) and what is
+// missing, with what a run would do about it. Values and secret managers are
+// never read.
+export async function explain(opts) {
+	if (!opts.template || !opts.namespace || !opts.templates) {
+		throw new Error('explain requires --template, --namespace and --templates <FOLDER>');
+	}
+	const config = await loadConfig();
+	const templates = await loadTemplates(opts.templates, [], { config });
+	const templatePath = findTemplate(templates, opts.template);
+	if (!templatePath) {
+		throw new Error(`Template '${opts.template}' not found in '${opts.templates}'`);
+	}
+	const documents = templates[templatePath];
+	const required = requiredKeys(documents);
+	const needed = [...new Set([...templatePlaceholders(documents), ...required])];
+
+	const clusters = config.sopsify
+		.map(item => Object.entries(item)[0])
+		.filter(([cluster]) => !opts.cluster || cluster === opts.cluster)
+		.map(([cluster, templateDefs]) => [cluster, templateDefs.find(def => def.template === opts.template)])
+		.filter(([, templateDef]) => templateDef);
+	if (clusters.length === 0) {
+		throw new Error(`Template '${opts.template}' is not defined${opts.cluster ? ` for cluster '${opts.cluster}'` : ''} in ${configFile()}`);
+	}
+
+	console.log(`📝 ${templatePath} needs ${needed.length} keys: ${needed.join(', ') || '(none)'}`);
+	const namespaceVars = await loadNamespaceVars();
+
+	for (const [cluster, templateDef] of clusters) {
+		console.log(`\n🌍 ${cluster}/${opts.namespace}`);
+		const values = withNamespaceVars(templateDef.values ?? [], namespaceVars, cluster);
+		const declared = new Set([...(templateDef.namespaces || []), ...values.filter(entry => !isRuleEntry(entry)).flatMap(entry => entry.namespaces)]);
+		if (!declared.has(opts.namespace)) {
+			console.log(`   ⏭️ Not rendered: ${templateDef.scope === 'global' ? `'${opts.template}' is written once for the cluster (scope: global)` : `no entry of '${opts.template}' lists namespace '${opts.namespace}'`}`);
+			continue;
+		}
+
+		// `key_aliases` of the keys the namespace has a value for
+		const resolved = [...new Set(values.map(entry => entry.key))].filter(key => valueSource(values, key, opts.namespace));
+		const aliases = addKeyAliases(Object.fromEntries(resolved.map(key => [key, { [opts.namespace]: true }])), config, cluster);
+
+		const missing = [];
+		for (const key of needed) {
+			const aliasOf = aliases.get(key);
+			const source = valueSource(values, aliasOf ?? key, opts.namespace);
+			const kind = required.has(key) ? ' (required)' : '';
+			if (source) {
+				console.log(`   ✅ ${key}${kind}: ${aliasOf ? `alias of '${aliasOf}', ` : ''}${source}`);
+				continue;
+			}
+			missing.push(key);
+			const elsewhere = values.filter(entry => entry.key === key && !isRuleEntry(entry)).flatMap(entry => entry.namespaces);
+			console.log(`   ❌ ${key}${kind}: missing${elsewhere.length > 0 ? ` (defined for: ${elsewhere.join(', ')})` : ' (not defined for any namespace)'}`);
+		}
+
+		const unused = [...new Set(values.filter(entry => !isRuleEntry(entry) && entry.namespaces.includes(opts.namespace)).map(entry => entry.key))]
+			.filter(key => !needed.includes(key) && !needed.some(name => aliases.get(name) === key));
+		if (unused.length > 0) {
+			console.log(`   ⚠️ Not used by the template: ${unused.join(', ')}`);
+		}
+
+		if (missing.length === 0) {
+			console.log(`   ✅ Rendered with all keys resolved`);
+		} else if (isStrict(config, opts, opts.template)) {
+			console.log(`   ❌ A strict run fails`);
+		} else {
+			const policy = unresolvedPolicy(config, templateDef, cluster, opts.namespace);
+			console.log(policy === 'error' ? `   ❌ The run fails (on_unresolved: error)` : `   ⏭️ Skipped by the run (on_unresolved: ${policy})`);
+		}
+	}
+}
+
+// Where the value of a key for a namespace comes from; an explicit entry wins
+// over rules, and the first matching rule wins
+function valueSource(values, key, namespace) {
+	const explicit = values.find(entry => entry.key === key && !isRuleEntry(entry) && entry.namespaces.includes(namespace));
+	if (explicit) {
+		if (explicit.from) {
+			return `namespace variable file ${explicit.from}`;
+		}
+		return describeEntry(explicit, configFile());
+	}
+	const rule = values.find(entry => entry.key === key && isRuleEntry(entry) && matchRule(entry, namespace));
+	if (!rule) {
+		return undefined;
+	}
+	return describeEntry(rule, isDefaultEntry(rule) ? `defaults in ${configFile()}` : `rule match '${rule.match}'`);
+}
+
+function describeEntry(entry, origin) {
+	const from = entry.value_from !== undefined ? ` from ${sourceNames(entry.value_from).join(' → ')}` : '';
+	const transform = entry.transform !== undefined ? `, transform: ${[].concat(entry.transform).join(', ')}` : '';
+	const owner = entry.owner ? ` — ${entry.owner}` : '';
+	const type = entry.type !== undefined ? `, type: ${entry.type}` : '';
+	const deprecated = entry.deprecated === true ? `, deprecated${entry.replaced_by ? ` (replaced by '${entry.replaced_by}')` : ''}` : '';
+	return `${origin}${from}${transform}${type}${deprecated}${entry.secret === false ? ' [public]' : ''}${owner}`;
+}
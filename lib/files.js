@@ -0,0 +1,56 @@
+import fs from 'fs/promises';
+import { join, sep } from 'path';
+
+// Files below a folder as paths relative to it, e.g. `apps/foo/deploy.yaml`,
+// sorted. Hidden files and folders (staged outputs, temporary plaintext) are
+// skipped; a missing folder has no files.
+export async function filesBelow(dir, filter = () => true) {
+	let entries;
+	try {
+		entries = await fs.readdir(dir, { withFileTypes: true });
+	} catch {
+		return [];
+	}
+	const files = [];
+	for (const entry of entries) {
+		if (entry.name.startsWith('.')) {
+			continue;
+		}
+		if (entry.isDirectory()) {
+			files.push(...(await filesBelow(join(dir, entry.name), filter)).map(file => join(entry.name, file)));
+		} else if (entry.isFile() && filter(entry.name)) {
+			files.push(entry.name);
+		}
+	}
+	return files.sort();
+}
+
+// Whether a relative path matches one of the glob patterns: `*` and `?`
+// match within a path segment, `**/` any number of folders, e.g.
+// `**/README*` or `apps/*/deploy.yaml`
+export function pathMatcher(patterns) {
+	const regexps = [].concat(patterns ?? []).map(pattern => globToRegExp(String(pattern)));
+	return path => regexps.some(regexp => regexp.test(path.split(sep).join('/')));
+}
+
+// Namespace names have no `/`, so the same globs select them
+export function globToRegExp(pattern) {
+	let source = '';
+	for (let i = 0; i < pattern.length; i++) {
+		const char = pattern[i];
+		if (pattern.startsWith('**/', i)) {
+			source += '(?:.*/)?';
+			i += 2;
+		} else if (pattern.startsWith('**', i)) {
+			source += '.*';
+			i += 1;
+		} else if (char === '*') {
+			source += '[^/]*';
+		} else if (char === '?') {
+			source += '[^/]';
+		} else {
+			source += char.replace(/[.+^${}()|[\]\\]/g, '\\$&');
+		}
+	}
+	return new RegExp(`^${source}$`);
+}
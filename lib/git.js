@@ -0,0 +1,25 @@
+import { execFileSync } from 'child_process';
+import { dirname, basename } from 'path';
+
+// Refuses to write plaintext to a path that git would pick up, i.e. inside a
+// work tree and not ignored. Outside of a git repository (or without git)
+// writing is always allowed.
+export function assertPlaintextPathIgnored(path, opts = {}) {
+	if (opts.allowTrackedPlaintext || !isGitVisible(path)) {
+		return;
+	}
+	throw new Error(
+		`Refusing to write plaintext to '${path}': it is inside a git work tree and not ignored. ` +
+		`Add it to .gitignore (e.g. '*.yaml' + '!*.enc.yaml') or pass --allow-tracked-plaintext`
+	);
+}
+
+function isGitVisible(path) {
+	try {
+		execFileSync('git', ['-C', dirname(path), 'check-ignore', '-q', basename(path)], { stdio: 'pipe' });
+		return false;
+	} catch (err) {
+		// 1: not ignored, 128: not a work tree, ENOENT: git not installed
+		return err.status === 1;
+	}
+}
@@ -0,0 +1,33 @@
+import fs from 'fs/promises';
+import { createHash } from 'crypto';
+
+// Writes run results in GitLab's dotenv artifact format (`artifacts:reports:dotenv`).
+// Only non-secret data is exported: output paths, hashes of the encrypted files
+// and the affected clusters/namespaces, and which hooks ran or failed. Values
+// must be single-line, so lists are joined with commas.
+export async function writeGitlabDotenv(path, outputs, hookResults = []) {
+	const unique = list => [...new Set(list)].join(',');
+
+	// One digest over all outputs so downstream jobs can cheaply detect changes
+	const digest = createHash('sha256');
+	for (const output of outputs) {
+		digest.update(`${output.path}:${output.sha256}\n`);
+	}
+
+	const variables = {
+		SOPSIFY_OUTPUT_COUNT: outputs.length,
+		SOPSIFY_OUTPUTS: outputs.map(o => o.path).join(','),
+		SOPSIFY_OUTPUT_HASHES: outputs.map(o => `${o.path}=${o.sha256}`).join(','),
+		SOPSIFY_OUTPUTS_DIGEST: digest.digest('hex'),
+		SOPSIFY_CLUSTERS: unique(outputs.map(o => o.cluster)),
+		SOPSIFY_NAMESPACES: unique(outputs.map(o => o.namespace).filter(ns => ns !== undefined)),
+		SOPSIFY_HOOKS_RUN: hookResults.length,
+		SOPSIFY_HOOKS_FAILED: unique(hookResults.filter(r => r.error).map(r => `${r.cluster}/${r.namespace}:${r.stage}`)),
+	};
+
+	const content = Object.entries(variables)
+		.map(([key, value]) => `${key}=${value}`)
+		.join('\n');
+
+	await fs.writeFile(path, `${content}\n`, 'utf-8');
+}
@@ -0,0 +1,106 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { join, relative, dirname, basename } from 'path';
+import { getBackend } from './backends.js';
+import { assertMissing, groupByValue } from './import-sops.js';
+
+// Converts a helm-secrets repository into sopsify config + one Secret template.
+// Each encrypted values file becomes a namespace: `secrets.<env>.yaml` maps to
+// `<env>`, any other `secrets*.yaml` maps to its parent directory name.
+export async function migrateHelmSecrets(opts) {
+	if (!opts.dir) {
+		throw new Error('migrate-helm-secrets requires --dir');
+	}
+
+	const backend = getBackend({ backend: 'sops' });
+	backend.check();
+
+	const templatePath = join(opts.templatesOut, `${opts.name}.yaml`);
+	if (!opts.force) {
+		await assertMissing(opts.output);
+		await assertMissing(templatePath);
+	}
+
+	// flattened key -> namespace -> value
+	const valuesByKey = {};
+	const files = await findSecretsFiles(opts.dir);
+
+	for (const path of files) {
+		const namespace = environmentOf(opts.dir, path);
+		const values = yaml.load(backend.decrypt(path)) || {};
+		delete values.sops;
+
+		for (const [key, value] of Object.entries(flatten(values))) {
+			(valuesByKey[key] = valuesByKey[key] || {})[namespace] = value;
+		}
+		console.log(`   🔓 Imported: ${relative(opts.dir, path)} → namespace '${namespace}'`);
+	}
+
+	if (files.length === 0) {
+		throw new Error(`No helm-secrets files (secrets*.yaml) found in '${opts.dir}'`);
+	}
+
+	const namespaces = new Set(files.map(path => environmentOf(opts.dir, path)));
+	const template = {
+		apiVersion: 'v1',
+		kind: 'Secret',
+		metadata: { name: opts.name },
+		stringData: {},
+	};
+	const values = [];
+
+	for (const [key, byNs] of Object.entries(valuesByKey)) {
+		template.stringData[key] = `\${${key}}`;
+		values.push(...groupByValue(key, byNs));
+
+		const missing = [...namespaces].filter(ns => !(ns in byNs));
+		if (missing.length > 0) {
+			console.warn(`   ⚠️ Key '${key}' is missing in: ${missing.join(', ')}, add a value by hand`);
+		}
+	}
+
+	await fs.mkdir(opts.templatesOut, { recursive: true });
+	await fs.writeFile(templatePath, yaml.dump(template), 'utf-8');
+	console.log(`   📄 Template written: ${templatePath}`);
+
+	const config = { sopsify: [{ [opts.cluster]: [{ template: basename(templatePath), values }] }] };
+	await fs.writeFile(opts.output, yaml.dump(config), 'utf-8');
+	console.log(`\n✅ Config written: ${opts.output}`);
+}
+
+async function findSecretsFiles(dir) {
+	const found = [];
+	for (const entry of await fs.readdir(dir, { withFileTypes: true })) {
+		const path = join(dir, entry.name);
+		if (entry.isDirectory() && entry.name !== '.git') {
+			found.push(...await findSecretsFiles(path));
+		} else if (entry.isFile() && /^secrets(\..+)?\.ya?ml$/.test(entry.name)) {
+			found.push(path);
+		}
+	}
+	return found;
+}
+
+function environmentOf(root, path) {
+	const match = basename(path).match(/^secrets\.(.+)\.ya?ml$/);
+	if (match) {
+		return match[1];
+	}
+	const dir = dirname(relative(root, path));
+	return dir === '.' ? 'default' : basename(dir);
+}
+
+// Nested Helm values become dash-joined keys that are valid placeholder names,
+// e.g. `postgresql.auth.password` → `postgresql-auth-password`
+function flatten(value, prefix = '') {
+	if (value === null || typeof value !== 'object') {
+		return { [prefix]: String(value) };
+	}
+
+	const result = {};
+	for (const [k, v] of Object.entries(value)) {
+		const key = (prefix ? `${prefix}-${k}` : k).replace(/[^a-zA-Z0-9_-]/g, '-');
+		Object.assign(result, flatten(v, /^[a-zA-Z_]/.test(key) ? key : `_${key}`));
+	}
+	return result;
+}
@@ -0,0 +1,114 @@
+import { spawnSync } from 'child_process';
+import { configFile } from './config.js';
+
+const STAGES = ['pre_render', 'post_encrypt'];
+
+// `hooks` in .sopsify.yaml run shell commands for a namespace, e.g. to run
+// kubeseal, notify a chat or trigger a deployment. Keys are a namespace or
+// `<cluster>/<namespace>`; per stage the cluster-specific entry wins. Each
+// stage is a command or a list of commands, run with `sh -c` in the project
+// root and SOPSIFY_CLUSTER, SOPSIFY_NAMESPACE and SOPSIFY_HOOK set:
+//
+// - `pre_render` runs before the first template of the namespace is rendered;
+//   a failure aborts the cluster, so nothing of it is written
+// - `post_encrypt` runs once the cluster's outputs are in place, for
+//   namespaces with new or changed outputs (listed in SOPSIFY_OUTPUTS); a
+//   failure fails the run at the end, the outputs stay
+export function createHooks(config) {
+	const settings = config.hooks || {};
+	if (typeof settings !== 'object' || Array.isArray(settings)) {
+		throw new Error(`'hooks' in ${configFile()} must be a map of namespaces to hooks`);
+	}
+	for (const [key, hooks] of Object.entries(settings)) {
+		if (!hooks || typeof hooks !== 'object' || Array.isArray(hooks)) {
+			throw new Error(`'hooks.${key}' in ${configFile()} must be a map of ${STAGES.join(' / ')} to commands`);
+		}
+		for (const [stage, commands] of Object.entries(hooks)) {
+			if (!STAGES.includes(stage)) {
+				throw new Error(`Unknown hook '${stage}' in 'hooks.${key}', expected one of: ${STAGES.join(', ')}`);
+			}
+			if (![].concat(commands).every(command => typeof command === 'string' && command.trim() !== '')) {
+				throw new Error(`'hooks.${key}.${stage}' in ${configFile()} must be a command or a list of commands`);
+			}
+		}
+	}
+
+	const results = [];
+	const rendered = new Set();
+
+	function runStage(stage, cluster, namespace, env = {}) {
+		const key = `${cluster}/${namespace}`;
+		const commands = [].concat((settings[key] || {})[stage] ?? (settings[namespace] || {})[stage] ?? []);
+		for (const command of commands) {
+			const started = Date.now();
+			const result = spawnSync('sh', ['-c', command], {
+				stdio: 'pipe',
+				encoding: 'utf-8',
+				env: { ...process.env, SOPSIFY_CLUSTER: cluster, SOPSIFY_NAMESPACE: namespace, SOPSIFY_HOOK: stage, ...env },
+			});
+			const error = hookError(result);
+			results.push({ cluster, namespace, stage, command, error, durationMs: Date.now() - started });
+			if (error) {
+				console.warn(`   ❌ Hook ${stage} failed: ${key} (${command}): ${error}`);
+				return error;
+			}
+			console.log(`   🪝 Hook ${stage}: ${key} (${command})`);
+		}
+		return undefined;
+	}
+
+	return {
+		results,
+
+		preRender(cluster, namespace) {
+			const key = `${cluster}/${namespace}`;
+			if (rendered.has(key)) {
+				return;
+			}
+			rendered.add(key);
+			const error = runStage('pre_render', cluster, namespace);
+			if (error) {
+				throw new Error(`Hook pre_render of '${key}' failed: ${error}`);
+			}
+		},
+
+		postEncrypt(cluster, outputs) {
+			const byNamespace = new Map();
+			// Outputs of global templates belong to no namespace
+			for (const output of outputs.filter(o => o.cluster === cluster && o.namespace !== undefined && !o.unchanged)) {
+				byNamespace.set(output.namespace, [...(byNamespace.get(output.namespace) || []), output.path]);
+			}
+			for (const [namespace, paths] of byNamespace) {
+				runStage('post_encrypt', cluster, namespace, { SOPSIFY_OUTPUTS: paths.join(',') });
+			}
+		},
+	};
+}
+
+function hookError(result) {
+	if (result.error) {
+		return result.error.message;
+	}
+	if (result.status === 0) {
+		return undefined;
+	}
+	const stderr = (result.stderr || '').trim().split('\n').pop();
+	const reason = result.status === null ? `killed by ${result.signal}` : `exit code ${result.status}`;
+	return stderr ? `${reason}: ${stderr}` : reason;
+}
+
+export function reportHooks(results) {
+	if (results.length === 0) {
+		return;
+	}
+	console.log('\n🪝 Hook results');
+	for (const result of results) {
+		const line = `${result.cluster}/${result.namespace} ${result.stage}: ${result.command} (${result.durationMs} ms)`;
+		console.log(result.error ? `   ❌ ${line}: ${result.error}` : `   ✅ ${line}`);
+	}
+
+	const failedCount = results.filter(r => r.error).length;
+	if (failedCount > 0) {
+		throw new Error(`${failedCount} hooks failed`);
+	}
+}
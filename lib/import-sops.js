@@ -0,0 +1,132 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { join } from 'path';
+import { getBackend } from './backends.js';
+
+// Bootstraps a `.sopsify.yaml` and parameterized templates from an existing
+// tree of per-namespace sops files laid out as `<dir>/<namespace>/<file>.yaml`.
+export async function importSops(opts) {
+	if (!opts.dir) {
+		throw new Error('import-sops requires --dir');
+	}
+
+	const backend = getBackend({ backend: 'sops' });
+	backend.check();
+
+	const configPath = opts.output;
+	const templatesDir = opts.templatesOut;
+	if (!opts.force) {
+		await assertMissing(configPath);
+	}
+
+	// template file name -> namespace -> decrypted Secret
+	const secretsByTemplate = {};
+	const namespaces = (await fs.readdir(opts.dir, { withFileTypes: true })).filter(e => e.isDirectory());
+
+	for (const nsEntry of namespaces) {
+		const nsDir = join(opts.dir, nsEntry.name);
+		const files = (await fs.readdir(nsDir)).filter(f => /\.ya?ml$/.test(f));
+
+		for (const file of files) {
+			const path = join(nsDir, file);
+			let secret;
+			try {
+				secret = yaml.load(backend.decrypt(path));
+			} catch (err) {
+				console.warn(`   ⚠️ Skipping '${path}': could not be decrypted (${err.message.split('\n')[0]})`);
+				continue;
+			}
+			if (!secret || !secret.kind || secret.kind.toLowerCase() !== 'secret') {
+				console.warn(`   ⚠️ Skipping '${path}': not of kind 'Secret'`);
+				continue;
+			}
+
+			const templateName = file.replace(/\.enc\.ya?ml$/, '.yaml');
+			(secretsByTemplate[templateName] = secretsByTemplate[templateName] || {})[nsEntry.name] = secret;
+			console.log(`   🔓 Imported: ${nsEntry.name}/${file}`);
+		}
+	}
+
+	if (Object.keys(secretsByTemplate).length === 0) {
+		throw new Error(`No sops encrypted Secrets found in '${opts.dir}'`);
+	}
+
+	await fs.mkdir(templatesDir, { recursive: true });
+	const templateDefs = [];
+
+	for (const [templateName, byNamespace] of Object.entries(secretsByTemplate)) {
+		const { template, values } = parameterize(templateName, byNamespace);
+		const templatePath = join(templatesDir, templateName);
+		if (!opts.force) {
+			await assertMissing(templatePath);
+		}
+		await fs.writeFile(templatePath, yaml.dump(template), 'utf-8');
+		console.log(`   📄 Template written: ${templatePath}`);
+		templateDefs.push({ template: templateName, values });
+	}
+
+	const config = { sopsify: [{ [opts.cluster]: templateDefs }] };
+	await fs.writeFile(configPath, yaml.dump(config), 'utf-8');
+	console.log(`\n✅ Config written: ${configPath}`);
+}
+
+// Turns the per-namespace variants of one Secret into a single template with
+// placeholders, grouping namespaces that share the same value into one entry.
+function parameterize(templateName, byNamespace) {
+	const [firstNs, first] = Object.entries(byNamespace)[0];
+	const section = first.stringData ? 'stringData' : 'data';
+
+	const template = JSON.parse(JSON.stringify(first));
+	delete template.sops;
+	if (template.metadata) {
+		delete template.metadata.namespace;
+	}
+
+	const values = [];
+	const dataKeys = new Set(Object.values(byNamespace).flatMap(s => Object.keys(s[section] || {})));
+
+	for (const dataKey of dataKeys) {
+		const key = `${first.metadata.name}-${dataKey}`.replace(/^[^a-zA-Z_]/, '_').replace(/[^a-zA-Z0-9_-]/g, '-');
+		template[section][dataKey] = `\${${key}}`;
+
+		const byNs = {};
+		for (const [ns, secret] of Object.entries(byNamespace)) {
+			if (!secret[section] || !(dataKey in secret[section])) {
+				console.warn(`   ⚠️ '${templateName}': key '${dataKey}' is missing in namespace '${ns}', add a value by hand`);
+				continue;
+			}
+			byNs[ns] = secret[section][dataKey];
+		}
+		values.push(...groupByValue(key, byNs));
+	}
+
+	for (const [ns, secret] of Object.entries(byNamespace)) {
+		if (ns !== firstNs && !secret[section]) {
+			console.warn(`   ⚠️ '${templateName}': namespace '${ns}' has no '${section}', template is based on '${firstNs}'`);
+		}
+	}
+
+	return { template, values };
+}
+
+// Builds `values` entries for one key, one entry per distinct value
+export function groupByValue(key, valueByNamespace) {
+	const nsByValue = new Map();
+	for (const [ns, value] of Object.entries(valueByNamespace)) {
+		if (!nsByValue.has(value)) {
+			nsByValue.set(value, []);
+		}
+		nsByValue.get(value).push(ns);
+	}
+
+	return [...nsByValue].map(([value, namespaces]) => ({ key, value, namespaces }));
+}
+
+export async function assertMissing(path) {
+	try {
+		await fs.access(path);
+	} catch {
+		return;
+	}
+	throw new Error(`'${path}' already exists, use --force to overwrite`);
+}
@@ -0,0 +1,31 @@
+// The public API for embedding sopsify's rendering pipeline in other tools;
+// the CLI in bin/sopsify.js is a thin wrapper around it. Everything else in
+// lib/ is internal and may change without notice.
+//
+//   const config = await loadConfig();
+//   const templates = await loadTemplates('secrets');
+//   const documents = templates['secrets/db.yaml'];
+//   const templateDef = clusterTemplates(config, 'production').find(def => def.template === 'db.yaml');
+//   const values = await resolveValueSources(templateDef.values);
+//   const plan = namespacePlan(templateDef, documents, values, 'production', config);
+//   const { documents: rendered } = renderTemplate(documents, namespaceValues(plan.keyNamespaceValueMap, 'payments'), 'payments');
+//
+// `run(opts)` is a whole run as `sopsify` does it; `opts` are the CLI options
+// in camelCase (`templates`, `skipUnchanged`, ...) plus `encryptor`, an object
+// implementing the backend interface described in backends.js.
+
+export {
+	run,
+	loadConfig,
+	loadTemplates,
+	clusterTemplates,
+	namespacePlan,
+	namespaceValues,
+	renderTemplate,
+	dumpDocuments,
+	templatePlaceholders,
+	requiredKeys,
+} from './sopsify.js';
+export { getBackend } from './backends.js';
+export { resolveValueSources } from './sources/index.js';
+export { configFile, useProject } from './config.js';
@@ -0,0 +1,55 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { join, dirname } from 'path';
+
+// `extends: base.yaml` makes a template inherit a base document. The
+// template's own fields are applied to the base as a JSON merge patch
+// (RFC 7386): objects are merged recursively, `null` removes a field and
+// anything else (including lists) replaces it. Bases are resolved relative to
+// the extending template and may extend further bases; `read` reads a
+// template, also from an archive.
+export async function resolveExtends(template, filePath, chain = [], read = path => fs.readFile(path, 'utf-8')) {
+	if (!template || typeof template !== 'object' || template.extends === undefined) {
+		return template;
+	}
+
+	const { extends: base, ...patch } = template;
+	if (typeof base !== 'string' || base === '') {
+		throw new Error(`Error in '${filePath}': 'extends' must be the file name of a base template`);
+	}
+
+	const basePath = join(dirname(filePath), base);
+	const seen = [...chain, filePath];
+	if (seen.includes(basePath)) {
+		throw new Error(`Error in '${filePath}': circular 'extends': ${[...seen, basePath].join(' -> ')}`);
+	}
+
+	let parsed;
+	try {
+		parsed = yaml.load(await read(basePath));
+	} catch (err) {
+		throw new Error(`Error in '${filePath}': cannot read base template '${basePath}': ${err.message}`);
+	}
+
+	return mergePatch(await resolveExtends(parsed, basePath, seen, read), patch);
+}
+
+export function mergePatch(target, patch) {
+	if (!isObject(patch)) {
+		return patch;
+	}
+
+	const result = isObject(target) ? { ...target } : {};
+	for (const [key, value] of Object.entries(patch)) {
+		if (value === null) {
+			delete result[key];
+		} else {
+			result[key] = mergePatch(result[key], value);
+		}
+	}
+	return result;
+}
+
+function isObject(value) {
+	return value !== null && typeof value === 'object' && !Array.isArray(value);
+}
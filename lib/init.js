@@ -0,0 +1,193 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { existsSync } from 'fs';
+import { join } from 'path';
+import { createInterface } from 'readline/promises';
+import { validateNamespaceName, validatePathSegment } from './sopsify.js';
+import { assertMissing } from './import-sops.js';
+
+const TEMPLATE_NAME = 'app-secret.yaml';
+const SOPS_CONFIG = '.sops.yaml';
+const GITIGNORE = '.gitignore';
+// Only the encrypted outputs are committed
+const GITIGNORE_LINES = ['clusters/**/*.yaml', '!clusters/**/*.enc.yaml'];
+
+const TEMPLATE = `# Example template: every \${key} is replaced with the value .sopsify.yaml
+# defines for the namespace being rendered
+apiVersion: v1
+kind: Secret
+metadata:
+  name: app-secret
+type: Opaque
+stringData:
+  api-token: \${api-token}
+  log-level: \${log-level}
+`;
+
+// Scaffolds a project: a starter `.sopsify.yaml` for one cluster, an example
+// template using it, the cluster's output folder, `.gitignore` rules keeping
+// plaintext outputs out of git and, on request, a `.sops.yaml` with
+// placeholders for the recipients. Options not given on the command line are asked for when run
+// in a terminal. Existing files are never overwritten without `force`.
+export async function init(opts) {
+	const answers = await ask(opts);
+	validatePathSegment(answers.cluster, 'Cluster');
+	answers.namespaces.forEach(namespace => validateNamespaceName(namespace));
+
+	const templatePath = join(opts.templatesOut, TEMPLATE_NAME);
+	const writeSopsConfig = answers.sopsConfig && (opts.force || !existsSync(SOPS_CONFIG));
+	if (!opts.force) {
+		await assertMissing(opts.output);
+		await assertMissing(templatePath);
+	}
+
+	await fs.writeFile(opts.output, configText(answers.cluster, answers.namespaces), 'utf-8');
+	console.log(`   📝 Config written: ${opts.output}`);
+	await fs.mkdir(opts.templatesOut, { recursive: true });
+	await fs.writeFile(templatePath, TEMPLATE, 'utf-8');
+	console.log(`   📄 Template written: ${templatePath}`);
+	// Runs only write into existing cluster folders
+	await fs.mkdir(join('clusters', answers.cluster), { recursive: true });
+	await writeGitignore();
+	if (writeSopsConfig) {
+		await fs.writeFile(SOPS_CONFIG, sopsConfigText(answers.cluster, opts), 'utf-8');
+		console.log(`   🔑 sops config written: ${SOPS_CONFIG}`);
+	} else if (answers.sopsConfig) {
+		console.log(`   ⏭️ Keeping the existing ${SOPS_CONFIG}`);
+	}
+
+	const steps = [
+		`Replace the example values in ${opts.output}`,
+		...(writeSopsConfig && opts.age.length === 0 && opts.kms.length === 0 ? [`Fill in the recipients of ${SOPS_CONFIG}`] : []),
+		`Run \`sopsify -t ${opts.templatesOut}\``,
+	];
+	console.log(`\n✅ Project initialized for '${answers.cluster}' (${answers.namespaces.join(', ')}). Next steps:`);
+	steps.forEach((step, index) => console.log(`   ${index + 1}. ${step}`));
+}
+
+// Appends the rules an existing .gitignore lacks. The negation only works
+// after the rule it excepts from, so both are appended when the first is
+// missing.
+async function writeGitignore() {
+	let text = '';
+	try {
+		text = await fs.readFile(GITIGNORE, 'utf-8');
+	} catch {
+		// no .gitignore yet
+	}
+	const present = new Set(text.split(/\r?\n/).map(line => line.trim()));
+	const lines = present.has(GITIGNORE_LINES[0]) ? GITIGNORE_LINES.filter(line => !present.has(line)) : GITIGNORE_LINES;
+	if (lines.length === 0) {
+		console.log(`   ⏭️ Keeping the existing ${GITIGNORE}`);
+		return;
+	}
+	const separator = text === '' ? '' : `${text.endsWith('\n') ? '' : '\n'}\n`;
+	await fs.writeFile(GITIGNORE, `${text}${separator}# Plaintext outputs and temporary files of sopsify\n${lines.join('\n')}\n`, 'utf-8');
+	console.log(`   🙈 Git ignore rules ${text === '' ? 'written' : 'added'}: ${GITIGNORE}`);
+}
+
+// Options given on the command line win; the others are asked for in a
+// terminal, or take their defaults
+async function ask(opts) {
+	const sopsConfig = opts.sopsConfig || opts.age.length > 0 || opts.kms.length > 0;
+	const answers = {
+		cluster: opts.cluster,
+		namespaces: opts.namespace.length > 0 ? opts.namespace : undefined,
+		sopsConfig: sopsConfig || undefined,
+	};
+	if (!process.stdin.isTTY) {
+		return { cluster: answers.cluster ?? 'default', namespaces: answers.namespaces ?? ['default'], sopsConfig };
+	}
+
+	const prompt = createInterface({ input: process.stdin, output: process.stdout });
+	try {
+		if (answers.cluster === undefined) {
+			answers.cluster = (await prompt.question('Cluster name [default]: ')).trim() || 'default';
+		}
+		if (answers.namespaces === undefined) {
+			const namespaces = (await prompt.question('Namespaces, comma separated [default]: ')).split(',').map(namespace => namespace.trim()).filter(Boolean);
+			answers.namespaces = namespaces.length > 0 ? namespaces : ['default'];
+		}
+		if (answers.sopsConfig === undefined) {
+			answers.sopsConfig = !existsSync(SOPS_CONFIG)
+				&& !/^n/i.test((await prompt.question(`Write a ${SOPS_CONFIG} with placeholders for the recipients? [Y/n]: `)).trim());
+		}
+	} finally {
+		prompt.close();
+	}
+	return answers;
+}
+
+// Written as text to keep the comments; checked by parsing it again
+function configText(cluster, namespaces) {
+	const list = `[${namespaces.map(scalar).join(', ')}]`;
+	const text = [
+		'# sopsify config: the templates of every cluster and the values of their',
+		'# keys per namespace',
+		'sopsify:',
+		`  - ${scalar(cluster)}:`,
+		`    - template: "${TEMPLATE_NAME}"`,
+		'      values:',
+		...namespaces.flatMap(namespace => [
+			'        - key: api-token',
+			`          value: ${scalar(`change-me-${namespace}`)}`,
+			`          namespaces: [${scalar(namespace)}]`,
+		]),
+		'        # Not sensitive: may also be used in ConfigMaps',
+		'        - key: log-level',
+		'          value: info',
+		'          secret: false',
+		`          namespaces: ${list}`,
+		'',
+	].join('\n');
+
+	const expected = {
+		sopsify: [{
+			[cluster]: [{
+				template: TEMPLATE_NAME,
+				values: [
+					...namespaces.map(namespace => ({ key: 'api-token', value: `change-me-${namespace}`, namespaces: [namespace] })),
+					{ key: 'log-level', value: 'info', secret: false, namespaces },
+				],
+			}],
+		}],
+	};
+	if (JSON.stringify(yaml.load(text)) !== JSON.stringify(expected)) {
+		throw new Error('The generated config does not parse as expected; please report this');
+	}
+	return text;
+}
+
+// One creation rule for the outputs of the cluster, with the recipients given
+// or placeholders for them
+function sopsConfigText(cluster, opts) {
+	const recipients = opts.age.length > 0 || opts.kms.length > 0
+		? [
+			...(opts.age.length > 0 ? [`    age: ${JSON.stringify(opts.age.join(','))}`] : []),
+			...(opts.kms.length > 0 ? [`    kms: ${JSON.stringify(opts.kms.join(','))}`] : []),
+		]
+		: [
+			'    # Replace with your age public keys (age-keygen -y <key file>), comma separated,',
+			'    # or use kms/pgp recipients instead',
+			'    age: age1replace-me',
+			'    # kms: arn:aws:kms:<region>:<account>:key/<key id>',
+		];
+	return [
+		`# sops creation rules for the outputs of sopsify, see`,
+		'# https://github.com/getsops/sops#using-sops-yaml-conf-to-select-kms-pgp-and-age-for-new-files',
+		'creation_rules:',
+		`  - path_regex: ${JSON.stringify(`clusters/${escapeRegExp(cluster)}/.*\\.enc\\.yaml$`)}`,
+		`    encrypted_regex: "^(data|stringData)$"`,
+		...recipients,
+		'',
+	].join('\n');
+}
+
+// Plain when that can't be mistaken for anything but the string
+function scalar(text) {
+	return /^[a-z][a-z0-9_-]*$/i.test(text) && !/^(true|false|yes|no|on|off|null|y|n)$/i.test(text) ? text : JSON.stringify(text);
+}
+
+function escapeRegExp(text) {
+	return text.replace(/[.*+?^${}()|[\]\\]/g, '\\$&');
+}
@@ -0,0 +1,82 @@
+import { expandBuiltins, KEY_NAME } from './sopsify.js';
+import { configFile } from './config.js';
+
+const CASES = {
+	'upper-snake': name => name.replace(/-/g, '_').toUpperCase(),
+	'lower-snake': name => name.replace(/-/g, '_').toLowerCase(),
+};
+
+// `key_aliases` in .sopsify.yaml make every resolved key available to the
+// templates under additional names, for templates written for another tool
+// that expects other placeholder names:
+//
+//   key_aliases:
+//     - prefix: SOPSIFY_
+//       case: upper-snake          # db-password -> ${SOPSIFY_DB_PASSWORD}
+//     - suffix: '_${namespace}'    # -> ${db-password_payments}
+//       namespaces: [payments, production/billing]
+//
+// `prefix` and `suffix` may use ${cluster} and ${namespace}; `case` is applied
+// to the whole alias. An alias without `namespaces` applies to every
+// namespace, otherwise to those listed by name or as `<cluster>/<namespace>`.
+export function keyAliasRules(config) {
+	const rules = config.key_aliases ?? [];
+	if (!Array.isArray(rules)) {
+		throw new Error(`'key_aliases' in ${configFile()} must be a list of aliases with a 'prefix' and/or 'suffix'`);
+	}
+	return rules.map((rule, index) => {
+		const where = `key alias ${index + 1} in ${configFile()}`;
+		if (!rule || typeof rule !== 'object' || Array.isArray(rule)) {
+			throw new Error(`The ${where} must be a map with a 'prefix' and/or 'suffix'`);
+		}
+		const unknown = Object.keys(rule).filter(name => !['prefix', 'suffix', 'case', 'namespaces'].includes(name));
+		if (unknown.length > 0) {
+			throw new Error(`Unknown setting '${unknown[0]}' in ${where}, expected prefix, suffix, case or namespaces`);
+		}
+		if (!['prefix', 'suffix'].some(name => typeof rule[name] === 'string' && rule[name] !== '') || ['prefix', 'suffix'].some(name => rule[name] !== undefined && typeof rule[name] !== 'string')) {
+			throw new Error(`The ${where} needs a 'prefix' and/or 'suffix' string`);
+		}
+		if (rule.case !== undefined && !CASES[rule.case]) {
+			throw new Error(`Unknown case '${rule.case}' in ${where}, expected one of: ${Object.keys(CASES).join(', ')}`);
+		}
+		if (rule.namespaces !== undefined && !(Array.isArray(rule.namespaces) && rule.namespaces.every(ns => typeof ns === 'string'))) {
+			throw new Error(`'namespaces' of ${where} must be a list of namespaces or <cluster>/<namespace>`);
+		}
+		return { ...rule, where };
+	});
+}
+
+// Adds the aliases of every key to a plan's key -> namespace -> value map and
+// returns them as alias -> key. An alias may not be a key of its own, nor the
+// alias of two keys.
+export function addKeyAliases(keyNamespaceValueMap, config, clusterName) {
+	const aliases = new Map();
+	const rules = keyAliasRules(config);
+	const keys = Object.keys(keyNamespaceValueMap);
+	for (const key of keys) {
+		for (const [ns, value] of Object.entries(keyNamespaceValueMap[key])) {
+			for (const rule of rules.filter(candidate => !candidate.namespaces || candidate.namespaces.some(name => name === ns || name === `${clusterName}/${ns}`))) {
+				const alias = aliasName(rule, key, clusterName, ns);
+				if (keys.includes(alias)) {
+					throw new Error(`Alias '${alias}' of key '${key}' (${rule.where}) is also a key of its own in '${clusterName}/${ns}'`);
+				}
+				if (aliases.has(alias) && aliases.get(alias) !== key) {
+					throw new Error(`Keys '${aliases.get(alias)}' and '${key}' have the same alias '${alias}' (${rule.where})`);
+				}
+				aliases.set(alias, key);
+				(keyNamespaceValueMap[alias] = keyNamespaceValueMap[alias] || {})[ns] = value;
+			}
+		}
+	}
+	return aliases;
+}
+
+function aliasName(rule, key, cluster, namespace) {
+	const builtins = { cluster, namespace };
+	const name = `${expandBuiltins(rule.prefix ?? '', builtins)}${key}${expandBuiltins(rule.suffix ?? '', builtins)}`;
+	const alias = rule.case ? CASES[rule.case](name) : name;
+	if (!KEY_NAME.test(alias)) {
+		throw new Error(`Alias '${alias}' of key '${key}' (${rule.where}) is not a valid key name`);
+	}
+	return alias;
+}
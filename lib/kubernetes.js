@@ -0,0 +1,38 @@
+import { execFileSync } from 'child_process';
+
+// Thin wrapper around kubectl, so sopsify uses the same kubeconfig, contexts
+// and auth plugins as the operator.
+export function kubectl(args, opts = {}, input) {
+	const fullArgs = opts.kubeContext ? ['--context', opts.kubeContext, ...args] : args;
+	try {
+		return execFileSync('kubectl', fullArgs, { input, stdio: ['pipe', 'pipe', 'pipe'], encoding: 'utf-8' });
+	} catch (err) {
+		if (err.code === 'ENOENT') {
+			throw new Error('kubectl is not installed or not in PATH');
+		}
+		const stderr = err.stderr ? err.stderr.toString().trim() : err.message;
+		throw new Error(`\`kubectl ${args.slice(0, 2).join(' ')}\` failed: ${stderr}`);
+	}
+}
+
+export function listClusterNamespaces(opts = {}) {
+	const args = ['get', 'namespaces', '-o', 'json'];
+	if (opts.selector) {
+		args.push('--selector', opts.selector);
+	}
+
+	const list = JSON.parse(kubectl(args, opts));
+	return new Set(list.items.map(item => item.metadata.name));
+}
+
+// Runs a server-side dry-run apply, so admission webhooks and schema
+// validation run without persisting anything. Returns the error message on
+// failure instead of throwing, so callers can report per namespace.
+export function serverDryRun(manifest, namespace, opts = {}) {
+	try {
+		kubectl(['apply', '--dry-run=server', '--namespace', namespace, '-f', '-'], opts, manifest);
+		return undefined;
+	} catch (err) {
+		return err.message;
+	}
+}
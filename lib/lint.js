@@ -0,0 +1,320 @@
+import fs from 'fs/promises';
+import { loadTemplates, findTemplate, templatePlaceholders, requiredKeys, validateNamespaceName, validatePathSegment, knownNamespaces, assertKnownNamespaces, expandConfig, KEY_NAME } from './sopsify.js';
+import { loadNamespaceVars, withNamespaceVars } from './namespace-vars.js';
+import { isRuleEntry, matchRule } from './rules.js';
+import { keyType } from './value-types.js';
+import { configFile } from './config.js';
+import { addKeyAliases } from './key-aliases.js';
+import { checkConfig } from './config-schema.js';
+import { valueSources } from './sources/index.js';
+import { defaultEntries, isDefaultEntry } from './defaults.js';
+import { deprecationWarnings } from './deprecation.js';
+import { templateGroups, groupEngine } from './template-groups.js';
+
+const PLACEHOLDER = /^\$\{[a-zA-Z_][a-zA-Z0-9_-]*\}$/;
+const SECTIONS = ['data', 'stringData'];
+
+// Checks .sopsify.yaml and, with `-t`, the templates for the mistakes a run
+// would only hit halfway, or not at all: malformed config entries, template
+// values that look like placeholders but are not substituted, and keys no
+// namespace has a value for. Like doctor, every problem is reported at once;
+// with `denyDeprecated`, templates using deprecated keys are errors.
+// sops is never called, secret managers are not asked and nothing is written.
+export async function lint(opts) {
+	const problems = [];
+	const error = (where, message) => problems.push({ level: 'error', where, message });
+	const warn = (where, message) => problems.push({ level: 'warn', where, message });
+
+	// Schema problems are reported one by one; the config is only linted further without them
+	let config;
+	try {
+		const text = await fs.readFile(configFile(), 'utf-8');
+		const checked = checkConfig(text, configFile());
+		checked.problems.forEach(({ where, message }) => error(where, message));
+		if (checked.problems.length === 0) {
+			(await expandConfig(checked.config, text)).forEach(duplicate => error(configFile(), duplicate));
+			config = checked.config;
+		}
+	} catch (err) {
+		error(configFile(), err.message);
+	}
+	let namespaceVars = [];
+	try {
+		namespaceVars = await loadNamespaceVars();
+	} catch (err) {
+		error('namespaces/', err.message);
+	}
+	if (config) {
+		lintConfig(config, error, warn);
+	}
+
+	const folders = [].concat(opts.templates ?? []);
+	if (config && folders.length > 0) {
+		let templates;
+		try {
+			templates = await loadTemplates(folders, [], { config });
+		} catch (err) {
+			error(folders.join(', '), err.message);
+		}
+		if (templates) {
+			lintTemplates(templates, error, warn);
+			lintKeys(config, templates, namespaceVars, error, opts.denyDeprecated ? error : warn, warn);
+		}
+	}
+
+	console.log(`\n🧹 sopsify lint${folders.length > 0 ? '' : ' (config only, pass -t <FOLDER> to lint the templates)'}\n`);
+	for (const { level, where, message } of problems) {
+		console.log(`   ${level === 'error' ? '❌' : '⚠️'} ${where}: ${message}`);
+	}
+	const errors = problems.filter(problem => problem.level === 'error').length;
+	const warnings = problems.length - errors;
+	if (errors > 0) {
+		throw new Error(`${errors} problems found (${warnings} warnings)`);
+	}
+	console.log(`${problems.length > 0 ? '\n' : ''}✅ No problems found (${warnings} warnings)`);
+}
+
+function lintConfig(config, error, warn) {
+	// Every template definition gets the defaults; they are linted once
+	lintEntries(defaultEntries(config), config, undefined, `defaults in ${configFile()}`, error, warn);
+	// Template groups decide the file type (`engine`) of their templates
+	let engineOf;
+	try {
+		templateGroups(config);
+		engineOf = name => groupEngine(config, name);
+	} catch (err) {
+		error(configFile(), err.message);
+	}
+	for (const clusterItem of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		const check = (where, fn) => {
+			try {
+				fn();
+			} catch (err) {
+				error(where, err.message);
+			}
+		};
+		check(`cluster '${cluster}'`, () => validatePathSegment(cluster, 'Cluster'));
+		if (!Array.isArray(templateDefs)) {
+			error(`cluster '${cluster}'`, 'must be a list of template definitions');
+			continue;
+		}
+
+		let known;
+		check(configFile(), () => {
+			known = knownNamespaces(config, cluster);
+		});
+		const seen = new Set();
+		templateDefs.forEach((templateDef, index) => {
+			const where = `${cluster}/${templateDef && typeof templateDef.template === 'string' ? templateDef.template : `template ${index + 1}`}`;
+			if (!templateDef || typeof templateDef !== 'object' || typeof templateDef.template !== 'string') {
+				error(where, "every template definition needs a 'template' naming a .yaml/.yml file");
+				return;
+			}
+			const engine = engineOf ? engineOf(templateDef.template) : undefined;
+			if (engine && !engine.extension.test(templateDef.template)) {
+				error(where, `every template definition needs a 'template' naming a ${engine.description} file`);
+				return;
+			}
+			if (seen.has(templateDef.template)) {
+				error(where, 'the template is defined twice for the cluster');
+			}
+			seen.add(templateDef.template);
+
+			const namespaces = templateDef.namespaces ?? [];
+			if (!Array.isArray(namespaces) || !namespaces.every(ns => typeof ns === 'string')) {
+				error(where, "'namespaces' must be a list of namespace names");
+			} else {
+				lintNamespaces(namespaces, config, known, where, "'namespaces'", error);
+			}
+			if (templateDef.values !== undefined && !Array.isArray(templateDef.values)) {
+				error(where, "'values' must be a list of key entries");
+				return;
+			}
+			lintEntries(templateDef.values ?? [], config, known, where, error, warn);
+		});
+	}
+}
+
+function lintEntries(values, config, known, where, error, warn) {
+	for (const entry of values.filter(entry => !isDefaultEntry(entry))) {
+		if (!entry || typeof entry !== 'object' || Array.isArray(entry)) {
+			error(where, 'every value entry must be a map with a key');
+			continue;
+		}
+		const key = `key '${entry.key}'`;
+		if (typeof entry.key !== 'string' || !KEY_NAME.test(entry.key)) {
+			error(where, `${key} is not a valid key name (letters, digits, '_' and '-', not starting with a digit or '-')`);
+		}
+		if (entry.replaced_by !== undefined && (!KEY_NAME.test(entry.replaced_by) || entry.replaced_by === entry.key)) {
+			error(where, `${key} is replaced_by '${entry.replaced_by}', which is not a valid key name other than its own`);
+		}
+		if (entry.value === undefined && entry.value_from === undefined) {
+			error(where, `${key} has neither 'value' nor 'value_from'`);
+		} else if (entry.value !== undefined && entry.value_from !== undefined) {
+			error(where, `${key} must define either 'value' or 'value_from', not both`);
+		} else if (entry.value_from !== undefined) {
+			try {
+				valueSources(entry.key, entry.value_from);
+			} catch (err) {
+				error(where, err.message);
+			}
+		}
+
+		if (isRuleEntry(entry)) {
+			try {
+				matchRule(entry, '');
+			} catch (err) {
+				error(where, err.message);
+			}
+			continue;
+		}
+		if (!Array.isArray(entry.namespaces) || !entry.namespaces.every(ns => typeof ns === 'string')) {
+			error(where, `${key} needs 'namespaces' (a list of namespace names) or a 'match' rule`);
+			continue;
+		}
+		lintNamespaces(entry.namespaces, config, known, where, key, error);
+	}
+
+	for (const name of new Set(values.filter(entry => entry && typeof entry.key === 'string').map(entry => entry.key))) {
+		try {
+			keyType(values, name, `of ${where}`);
+		} catch (err) {
+			error(where, err.message);
+		}
+	}
+}
+
+function lintNamespaces(namespaces, config, known, where, what, error) {
+	if (new Set(namespaces).size !== namespaces.length) {
+		error(where, `${what} lists a namespace twice`);
+	}
+	for (const ns of namespaces) {
+		try {
+			validateNamespaceName(ns, config.namespace_names);
+		} catch (err) {
+			error(where, err.message);
+		}
+	}
+	try {
+		assertKnownNamespaces(known, namespaces, `${what} of ${where}`);
+	} catch (err) {
+		error(where, err.message);
+	}
+}
+
+// Only whole `${key}` values of data/stringData are substituted; anything
+// else that looks like a placeholder ends up in the output as it is. That may
+// be meant, e.g. `${VAR}` in a container command, so only malformed
+// placeholders in data/stringData are errors. Multi-line values (scripts,
+// config files) are not checked.
+function lintTemplates(templates, error, warn) {
+	for (const [path, documents] of Object.entries(templates)) {
+		documents.forEach((document, index) => {
+			const where = documents.length > 1 ? `${path} (document ${index + 1})` : path;
+			const sections = SECTIONS.filter(section => document[section]);
+			if (sections.length > 1) {
+				warn(where, `only 'data' is substituted when a document has both 'data' and 'stringData'; placeholders in 'stringData' stay as they are`);
+			}
+			visit(document, [], (value, fieldPath) => {
+				const inSection = SECTIONS.includes(fieldPath[0]) && fieldPath.length === 2;
+				const problem = placeholderProblem(value, inSection);
+				if (problem) {
+					(problem.error ? error : warn)(where, `${fieldPath.join('.')}: ${problem.message}`);
+				}
+			});
+		});
+	}
+}
+
+function placeholderProblem(value, inSection) {
+	if (!value.includes('${') || value.includes('\n') || (inSection && PLACEHOLDER.test(value))) {
+		return undefined;
+	}
+	if (!inSection) {
+		return /\$\{[a-zA-Z_][a-zA-Z0-9_-]*\}/.test(value) ? { message: 'placeholders are only substituted in the fields of data and stringData' } : undefined;
+	}
+	const opened = value.indexOf('${');
+	const closed = value.indexOf('}', opened);
+	if (closed === -1) {
+		return { error: true, message: `unclosed placeholder '${value.slice(opened, opened + 20)}'` };
+	}
+	const name = value.slice(opened + 2, closed);
+	if (!KEY_NAME.test(name)) {
+		return { error: true, message: `'\${${name}}' is not a valid key name` };
+	}
+	return { message: 'a placeholder must be the whole value, text around it is not substituted' };
+}
+
+function visit(value, path, fn) {
+	if (typeof value === 'string') {
+		fn(value, path);
+	} else if (Array.isArray(value)) {
+		value.forEach((item, index) => visit(item, [...path, index], fn));
+	} else if (value && typeof value === 'object') {
+		Object.entries(value).forEach(([key, item]) => visit(item, [...path, key], fn));
+	}
+}
+
+// Every placeholder and required key needs a value in at least one namespace
+// of every cluster using the template; otherwise no namespace can be rendered.
+// Templates using deprecated keys are reported with `deprecated`.
+function lintKeys(config, templates, namespaceVars, error, deprecated, warn) {
+	const used = new Set();
+	const usedDefaults = new Set();
+	for (const clusterItem of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		for (const templateDef of Array.isArray(templateDefs) ? templateDefs : []) {
+			if (!templateDef || typeof templateDef.template !== 'string') {
+				continue;
+			}
+			const where = `${cluster}/${templateDef.template}`;
+			const templatePath = findTemplate(templates, templateDef.template);
+			if (!templatePath) {
+				error(where, 'the template file is not in the templates folders');
+				continue;
+			}
+			used.add(templatePath);
+			if (templateDef.scope === 'global') {
+				continue;
+			}
+
+			let values;
+			try {
+				values = withNamespaceVars((templateDef.values ?? []).filter(entry => entry && typeof entry.key === 'string' && (isRuleEntry(entry) || Array.isArray(entry.namespaces))), namespaceVars, cluster);
+			} catch (err) {
+				error(where, err.message);
+				continue;
+			}
+			const documents = templates[templatePath];
+			const defined = new Set(values.map(entry => entry.key));
+			// `key_aliases` of the keys, as if every key had a value in every namespace
+			const namespaces = [...new Set([...(templateDef.namespaces ?? []), ...values.filter(entry => !isRuleEntry(entry)).flatMap(entry => entry.namespaces)])];
+			let aliases = new Map();
+			try {
+				aliases = addKeyAliases(Object.fromEntries([...defined].map(key => [key, Object.fromEntries(namespaces.map(ns => [ns, true]))])), config, cluster);
+			} catch (err) {
+				error(where, err.message);
+			}
+			const needed = [...new Set([...templatePlaceholders(documents), ...requiredKeys(documents)])];
+			const missing = needed.filter(key => !defined.has(key) && !aliases.has(key));
+			if (missing.length > 0) {
+				error(where, `no namespace has a value for: ${missing.join(', ')}`);
+			}
+			const isUsed = name => needed.includes(name) || needed.some(placeholder => aliases.get(placeholder) === name);
+			const unused = [...defined].filter(key => !isUsed(key) && values.some(entry => entry.key === key && !entry.from && !isDefaultEntry(entry)));
+			if (unused.length > 0) {
+				warn(where, `keys not used by the template: ${unused.join(', ')}`);
+			}
+			deprecationWarnings(values, needed, aliases).forEach(warning => deprecated(where, `the template uses ${warning}`));
+			[...defined].filter(isUsed).forEach(key => usedDefaults.add(key));
+		}
+	}
+	for (const path of Object.keys(templates).filter(candidate => !used.has(candidate))) {
+		warn(path, `not used by any cluster of ${configFile()}`);
+	}
+	const unusedDefaults = defaultEntries(config).map(entry => entry.key).filter(key => !usedDefaults.has(key));
+	if (unusedDefaults.length > 0) {
+		warn(`defaults in ${configFile()}`, `keys not used by any template: ${unusedDefaults.join(', ')}`);
+	}
+}
@@ -0,0 +1,26 @@
+import { loadConfig } from './sopsify.js';
+import { collectKeys, rows } from './docs.js';
+
+// Prints the managed keys per cluster with their owner, description and
+// namespaces. Like `docs`, values are never printed.
+export async function list(opts) {
+	const config = await loadConfig();
+	const clusters = collectKeys(config)
+		.filter(({ cluster }) => !opts.cluster || cluster === opts.cluster);
+	if (opts.cluster && clusters.length === 0) {
+		throw new Error(`Cluster '${opts.cluster}' is not defined in .sopsify.yaml`);
+	}
+
+	for (const { cluster, keys } of clusters) {
+		const listed = rows(keys).filter(row => !opts.owner || row.owner === opts.owner);
+		console.log(`🌍 ${cluster} (${listed.length} keys)`);
+		for (const row of listed) {
+			const owner = row.owner ? ` — ${row.owner}` : '';
+			const secret = row.secret === 'yes' ? '' : ' [public]';
+			console.log(`   ${row.name}${secret}${owner}: ${row.namespaces}`);
+			if (row.description) {
+				console.log(`      ${row.description}`);
+			}
+		}
+	}
+}
@@ -0,0 +1,187 @@
+import fs from 'fs/promises';
+
+const COLUMNS = ['key', 'template', 'cluster', 'secret', 'description', 'owner'];
+
+// `--matrix secrets.csv` reads values from a spreadsheet export next to
+// .sopsify.yaml: one row per key, one column per namespace, e.g.
+//
+//   key,template,cluster,frontend,backend,production/billing
+//   api-token,app-secret.yaml,production,tok-fe,tok-be,
+//   log-level,app-secret.yaml,,info,info,debug
+//
+// `key` and `template` are required, `cluster`, `secret` (true/false),
+// `description` and `owner` optional. Every other column is a namespace, by
+// name or as `<cluster>/<namespace>` for one cluster only, which wins over the
+// namespace's column; empty cells have no value. A row without a cluster
+// applies to every cluster defining the template, or those its
+// `<cluster>/<namespace>` columns name. A `.tsv` file is read tab separated.
+export async function loadMatrix(path) {
+	let text;
+	try {
+		text = await fs.readFile(path, 'utf-8');
+	} catch (err) {
+		throw new Error(`Could not read the matrix '${path}': ${err.message}`);
+	}
+	const [header, ...rows] = parseRows(text.replace(/^\uFEFF/, ''), path.endsWith('.tsv') ? '\t' : ',', path);
+	if (!header) {
+		throw new Error(`The matrix '${path}' is empty; its first row names the columns: key, template and the namespaces`);
+	}
+	const names = header.cells.map(name => name.trim());
+	const duplicate = names.find((name, index) => names.indexOf(name) !== index);
+	if (duplicate !== undefined) {
+		throw new Error(`Column '${duplicate}' is in the matrix '${path}' twice`);
+	}
+	for (const required of ['key', 'template']) {
+		if (!names.includes(required)) {
+			throw new Error(`The matrix '${path}' has no '${required}' column`);
+		}
+	}
+	const namespaceColumns = names.map((name, index) => ({ name, index })).filter(({ name }) => !COLUMNS.includes(name));
+	if (namespaceColumns.some(({ name }) => name === '' || name.split('/').length > 2)) {
+		throw new Error(`Every other column of the matrix '${path}' than ${COLUMNS.join(', ')} must be named after a namespace or <cluster>/<namespace>`);
+	}
+
+	return rows.filter(({ cells }) => cells.some(cell => cell !== '')).map(({ line, cells }) => {
+		const where = `${path}:${line}`;
+		const cell = name => (names.includes(name) ? (cells[names.indexOf(name)] ?? '').trim() : '');
+		if (cells.length > names.length) {
+			throw new Error(`Row ${where} has more cells than the matrix has columns`);
+		}
+		if (cell('key') === '' || cell('template') === '') {
+			throw new Error(`Row ${where} needs a key and a template`);
+		}
+		if (!['', 'true', 'false'].includes(cell('secret'))) {
+			throw new Error(`'secret' of row ${where} must be true or false`);
+		}
+		return {
+			where,
+			key: cell('key'),
+			template: cell('template'),
+			cluster: cell('cluster') || undefined,
+			secret: cell('secret') === 'false' ? false : undefined,
+			description: cell('description') || undefined,
+			owner: cell('owner') || undefined,
+			values: namespaceColumns
+				.filter(({ index }) => (cells[index] ?? '') !== '')
+				.map(({ name, index }) => ({ column: name, value: cells[index] })),
+		};
+	});
+}
+
+// Adds the rows of a matrix to the config as value entries, one per value of a
+// row, with the namespaces having it. Template definitions and clusters a row
+// names are added when the config has none yet.
+export function applyMatrix(config, rows) {
+	for (const row of rows) {
+		const named = row.values.filter(({ column }) => column.includes('/')).map(({ column }) => column.split('/')[0]);
+		const clusters = row.cluster
+			? [row.cluster]
+			: [...new Set([...clustersDefining(config, row.template), ...named])];
+		if (clusters.length === 0) {
+			throw new Error(`Template '${row.template}' of row ${row.where} is not defined for any cluster; add it to .sopsify.yaml or give the row a cluster`);
+		}
+
+		for (const cluster of clusters) {
+			// A `<cluster>/<namespace>` cell wins over the namespace's own
+			const byNamespace = new Map();
+			for (const { column, value } of [...row.values].sort((a, b) => a.column.includes('/') - b.column.includes('/'))) {
+				const [columnCluster, namespace] = column.includes('/') ? column.split('/') : [cluster, column];
+				if (columnCluster === cluster) {
+					byNamespace.set(namespace, value);
+				}
+			}
+			const byValue = new Map();
+			for (const [namespace, value] of byNamespace) {
+				byValue.set(value, [...(byValue.get(value) ?? []), namespace]);
+			}
+			if (byValue.size === 0) {
+				continue;
+			}
+
+			const templateDef = templateDefinition(config, cluster, row.template);
+			templateDef.values = templateDef.values ?? [];
+			for (const [value, namespaces] of byValue) {
+				templateDef.values.push({
+					key: row.key,
+					value,
+					namespaces,
+					...(row.secret === false ? { secret: false } : {}),
+					...(row.description ? { description: row.description } : {}),
+					...(row.owner ? { owner: row.owner } : {}),
+					matrix: row.where,
+				});
+			}
+		}
+	}
+}
+
+function clustersDefining(config, template) {
+	return config.sopsify.map(item => Object.entries(item)[0])
+		.filter(([, templateDefs]) => templateDefs.some(def => def.template === template))
+		.map(([cluster]) => cluster);
+}
+
+function templateDefinition(config, cluster, template) {
+	let clusterItem = config.sopsify.find(item => Object.keys(item)[0] === cluster);
+	if (!clusterItem) {
+		clusterItem = { [cluster]: [] };
+		config.sopsify.push(clusterItem);
+	}
+	const templateDefs = clusterItem[cluster];
+	let templateDef = templateDefs.find(def => def.template === template);
+	if (!templateDef) {
+		templateDef = { template, values: [] };
+		templateDefs.push(templateDef);
+	}
+	return templateDef;
+}
+
+// Rows of cells with the line they start on. Cells may be quoted ("a, b"),
+// with "" for a quote, and then span lines.
+function parseRows(text, delimiter, path) {
+	const rows = [];
+	let cells = [];
+	let cell = '';
+	let quoted = false;
+	let line = 1;
+	let start = 1;
+	for (let i = 0; i < text.length; i++) {
+		const char = text[i];
+		if (quoted) {
+			if (char === '"' && text[i + 1] === '"') {
+				cell += '"';
+				i++;
+			} else if (char === '"') {
+				quoted = false;
+			} else {
+				line += char === '\n' ? 1 : 0;
+				cell += char;
+			}
+		} else if (char === '"' && cell === '') {
+			quoted = true;
+		} else if (char === delimiter) {
+			cells.push(cell);
+			cell = '';
+		} else if (char === '\n' || char === '\r') {
+			if (char === '\r' && text[i + 1] === '\n') {
+				i++;
+			}
+			cells.push(cell);
+			rows.push({ line: start, cells });
+			cells = [];
+			cell = '';
+			line++;
+			start = line;
+		} else {
+			cell += char;
+		}
+	}
+	if (quoted) {
+		throw new Error(`Unclosed quote in the matrix '${path}', row starting on line ${start}`);
+	}
+	if (cell !== '' || cells.length > 0) {
+		cells.push(cell);
+		rows.push({ line: start, cells });
+	}
+	return rows;
+}
@@ -0,0 +1,79 @@
+import { validateNamespaceName, knownNamespaces, assertKnownNamespaces, editDistance } from './sopsify.js';
+import { isRuleEntry } from './rules.js';
+import { configFile } from './config.js';
+
+const PREFIX = 'group:';
+
+// `groups` in .sopsify.yaml name lists of namespaces that `namespaces` lists
+// of template definitions and value entries reference as `group:<name>`:
+//
+//   groups:
+//     prod-clusters: [prod-eu, prod-us]
+//     everything: [group:prod-clusters, staging]
+//
+//   - key: registry-token
+//     value: token
+//     namespaces: [group:prod-clusters, preview]
+//
+// A group may include other groups. A namespace in several groups of a list,
+// or in a group and the list itself, is used once.
+export function expandNamespaceGroups(config) {
+	const groups = config.groups ?? {};
+	const members = name => resolve(groups, name, []);
+	// Unknown groups and cycles fail even in groups nothing uses yet
+	Object.keys(groups).forEach(members);
+	for (const clusterItem of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		const known = knownNamespaces(config, cluster);
+		for (const templateDef of templateDefs) {
+			const lists = [
+				...(Array.isArray(templateDef.namespaces) ? [{ owner: templateDef, where: `'namespaces' of template '${templateDef.template}'` }] : []),
+				...(templateDef.values ?? []).filter(entry => !isRuleEntry(entry) && Array.isArray(entry.namespaces))
+					.map(entry => ({ owner: entry, where: `key '${entry.key}' of template '${templateDef.template}'` })),
+			];
+			for (const { owner, where } of lists.filter(({ owner }) => owner.namespaces.some(isGroupReference))) {
+				const expanded = owner.namespaces.filter(ns => !isGroupReference(ns));
+				for (const reference of owner.namespaces.filter(isGroupReference)) {
+					const name = reference.slice(PREFIX.length);
+					if (!Object.hasOwn(groups, name)) {
+						throw new Error(`Unknown group '${name}' in ${where} in cluster '${cluster}'${suggestion(groups, name)}; define it under 'groups' in ${configFile()}`);
+					}
+					const namespaces = members(name);
+					// The group is checked here, so its errors name it
+					namespaces.forEach(ns => validateNamespaceName(ns, config.namespace_names));
+					assertKnownNamespaces(known, namespaces, `group '${name}' (used by ${where} in cluster '${cluster}')`);
+					expanded.push(...namespaces.filter(ns => !expanded.includes(ns)));
+				}
+				owner.namespaces = expanded;
+			}
+		}
+	}
+}
+
+function resolve(groups, name, seen) {
+	if (seen.includes(name)) {
+		throw new Error(`Group '${name}' includes itself: ${[...seen, name].join(' -> ')}`);
+	}
+	const namespaces = [];
+	for (const member of groups[name]) {
+		if (!isGroupReference(member)) {
+			namespaces.push(member);
+			continue;
+		}
+		const included = member.slice(PREFIX.length);
+		if (!Object.hasOwn(groups, included)) {
+			throw new Error(`Unknown group '${included}' in group '${name}'${suggestion(groups, included)}`);
+		}
+		namespaces.push(...resolve(groups, included, [...seen, name]));
+	}
+	return [...new Set(namespaces)];
+}
+
+function suggestion(groups, name) {
+	const similar = Object.keys(groups).find(candidate => editDistance(name, candidate) <= 2);
+	return similar ? ` (did you mean '${similar}'?)` : '';
+}
+
+export function isGroupReference(name) {
+	return typeof name === 'string' && name.startsWith(PREFIX);
+}
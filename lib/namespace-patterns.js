@@ -0,0 +1,51 @@
+import { knownNamespaces } from './sopsify.js';
+import { pathMatcher } from './files.js';
+import { isRuleEntry } from './rules.js';
+import { configFile } from './config.js';
+
+// `namespaces` of template definitions and value entries may use `*` and `?`
+// patterns, e.g. `[team-*]` or `["*"]`, instead of listing every namespace.
+// They expand to the namespaces of the cluster: its `known_namespaces`, or
+// else every namespace its template definitions and entries list by name.
+// A namespace another entry of the key lists by name keeps that entry's
+// value, so a pattern can give every namespace but a few the same value. A
+// pattern matching none of the namespaces is an error, as a misspelt name
+// would be.
+export function expandNamespacePatterns(config) {
+	for (const clusterItem of config.sopsify) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		const lists = templateDefs.flatMap(templateDef => [
+			...(Array.isArray(templateDef.namespaces) ? [{ owner: templateDef, where: `'namespaces' of template '${templateDef.template}'` }] : []),
+			...(templateDef.values ?? []).filter(entry => !isRuleEntry(entry) && Array.isArray(entry.namespaces))
+				.map(entry => ({
+					owner: entry,
+					where: `key '${entry.key}' of template '${templateDef.template}'`,
+					named: templateDef.values.filter(other => other !== entry && other.key === entry.key && Array.isArray(other.namespaces))
+						.flatMap(other => other.namespaces),
+				})),
+		]);
+		if (!lists.some(({ owner }) => owner.namespaces.some(isNamespacePattern))) {
+			continue;
+		}
+
+		const known = knownNamespaces(config, cluster);
+		const namespaces = known
+			? [...known]
+			: [...new Set(lists.flatMap(({ owner }) => owner.namespaces.filter(ns => !isNamespacePattern(ns))))].sort();
+		for (const { owner, where, named = [] } of lists.filter(({ owner }) => owner.namespaces.some(isNamespacePattern))) {
+			const expanded = owner.namespaces.filter(ns => !isNamespacePattern(ns));
+			for (const pattern of owner.namespaces.filter(isNamespacePattern)) {
+				const matching = namespaces.filter(pathMatcher(pattern));
+				if (matching.length === 0) {
+					throw new Error(`Pattern '${pattern}' in ${where} in cluster '${cluster}' matches none of its namespaces (${known ? `known_namespaces in ${configFile()}` : 'those listed by name'}: ${namespaces.join(', ') || 'none'})`);
+				}
+				expanded.push(...matching.filter(ns => !expanded.includes(ns) && !named.includes(ns)));
+			}
+			owner.namespaces = expanded;
+		}
+	}
+}
+
+export function isNamespacePattern(name) {
+	return typeof name === 'string' && /[*?]/.test(name);
+}
@@ -0,0 +1,26 @@
+import { globToRegExp } from './files.js';
+
+// `--namespace` limits a run to some namespaces: names or globs (`*` matches
+// any characters, `?` one), e.g. `--namespace 'team-*'`. Returns undefined
+// without patterns, so every namespace is rendered.
+export function namespaceSelector(patterns) {
+	const list = [].concat(patterns ?? []).map(String);
+	if (list.length === 0) {
+		return undefined;
+	}
+	const compiled = list.map(pattern => [pattern, globToRegExp(pattern)]);
+	const matched = new Set();
+
+	return {
+		patterns: list,
+		matches(namespace) {
+			const hits = compiled.filter(([, regexp]) => regexp.test(namespace));
+			hits.forEach(([pattern]) => matched.add(pattern));
+			return hits.length > 0;
+		},
+		// Patterns no namespace of the run matched, most likely typos
+		unmatched() {
+			return list.filter(pattern => !matched.has(pattern));
+		},
+	};
+}
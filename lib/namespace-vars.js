@@ -0,0 +1,83 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { join } from 'path';
+import { isRuleEntry } from './rules.js';
+
+export const NAMESPACE_VARS_DIR = 'namespaces';
+
+// Loads namespace variable files: flat key/value maps owned by the team of a
+// namespace (e.g. via CODEOWNERS), merged into that namespace's values next to
+// the shared ones in .sopsify.yaml. `namespaces/<ns>.yaml` applies to every
+// cluster, `namespaces/<cluster>/<ns>.yaml` to one cluster only.
+export async function loadNamespaceVars(dir = NAMESPACE_VARS_DIR) {
+	let entries;
+	try {
+		entries = await fs.readdir(dir, { withFileTypes: true });
+	} catch {
+		return [];
+	}
+
+	const namespaceVars = [];
+	for (const entry of entries) {
+		if (entry.isFile() && /\.ya?ml$/.test(entry.name)) {
+			namespaceVars.push(await loadFile(join(dir, entry.name), undefined, entry.name));
+		} else if (entry.isDirectory()) {
+			const files = (await fs.readdir(join(dir, entry.name))).filter(f => /\.ya?ml$/.test(f));
+			for (const file of files) {
+				namespaceVars.push(await loadFile(join(dir, entry.name, file), entry.name, file));
+			}
+		}
+	}
+
+	const seen = new Map();
+	for (const { cluster, namespace, file } of namespaceVars) {
+		const id = `${cluster || '*'}/${namespace}`;
+		if (seen.has(id)) {
+			throw new Error(`Namespace '${namespace}' has more than one variable file: ${seen.get(id)}, ${file}`);
+		}
+		seen.set(id, file);
+	}
+	return namespaceVars;
+}
+
+async function loadFile(path, cluster, name) {
+	const values = yaml.load(await fs.readFile(path, 'utf-8')) || {};
+	if (typeof values !== 'object' || Array.isArray(values)) {
+		throw new Error(`Error in '${path}': expected a map of keys to values`);
+	}
+	for (const [key, value] of Object.entries(values)) {
+		if (value !== null && typeof value === 'object') {
+			throw new Error(`Error in '${path}': value of key '${key}' must be a string, number or boolean`);
+		}
+	}
+	return { cluster, namespace: name.replace(/\.ya?ml$/, ''), file: path, values };
+}
+
+// Adds the variables of every namespace the template definition renders as
+// value entries; a cluster's file overrides the shared file of the namespace.
+// A key may not be defined for the same namespace both centrally and in a file.
+export function withNamespaceVars(values, namespaceVars, clusterName) {
+	const namespaces = new Set(values.filter(entry => !isRuleEntry(entry)).flatMap(entry => entry.namespaces));
+	const merged = [...values];
+
+	for (const ns of namespaces) {
+		const files = namespaceVars
+			.filter(v => v.namespace === ns && (!v.cluster || v.cluster === clusterName))
+			.sort((a, b) => Boolean(a.cluster) - Boolean(b.cluster));
+
+		const vars = {};
+		for (const { file, values: fileValues } of files) {
+			for (const [key, value] of Object.entries(fileValues)) {
+				vars[key] = { value, file };
+			}
+		}
+
+		for (const [key, { value, file }] of Object.entries(vars)) {
+			if (values.some(entry => entry.key === key && !isRuleEntry(entry) && entry.namespaces.includes(ns))) {
+				throw new Error(`Key '${key}' for namespace '${ns}' is defined both in .sopsify.yaml and in '${file}'`);
+			}
+			merged.push({ key, value, namespaces: [ns], from: file });
+		}
+	}
+	return merged;
+}
@@ -0,0 +1,67 @@
+import { isAbsolute, join, normalize, sep } from 'path';
+import { isRuleEntry } from './rules.js';
+import { clusterEnvironment } from './environments.js';
+import { configFile } from './config.js';
+
+// `output_roots` in .sopsify.yaml moves the outputs of a namespace out of the
+// default `clusters/<cluster>/{secrets,manifests}/<namespace>` layout, e.g. for
+// GitOps repos laid out per environment. Keys are a namespace,
+// `<cluster>/<namespace>` (which wins) or `*` for every other namespace;
+// values a folder relative to the repository that may use ${cluster},
+// ${environment} and ${namespace}. Encrypted and plain outputs (and variable
+// snapshots) of an overridden namespace share that folder.
+// `groupRoot` is the `output_root` of a template group, which wins.
+export function outputDirs(config, cluster, namespace, groupRoot) {
+	const roots = config.output_roots || {};
+	const root = groupRoot ?? roots[`${cluster}/${namespace}`] ?? roots[namespace] ?? roots['*'];
+	if (root === undefined) {
+		return {
+			secrets: join('clusters', cluster, 'secrets', namespace),
+			manifests: join('clusters', cluster, 'manifests', namespace),
+			snapshots: join('clusters', cluster, 'snapshots', namespace),
+			overridden: false,
+		};
+	}
+
+	if (typeof root !== 'string' || root.trim() === '') {
+		throw new Error(`Output root of '${cluster}/${namespace}' in '${groupRoot !== undefined ? 'template_groups' : 'output_roots'}' must be a folder name`);
+	}
+	const environment = clusterEnvironment(config, cluster);
+	if (root.includes('${environment}') && environment === undefined) {
+		throw new Error(`Output root '${root}' of '${cluster}/${namespace}' uses \${environment}, but cluster '${cluster}' is in none of 'environments' in ${configFile()}`);
+	}
+	const dir = normalize(root.replace(/\$\{(cluster|environment|namespace)\}/g, (match, name) => ({ cluster, environment, namespace })[name]));
+	if (isAbsolute(dir) || dir === '..' || dir.startsWith(`..${sep}`)) {
+		throw new Error(`Output root '${root}' of '${cluster}/${namespace}' must be a folder inside the repository`);
+	}
+	return { secrets: dir, manifests: dir, snapshots: dir, overridden: true };
+}
+
+// Global templates (`scope: global`, `--global`) are written once per cluster,
+// next to the per-namespace folders; `output_roots` do not apply to them
+export function globalOutputDir(cluster) {
+	return join('clusters', cluster, 'global');
+}
+
+// Every configured cluster/namespace whose outputs live outside the default
+// layout; namespaces only selected by `match` rules cannot be listed
+export function overriddenOutputDirs(config) {
+	const dirs = [];
+	if (!config.output_roots) {
+		return dirs;
+	}
+	for (const clusterItem of config.sopsify || []) {
+		const [cluster, templateDefs] = Object.entries(clusterItem)[0];
+		const namespaces = new Set(templateDefs.flatMap(def => [
+			...(def.namespaces || []),
+			...(def.values || []).filter(entry => !isRuleEntry(entry)).flatMap(entry => entry.namespaces),
+		]));
+		for (const namespace of namespaces) {
+			const { secrets, overridden } = outputDirs(config, cluster, namespace);
+			if (overridden) {
+				dirs.push({ cluster, namespace, dir: secrets });
+			}
+		}
+	}
+	return dirs;
+}
@@ -0,0 +1,66 @@
+import fs from 'fs/promises';
+import os from 'os';
+import { join } from 'path';
+
+const SEVERITIES = ['ignore', 'warn', 'error'];
+
+// Warns (or fails) when files holding plaintext values or keys are readable
+// by group or others. `fix` tightens them to 0600 instead.
+export async function checkPermissions(paths, { severity = 'warn', fix = false } = {}) {
+	if (!SEVERITIES.includes(severity)) {
+		throw new Error(`Unknown permissions severity '${severity}', expected one of: ${SEVERITIES.join(', ')}`);
+	}
+	// Mode bits don't reflect ACLs on Windows
+	if (process.platform === 'win32' || (severity === 'ignore' && !fix)) {
+		return;
+	}
+
+	const exposed = [];
+	for (const path of new Set(paths.filter(Boolean))) {
+		let stat;
+		try {
+			stat = await fs.stat(path);
+		} catch {
+			continue;
+		}
+
+		const mode = stat.mode & 0o777;
+		if ((mode & 0o077) === 0) {
+			continue;
+		}
+
+		if (fix) {
+			await fs.chmod(path, mode & 0o700);
+			console.log(`   🔧 Tightened permissions of \`${path}\`: ${mode.toString(8)} → ${(mode & 0o700).toString(8)}`);
+		} else {
+			exposed.push({ path, mode });
+		}
+	}
+
+	if (exposed.length === 0 || severity === 'ignore') {
+		return;
+	}
+
+	for (const { path, mode } of exposed) {
+		console.warn(`   ⚠️ \`${path}\` is readable by group/others (mode ${mode.toString(8)})`);
+	}
+	if (severity === 'error') {
+		throw new Error(`${exposed.length} sensitive files are readable by group/others, run with --fix-perms to tighten them`);
+	}
+	console.warn('   ⚠️ Run with --fix-perms to tighten them');
+}
+
+// Key files sops picks up by default, in addition to explicitly configured ones
+export function defaultKeyFiles() {
+	return [process.env.SOPS_AGE_KEY_FILE, defaultAgeKeyFile()];
+}
+
+// sops' age key file in the user config dir, used besides $SOPS_AGE_KEY_FILE
+export function defaultAgeKeyFile() {
+	const home = os.homedir();
+	const configDir = process.env.XDG_CONFIG_HOME || (process.platform === 'darwin'
+		? join(home, 'Library', 'Application Support')
+		: join(home, '.config'));
+
+	return join(configDir, 'sops', 'age', 'keys.txt');
+}
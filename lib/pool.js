@@ -0,0 +1,33 @@
+// Runs `fn` for every item with at most `jobs` calls in flight. Results keep
+// the order of `items`. After the first error no new calls are started; it is
+// rethrown once the calls already running have settled.
+export async function runPool(items, jobs, fn) {
+	const results = new Array(items.length);
+	let next = 0;
+	let failure;
+
+	async function worker() {
+		while (next < items.length && !failure) {
+			const index = next++;
+			try {
+				results[index] = await fn(items[index], index);
+			} catch (err) {
+				failure = failure || { err };
+			}
+		}
+	}
+
+	await Promise.all(Array.from({ length: Math.max(1, Math.min(jobs, items.length)) }, worker));
+	if (failure) {
+		throw failure.err;
+	}
+	return results;
+}
+
+export function positiveInt(value, option) {
+	const n = Number(value);
+	if (!Number.isInteger(n) || n < 1) {
+		throw new Error(`${option} must be a positive integer, got '${value}'`);
+	}
+	return n;
+}
@@ -0,0 +1,23 @@
+import { loadYamlFile } from './sopsify.js';
+import { configFile } from './config.js';
+import { setOptionValues } from './user-config.js';
+
+// `presets` in .sopsify.yaml bundle the options of a run under a name, so
+// `sopsify run ci` replaces a Makefile target. A preset maps long option
+// names (without dashes or "no-") to values; options given on the command
+// line still win. Returns the options of the run.
+export async function presetOptions(name, program) {
+	const presets = (await loadYamlFile(configFile())).presets || {};
+	const preset = presets[name];
+	if (!preset || typeof preset !== 'object' || Array.isArray(preset)) {
+		const defined = Object.keys(presets);
+		throw new Error(`Preset '${name}' is not defined in ${configFile()}${defined.length > 0 ? `, expected one of: ${defined.join(', ')}` : ''}`);
+	}
+
+	const unknown = setOptionValues(program, preset, 'preset');
+	if (unknown.length > 0) {
+		throw new Error(`Preset '${name}' sets unknown options: ${unknown.join(', ')}`);
+	}
+	console.log(`📋 Preset: ${name}`);
+	return program.opts();
+}
@@ -0,0 +1,50 @@
+import { execFileSync } from 'child_process';
+import { readFileSync } from 'fs';
+import { configFile } from './config.js';
+
+const VERSION = JSON.parse(readFileSync(new URL('../package.json', import.meta.url), 'utf-8')).version;
+
+// `--provenance` annotates every rendered document with where it came from,
+// so whoever decrypts an output later can trace it:
+//
+//   metadata:
+//     annotations:
+//       sopsify.io/template: templates/app-secret.yaml
+//       sopsify.io/revision: 3f2c1e0...   # last commit of the config or template, -dirty when changed since
+//       sopsify.io/version: 1.0.0
+//
+// The revision is the last commit touching .sopsify.yaml or the template,
+// not HEAD, so outputs only change when their sources do. Outside of a git
+// repository it is left out.
+export function createProvenance() {
+	const revisions = new Map();
+	return (document, templatePath) => {
+		if (!revisions.has(templatePath)) {
+			revisions.set(templatePath, revision([configFile(), templatePath]) ?? revision([configFile()]));
+		}
+		const metadata = document.metadata = document.metadata || {};
+		metadata.annotations = {
+			...(metadata.annotations || {}),
+			'sopsify.io/template': templatePath,
+			...(revisions.get(templatePath) ? { 'sopsify.io/revision': revisions.get(templatePath) } : {}),
+			'sopsify.io/version': VERSION,
+		};
+	};
+}
+
+// Undefined outside of a work tree, for files git does not know, or without git
+function revision(paths) {
+	try {
+		const sha = git(['log', '-1', '--format=%H', '--', ...paths]);
+		if (sha === '') {
+			return undefined;
+		}
+		return git(['status', '--porcelain', '--', ...paths]) === '' ? sha : `${sha}-dirty`;
+	} catch {
+		return undefined;
+	}
+}
+
+function git(args) {
+	return execFileSync('git', args, { stdio: 'pipe', encoding: 'utf-8' }).trim();
+}
@@ -0,0 +1,110 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { loadNamespaceVars } from './namespace-vars.js';
+import { renameRotationHistory } from './rotation.js';
+import { configFile } from './config.js';
+import { loadTemplates, KEY_NAME } from './sopsify.js';
+import { isArchive } from './archives.js';
+
+// Renames a config key and every `${key}` placeholder referencing it. All
+// files are edited as text, so comments and formatting survive; the config is
+// parsed again and compared with the structural rename before anything is
+// written. `--dry-run` prints the changed lines instead.
+export async function renameKey(oldKey, newKey, opts) {
+	if (!KEY_NAME.test(newKey)) {
+		throw new Error(`'${newKey}' is not a valid key name (letters, digits, '_' and '-', not starting with a digit or '-')`);
+	}
+	if (opts.templates && isArchive(opts.templates)) {
+		throw new Error(`Templates of archive '${opts.templates}' cannot be rewritten; rename '${oldKey}' where the archive is built`);
+	}
+
+	const configText = await fs.readFile(configFile(), 'utf-8');
+	const config = yaml.load(configText);
+	const keys = configKeys(config);
+	if (!keys.has(oldKey)) {
+		throw new Error(`Key '${oldKey}' is not defined in ${configFile()}`);
+	}
+	if (keys.has(newKey)) {
+		throw new Error(`Key '${newKey}' is already defined in ${configFile()}; renaming cannot merge keys`);
+	}
+
+	const quoted = `(?<q>['"]?)${oldKey.replace(/[.*+?^${}()|[\]\\]/g, '\\$&')}\\k<q>`;
+	const changes = [];
+
+	const configEdit = editLines(configFile(), configText, line =>
+		line.replace(new RegExp(`^(\\s*(?:-\\s+)?key:\\s*)${quoted}(?=\\s*(#.*)?$)`), `$1$<q>${newKey}$<q>`));
+	if (JSON.stringify(yaml.load(configEdit.text)) !== JSON.stringify(renamedConfig(config, oldKey, newKey))) {
+		throw new Error(`Could not rename '${oldKey}' in ${configFile()} without changing its structure; please rename it manually`);
+	}
+	changes.push(configEdit);
+
+	for (const { file } of await loadNamespaceVars()) {
+		changes.push(editLines(file, await fs.readFile(file, 'utf-8'), line =>
+			line.replace(new RegExp(`^${quoted}(?=\\s*:)`), `$<q>${newKey}$<q>`)));
+	}
+
+	if (opts.templates) {
+		const templates = await loadTemplates(opts.templates, [], { config, quiet: true });
+		for (const path of Object.keys(templates)) {
+			changes.push(editLines(path, await fs.readFile(path, 'utf-8'), line => line.split(`\${${oldKey}}`).join(`\${${newKey}}`)));
+		}
+	}
+
+	const changed = changes.filter(change => change.lines.length > 0);
+	console.log(`🔄 Renaming key '${oldKey}' to '${newKey}'${opts.templates ? '' : ' (templates not rewritten, pass -t <FOLDER>)'}`);
+	for (const { path, lines } of changed) {
+		console.log(`\n   📝 ${path}`);
+		for (const { number, before, after } of lines) {
+			console.log(`      ${number}: - ${before.trim()}`);
+			console.log(`      ${number}: + ${after.trim()}`);
+		}
+	}
+	if (opts.dryRun) {
+		console.log('\n⏸️ Dry-run, nothing was changed');
+		return;
+	}
+
+	for (const { path, text } of changed) {
+		await fs.writeFile(path, text, 'utf-8');
+	}
+	if (config.rotation) {
+		await renameRotationHistory(config.rotation, { key: [oldKey, newKey] });
+	}
+	console.log(`\n✅ Renamed '${oldKey}' to '${newKey}' in ${changed.length} files`);
+}
+
+function configKeys(config) {
+	const keys = new Set();
+	for (const clusterItem of config.sopsify || []) {
+		for (const templateDef of Object.values(clusterItem)[0]) {
+			(templateDef.values ?? []).forEach(entry => keys.add(entry.key));
+		}
+	}
+	return keys;
+}
+
+function renamedConfig(config, oldKey, newKey) {
+	const renamed = JSON.parse(JSON.stringify(config));
+	for (const clusterItem of renamed.sopsify || []) {
+		for (const templateDef of Object.values(clusterItem)[0]) {
+			for (const entry of templateDef.values ?? []) {
+				if (entry.key === oldKey) {
+					entry.key = newKey;
+				}
+			}
+		}
+	}
+	return renamed;
+}
+
+function editLines(path, text, edit) {
+	const lines = [];
+	const edited = text.split('\n').map((before, index) => {
+		const after = edit(before);
+		if (after !== before) {
+			lines.push({ number: index + 1, before, after });
+		}
+		return after;
+	});
+	return { path, text: edited.join('\n'), lines };
+}
@@ -0,0 +1,269 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { join, dirname, basename } from 'path';
+import { getBackend } from './backends.js';
+import { validateNamespaceName, knownNamespaces, dumpDocuments, assertNoPlaintextLeft, assertExpectedRecipients } from './sopsify.js';
+import { loadNamespaceVars } from './namespace-vars.js';
+import { assertPlaintextPathIgnored } from './git.js';
+import { renameRotationHistory } from './rotation.js';
+import { overriddenOutputDirs } from './output-roots.js';
+import { temporaryPlaintextPath, renameStagingPath } from './staging.js';
+import { configFile } from './config.js';
+import { filesBelow } from './files.js';
+
+
+// Renames a namespace everywhere: the `namespaces` lists of .sopsify.yaml
+// (edited in place, so comments and layout survive), its variable files, the
+// rotation history and the output folders of every cluster. Outputs are
+// decrypted, get the new `metadata.namespace` and are re-encrypted into a
+// staging folder first; nothing is moved before all of them succeeded.
+export async function renameNamespace(oldName, newName, opts) {
+	const configText = await fs.readFile(configFile(), 'utf-8');
+	const config = yaml.load(configText);
+	validateNamespaceName(newName, config.namespace_names);
+	const known = config.sopsify.map(item => knownNamespaces(config, Object.keys(item)[0])).filter(Boolean);
+	if (known.some(namespaces => !namespaces.has(newName))) {
+		throw new Error(`Namespace '${newName}' is not in 'known_namespaces' of ${configFile()}; add it there first`);
+	}
+
+	const used = usedNamespaces(config);
+	if (used.has(newName)) {
+		throw new Error(`Namespace '${newName}' is already used in ${configFile()}; renaming cannot merge namespaces`);
+	}
+	if (!used.has(oldName)) {
+		throw new Error(`Namespace '${oldName}' is not used in ${configFile()}`);
+	}
+
+	const rooted = overriddenOutputDirs(config).filter(({ namespace }) => namespace === oldName);
+	if (rooted.length > 0) {
+		throw new Error(`Namespace '${oldName}' has an output root in 'output_roots' (${rooted.map(r => r.dir).join(', ')}); please rename it manually`);
+	}
+	if (Object.keys(config.sops_env || {}).some(key => key === oldName || key.endsWith(`/${oldName}`))) {
+		throw new Error(`Namespace '${oldName}' has variables in 'sops_env'; please rename it manually`);
+	}
+	if (Object.keys(config.encryption_keys || {}).some(key => key === oldName || key.endsWith(`/${oldName}`))) {
+		throw new Error(`Namespace '${oldName}' has keys in 'encryption_keys'; please rename it manually`);
+	}
+
+	const newText = rewriteConfig(configText, config, oldName, newName);
+	const varFiles = (await loadNamespaceVars()).filter(v => v.namespace === oldName).map(v => v.file);
+	const outputDirs = await findOutputDirs(oldName, newName);
+
+	console.log(`🔄 Renaming namespace '${oldName}' to '${newName}'`);
+	console.log(`   📝 ${configFile()}`);
+	varFiles.forEach(file => console.log(`   📝 ${file} → ${renamedFile(file, newName)}`));
+	outputDirs.forEach(({ from, to }) => console.log(`   📁 ${from} → ${to}`));
+	if (opts.dryRun) {
+		console.log('\n⏸️ Dry-run, nothing was changed');
+		return;
+	}
+
+	const backend = getBackend(opts);
+	if (outputDirs.length > 0) {
+		await backend.check();
+	}
+
+	const staged = [];
+	try {
+		for (const dir of outputDirs) {
+			staged.push(await stageOutputDir(dir, newName, backend, config, opts));
+		}
+	} catch (err) {
+		await Promise.all(staged.map(dir => fs.rm(dir.staging, { recursive: true, force: true })));
+		throw err;
+	}
+
+	for (const { from, to, staging } of staged) {
+		await fs.rename(staging, to);
+		await fs.rm(from, { recursive: true });
+	}
+	for (const file of varFiles) {
+		await fs.rename(file, renamedFile(file, newName));
+	}
+	await fs.writeFile(configFile(), newText, 'utf-8');
+	const moved = config.rotation ? await renameRotationHistory(config.rotation, { namespace: [oldName, newName] }) : 0;
+	if (moved > 0) {
+		console.log(`   🕒 Moved the rotation history of ${moved} keys`);
+	}
+
+	console.log(`\n✅ Renamed '${oldName}' to '${newName}' (${staged.length} output folders)`);
+}
+
+function usedNamespaces(config) {
+	const used = new Set();
+	for (const clusterItem of config.sopsify || []) {
+		for (const templateDef of Object.values(clusterItem)[0]) {
+			(templateDef.namespaces || []).forEach(ns => used.add(ns));
+			for (const entry of templateDef.values ?? []) {
+				(entry.namespaces || []).forEach(ns => used.add(ns));
+			}
+		}
+	}
+	return used;
+}
+
+// The structural rename the text edit must produce
+function renamedConfig(config, oldName, newName) {
+	const renamed = JSON.parse(JSON.stringify(config));
+	const rename = list => list && list.map(ns => (ns === oldName ? newName : ns));
+
+	for (const clusterItem of renamed.sopsify || []) {
+		for (const templateDef of Object.values(clusterItem)[0]) {
+			if (templateDef.namespaces) {
+				templateDef.namespaces = rename(templateDef.namespaces);
+			}
+			for (const entry of templateDef.values ?? []) {
+				if (entry.namespaces) {
+					entry.namespaces = rename(entry.namespaces);
+				}
+			}
+		}
+	}
+	for (const rule of renamed.recipients || []) {
+		if (rule.namespaces) {
+			rule.namespaces = rename(rule.namespaces);
+		}
+	}
+	const perNamespace = renamed.rotation && renamed.rotation.namespaces;
+	if (perNamespace && oldName in perNamespace) {
+		perNamespace[newName] = perNamespace[oldName];
+		delete perNamespace[oldName];
+	}
+	return renamed;
+}
+
+// Replaces the namespace in `namespaces: [a, b]` flow lists and in the block
+// lists/maps below a `namespaces:` key. The result is parsed and compared with
+// the structural rename, so an unsupported layout fails instead of being
+// rewritten wrongly.
+function rewriteConfig(text, config, oldName, newName) {
+	const item = `(?<q>['"]?)${oldName.replace(/[.*+?^${}()|[\]\\]/g, '\\$&')}\\k<q>`;
+	const flowItem = new RegExp(`([\\[,]\\s*)${item}(?=\\s*[,\\]])`, 'g');
+	const blockItem = new RegExp(`^(\\s*-\\s+)${item}(?=\\s*(#.*)?$)`);
+	const mapKey = new RegExp(`^(\\s+)${item}(?=\\s*:)`);
+
+	let blockIndent = -1;
+	const lines = text.split('\n').map(line => {
+		const indent = line.search(/\S/);
+		if (indent < 0) {
+			return line;
+		}
+		if (blockIndent >= 0 && (indent > blockIndent || (indent === blockIndent && /^\s*-/.test(line)))) {
+			return line.replace(blockItem, `$1$<q>${newName}$<q>`).replace(mapKey, `$1$<q>${newName}$<q>`);
+		}
+		blockIndent = -1;
+
+		const key = line.match(/^(\s*(?:-\s+)?)namespaces:\s*(.*)$/);
+		if (!key) {
+			return line;
+		}
+		if (/^\[/.test(key[2])) {
+			return line.replace(flowItem, `$1$<q>${newName}$<q>`);
+		}
+		blockIndent = key[1].length;
+		return line;
+	});
+
+	const rewritten = lines.join('\n');
+	if (JSON.stringify(yaml.load(rewritten)) !== JSON.stringify(renamedConfig(config, oldName, newName))) {
+		throw new Error(`Could not rename '${oldName}' in ${configFile()} without changing its structure; please rename it manually`);
+	}
+	return rewritten;
+}
+
+async function findOutputDirs(oldName, newName) {
+	const dirs = [];
+	let clusters;
+	try {
+		clusters = (await fs.readdir('clusters', { withFileTypes: true })).filter(e => e.isDirectory());
+	} catch {
+		return dirs;
+	}
+
+	for (const cluster of clusters) {
+		for (const kind of ['secrets', 'manifests', 'snapshots']) {
+			const from = join('clusters', cluster.name, kind, oldName);
+			const to = join('clusters', cluster.name, kind, newName);
+			if (!await exists(from)) {
+				continue;
+			}
+			if (await exists(to)) {
+				throw new Error(`Output folder '${to}' already exists`);
+			}
+			dirs.push({ cluster: cluster.name, kind, from, to });
+		}
+	}
+	return dirs;
+}
+
+async function stageOutputDir({ cluster, kind, from, to }, newName, backend, config, opts) {
+	const staging = renameStagingPath(join('clusters', cluster, kind), newName);
+	await fs.rm(staging, { recursive: true, force: true });
+	await fs.mkdir(staging, { recursive: true });
+	const result = { from, to, staging };
+
+	// Outputs of templates in subfolders keep their layout
+	for (const file of await filesBelow(from)) {
+		const path = join(from, file);
+		const stagedPath = join(staging, file);
+		await fs.mkdir(dirname(stagedPath), { recursive: true });
+
+		if (!file.endsWith(backend.suffix)) {
+			const documents = withNamespace(yaml.loadAll(await fs.readFile(path, 'utf-8')), newName);
+			await fs.writeFile(stagedPath, dumpDocuments(documents), 'utf-8');
+			continue;
+		}
+
+		const documents = withNamespace(yaml.loadAll(backend.decrypt(path)), newName);
+		const outputPath = join(to, file);
+		const plaintextPath = temporaryPlaintextPath(dirname(stagedPath), basename(file).slice(0, -backend.suffix.length) + '.yaml');
+
+		assertPlaintextPathIgnored(plaintextPath, opts);
+		await fs.writeFile(plaintextPath, dumpDocuments(documents), 'utf-8');
+		try {
+			await backend.encrypt(plaintextPath, outputPath);
+		} catch (err) {
+			await fs.rm(plaintextPath, { force: true });
+			throw err;
+		}
+		await fs.rename(plaintextPath, stagedPath);
+
+		await assertNoPlaintextLeft(stagedPath, outputPath, documentValues(documents));
+		if (config.recipients && backend.format === 'sops') {
+			await assertExpectedRecipients(stagedPath, outputPath, config.recipients, { cluster, namespace: newName });
+		}
+		console.log(`   🔐 Re-encrypted: ${newName}/${file}`);
+	}
+	return result;
+}
+
+function withNamespace(documents, namespace) {
+	return documents.filter(doc => doc && typeof doc === 'object').map(doc => {
+		delete doc.sops;
+		doc.metadata = { ...doc.metadata, namespace };
+		return doc;
+	});
+}
+
+function documentValues(documents) {
+	const entries = [];
+	for (const doc of documents) {
+		for (const section of ['data', 'stringData']) {
+			entries.push(...Object.entries(doc[section] || {}));
+		}
+	}
+	return entries;
+}
+
+function renamedFile(file, newName) {
+	return file.replace(/[^/\\]+(\.ya?ml)$/, `${newName}$1`);
+}
+
+async function exists(path) {
+	try {
+		await fs.access(path);
+		return true;
+	} catch {
+		return false;
+	}
+}
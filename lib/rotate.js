@@ -0,0 +1,90 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { dirname, basename } from 'path';
+import { loadConfig } from './sopsify.js';
+import { getBackend } from './backends.js';
+import { listOutputs, sopsRecipients } from './verify.js';
+import { encryptionKeys, encryptedFor } from './encryption-keys.js';
+import { sopsEnv } from './sops-env.js';
+import { assertPlaintextPathIgnored } from './git.js';
+import { temporaryPlaintextPath } from './staging.js';
+
+// Re-keys every encrypted output of a tree for the keys configured now, e.g.
+// after adding or retiring an age or KMS key: `sops updatekeys` for outputs
+// following the creation rules of .sops.yaml, re-encryption for namespaces
+// with `encryption_keys` (and with the rops backend). Values are not
+// changed; files already encrypted for the right keys are left untouched.
+export async function rotateKeys(opts) {
+	const backend = getBackend(opts);
+	if (backend.format !== 'sops') {
+		throw new Error(`rotate re-keys sops files; the ${backend.name} backend has no recipients to rotate`);
+	}
+	await backend.check();
+	const config = await loadConfig();
+	const outputs = await listOutputs(opts.output, config);
+	console.log(`🔑 Re-keying ${outputs.length} outputs in ${opts.output}`);
+
+	const changed = [];
+	for (const output of outputs) {
+		const env = output.namespace === undefined ? undefined : sopsEnv(config, output.cluster, output.namespace);
+		const keys = output.namespace === undefined ? undefined : encryptionKeys(config, output.cluster, output.namespace);
+		const before = await recipients(output.path);
+
+		if (keys || !backend.updateKeys) {
+			await reencrypt(output.path, backend, env, keys, opts);
+		} else {
+			try {
+				backend.updateKeys(output.path, env);
+			} catch (err) {
+				throw new Error(`sops updatekeys failed for '${output.path}': ${err.stderr ? err.stderr.toString().trim() : err.message}`);
+			}
+		}
+
+		const difference = describeChange(before, await recipients(output.path));
+		if (difference) {
+			changed.push(output.path);
+			console.log(`   🔄 ${output.path}: ${difference}`);
+		} else {
+			console.log(`   ✅ ${output.path}`);
+		}
+	}
+
+	console.log(changed.length === 0 ? '\n✅ Every output is encrypted for the configured keys' : `\n📝 ${changed.length} of ${outputs.length} outputs re-keyed`);
+}
+
+async function recipients(path) {
+	const [document] = yaml.loadAll(await fs.readFile(path, 'utf-8'));
+	if (!document || !document.sops) {
+		throw new Error(`'${path}' has no sops metadata, it is not encrypted`);
+	}
+	return sopsRecipients(document.sops);
+}
+
+// Encrypts the decrypted content again next to the output and only replaces
+// it when that selected other recipients, so unchanged files keep their MAC
+async function reencrypt(path, backend, env, keys, opts) {
+	const [document] = yaml.loadAll(await fs.readFile(path, 'utf-8'));
+	if (keys && encryptedFor(document.sops, keys)) {
+		return;
+	}
+	const plaintextPath = temporaryPlaintextPath(dirname(path), basename(path).replace(backend.suffix, '.yaml'));
+	assertPlaintextPathIgnored(plaintextPath, opts);
+	await fs.writeFile(plaintextPath, backend.decrypt(path, env), 'utf-8');
+	try {
+		await backend.encrypt(plaintextPath, path, env, { keys, encryptedRegex: document.sops.encrypted_regex });
+		if (describeChange(sopsRecipients(document.sops), await recipients(plaintextPath))) {
+			await fs.rename(plaintextPath, path);
+		}
+	} finally {
+		await fs.rm(plaintextPath, { force: true });
+	}
+}
+
+// e.g. `+age age1new..., -kms arn:...`, or undefined for the same recipients
+function describeChange(before, after) {
+	const changes = Object.keys(after).flatMap(type => [
+		...[...after[type]].filter(recipient => !before[type].has(recipient)).map(recipient => `+${type} ${recipient}`),
+		...[...before[type]].filter(recipient => !after[type].has(recipient)).map(recipient => `-${type} ${recipient}`),
+	]);
+	return changes.length > 0 ? changes.join(', ') : undefined;
+}
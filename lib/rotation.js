@@ -0,0 +1,129 @@
+import fs from 'fs/promises';
+import { dirname } from 'path';
+import { createHash, randomBytes } from 'crypto';
+import { projectStateFile } from './user-config.js';
+
+const LEGACY_STATE_FILE = '.sopsify-state.json';
+const HISTORY_LENGTH = 10;
+const DAY_MS = 24 * 60 * 60 * 1000;
+
+// Tracks when each cluster/namespace/key value last changed. Only salted
+// hashes are stored, never values. Values older than the configured maximum
+// age are reported as a warning or, with `severity: error`, fail the run.
+export async function openRotationState(settings) {
+	const path = await stateFile(settings);
+	const state = await loadState(path);
+	const now = new Date();
+	const observed = new Set();
+
+	const hash = value => createHash('sha256').update(state.salt).update(String(value)).digest('hex');
+
+	return {
+		observe(cluster, namespace, key, value) {
+			const id = `${cluster}/${namespace}/${key}`;
+			if (observed.has(id)) {
+				return;
+			}
+			observed.add(id);
+
+			const digest = hash(value);
+			const history = state.keys[id] = state.keys[id] || [];
+			if (history.length === 0 || history[0].hash !== digest) {
+				history.unshift({ hash: digest, changed_at: now.toISOString() });
+				history.splice(HISTORY_LENGTH);
+			}
+		},
+
+		async finish() {
+			await writeState(path, state);
+
+			const overdue = [];
+			for (const id of observed) {
+				const [cluster, namespace, ...keyParts] = id.split('/');
+				const maxAge = maxAgeDays(settings, namespace);
+				if (maxAge === undefined) {
+					continue;
+				}
+				const age = Math.floor((now - new Date(state.keys[id][0].changed_at)) / DAY_MS);
+				if (age > maxAge) {
+					overdue.push({ cluster, namespace, key: keyParts.join('/'), age, maxAge });
+				}
+			}
+
+			if (overdue.length === 0) {
+				return;
+			}
+
+			const error = settings.severity === 'error';
+			console.log(`\n${error ? '❌' : '⚠️'} ${overdue.length} values exceed their maximum age`);
+			for (const o of overdue) {
+				console.log(`   - ${o.cluster}/${o.namespace}: '${o.key}' unchanged for ${o.age} days (max ${o.maxAge})`);
+			}
+			if (error) {
+				throw new Error(`${overdue.length} values are overdue for rotation`);
+			}
+		},
+	};
+}
+
+// Moves the history of a renamed namespace and/or key, so values keep their
+// age. `rename` is e.g. { namespace: ['old', 'new'] } or { key: ['old', 'new'] }.
+export async function renameRotationHistory(settings, rename) {
+	const path = await stateFile(settings);
+	const state = await loadState(path);
+	const renamed = (value, [from, to] = []) => (value === from ? to : value);
+
+	let moved = 0;
+	for (const id of Object.keys(state.keys)) {
+		const [cluster, namespace, ...keyParts] = id.split('/');
+		const key = keyParts.join('/');
+		const newId = [cluster, renamed(namespace, rename.namespace), renamed(key, rename.key)].join('/');
+		if (newId !== id) {
+			state.keys[newId] = state.keys[id];
+			delete state.keys[id];
+			moved++;
+		}
+	}
+	if (moved > 0) {
+		await writeState(path, state);
+	}
+	return moved;
+}
+
+// Without `state_file` the state lives in the XDG state directory; a state
+// file from before that in the project is still used
+async function stateFile(settings) {
+	if (settings.state_file) {
+		return settings.state_file;
+	}
+	try {
+		await fs.access(LEGACY_STATE_FILE);
+		return LEGACY_STATE_FILE;
+	} catch {
+		return projectStateFile('rotation.json');
+	}
+}
+
+async function writeState(path, state) {
+	await fs.mkdir(dirname(path), { recursive: true, mode: 0o700 });
+	await fs.writeFile(path, `${JSON.stringify(state, null, 2)}\n`, { encoding: 'utf-8', mode: 0o600 });
+}
+
+function maxAgeDays(settings, namespace) {
+	const perNamespace = settings.namespaces || {};
+	if (namespace in perNamespace) {
+		return perNamespace[namespace];
+	}
+	return settings.max_age_days;
+}
+
+async function loadState(path) {
+	try {
+		return JSON.parse(await fs.readFile(path, 'utf-8'));
+	} catch (err) {
+		if (err.code !== 'ENOENT') {
+			throw new Error(`Could not read rotation state '${path}': ${err.message}`);
+		}
+		return { salt: randomBytes(16).toString('hex'), keys: {} };
+	}
+}
@@ -0,0 +1,36 @@
+// Rule entries derive values from the namespace name instead of listing
+// namespaces: `match` is a regular expression that must match the whole
+// namespace name, its capture groups are available as ${match:1} or
+// ${match:<name>} in the value.
+const compiled = new Map();
+
+export function isRuleEntry(entry) {
+	return entry.match !== undefined;
+}
+
+// Returns the match of a rule entry against a namespace, or null
+export function matchRule(entry, namespace) {
+	if (!compiled.has(entry.match)) {
+		compiled.set(entry.match, compileRule(entry));
+	}
+	return compiled.get(entry.match).exec(namespace);
+}
+
+// Whether an entry (rule or plain) provides a value for the namespace
+export function appliesTo(entry, namespace) {
+	return isRuleEntry(entry) ? matchRule(entry, namespace) !== null : entry.namespaces.includes(namespace);
+}
+
+function compileRule(entry) {
+	if (entry.namespaces !== undefined) {
+		throw new Error(`Key '${entry.key}' must define either 'namespaces' or 'match', not both`);
+	}
+	if (typeof entry.match !== 'string' || entry.match === '') {
+		throw new Error(`'match' of key '${entry.key}' must be a regular expression`);
+	}
+	try {
+		return new RegExp(`^(?:${entry.match})$`);
+	} catch (err) {
+		throw new Error(`'match' of key '${entry.key}' is not a valid regular expression: ${err.message}`);
+	}
+}
@@ -0,0 +1,171 @@
+import fs from 'fs/promises';
+
+export const REPORT_VERSION = 1;
+
+// `--report <FILE>`: a JSON record of a run for tooling, written whether the
+// run succeeded or not. It lists the outputs (paths and hashes of the written
+// files), the skipped template/namespace pairs with the reason, the source
+// each `value_from` key was resolved from, and what
+// failed: server-side dry-runs, hooks, strict templates and the error that
+// ended the run. Values are never included. The run fills it in as it goes;
+// `cluster` is the cluster being processed, `shard` the `--shard` of the run.
+export function createRunReport() {
+	return { started: new Date().toISOString(), outputs: [], skipped: [], sources: [], failures: [], dryRunResults: [], hookResults: [], cluster: undefined };
+}
+
+export async function writeRunReport(path, report, error) {
+	const failures = [
+		...report.failures,
+		...report.dryRunResults.filter(result => result.error)
+			.map(({ cluster, namespace, template, error: message }) => ({ stage: 'server-dry-run', cluster, namespace, template, error: message })),
+		...report.hookResults.filter(result => result.error)
+			.map(({ cluster, namespace, stage, error: message }) => ({ stage: `hook:${stage}`, cluster, namespace, error: message })),
+		...(error ? [{ stage: 'run', cluster: report.cluster, error: error.message }] : []),
+	];
+	const content = {
+		version: REPORT_VERSION,
+		started: report.started,
+		finished: new Date().toISOString(),
+		status: error ? 'failed' : failures.length > 0 ? 'partial' : 'ok',
+		...(report.shard ? { shard: report.shard } : {}),
+		outputs: report.outputs.map(({ cluster, namespace, template, path: outputPath, sha256, unchanged, renderOnly }) =>
+			({ cluster, namespace, template, path: outputPath, sha256, ...(unchanged ? { unchanged } : {}), ...(renderOnly ? { renderOnly } : {}) })),
+		skipped: report.skipped.map(({ cluster, namespace, template, reason }) => ({ cluster, namespace, template, reason })),
+		sources: report.sources,
+		failures,
+	};
+	await fs.writeFile(path, `${JSON.stringify(content, null, 2)}\n`, 'utf-8');
+}
+
+// `report diff OLD NEW`: what got worse or better between two runs, e.g. the
+// reports of the last two releases
+export async function diffRunReports(oldPath, newPath, opts = {}) {
+	if (!oldPath || !newPath) {
+		throw new Error('report diff requires two run reports written with --report, e.g. report diff old.json new.json');
+	}
+	const before = await readRunReport(oldPath);
+	const after = await readRunReport(newPath);
+	console.log(`📊 ${oldPath} (${before.status}, ${before.finished}) → ${newPath} (${after.status}, ${after.finished})`);
+
+	const failures = compare(before.failures, after.failures, failureKey);
+	const skipped = compare(before.skipped, after.skipped, pairKey);
+	const outputs = compare(before.outputs, after.outputs, output => output.path);
+	const changed = outputs.kept.filter(([was, now]) => was.sha256 !== now.sha256).length;
+
+	section('❌ Newly failing', failures.added, failure => `${failureKey(failure)}: ${String(failure.error).split('\n')[0]}`);
+	section('✅ No longer failing', failures.removed, failureKey);
+	section('⏭️ Newly skipped', skipped.added, entry => `${pairKey(entry)}: ${entry.reason}`);
+	section('🔄 No longer skipped', skipped.removed, pairKey);
+	section('➕ New outputs', outputs.added, output => output.path);
+	section('➖ Outputs gone', outputs.removed, output => output.path);
+	console.log(`\n📝 ${outputs.kept.length} outputs in both runs, ${changed} of them with other content`);
+
+	const regressions = failures.added.length + skipped.added.length;
+	if (regressions === 0) {
+		console.log('✅ No new failures or skipped namespaces');
+	} else if (opts.failOnRegression) {
+		throw new Error(`${regressions} new failures or skipped template/namespace pairs since ${oldPath}`);
+	}
+}
+
+// `report merge -o FILE REPORTS...`: one report of a run split with --shard,
+// from the reports of all its shards. It fails when a shard is missing or
+// given twice, so a merged report always covers the whole run.
+export async function mergeRunReports(paths, opts = {}) {
+	if (!opts.output) {
+		throw new Error('report merge requires -o <FILE> for the merged report');
+	}
+	if (paths.length === 0) {
+		throw new Error('report merge requires the run reports of all shards, e.g. report merge -o report.json shard-*.json');
+	}
+	const reports = [];
+	for (const path of paths) {
+		const report = await readRunReport(path);
+		if (!report.shard) {
+			throw new Error(`'${path}' is not the report of a shard; write the reports of a run with --shard <INDEX>/<COUNT> --report <FILE>`);
+		}
+		reports.push({ path, report, index: Number(report.shard.split('/')[0]), count: Number(report.shard.split('/')[1]) });
+	}
+	const count = reports[0].count;
+	const others = reports.find(entry => entry.count !== count);
+	if (others) {
+		throw new Error(`'${others.path}' is shard ${others.report.shard}, but '${reports[0].path}' is of a run with ${count} shards`);
+	}
+	for (const entry of reports) {
+		const twice = reports.find(other => other !== entry && other.index === entry.index);
+		if (twice) {
+			throw new Error(`Shard ${entry.report.shard} is in both '${entry.path}' and '${twice.path}'`);
+		}
+	}
+	const missing = Array.from({ length: count }, (_, i) => `${i + 1}/${count}`).filter(shard => !reports.some(entry => entry.report.shard === shard));
+	if (missing.length > 0) {
+		throw new Error(`The reports of shards ${missing.join(', ')} are missing`);
+	}
+
+	reports.sort((a, b) => a.index - b.index);
+	const outputs = reports.flatMap(({ report }) => report.outputs);
+	const writtenBy = new Map();
+	for (const { path, report } of reports) {
+		for (const output of report.outputs) {
+			if (writtenBy.has(output.path)) {
+				throw new Error(`'${output.path}' is an output of both '${writtenBy.get(output.path)}' and '${path}'; were the shards run with the same config?`);
+			}
+			writtenBy.set(output.path, path);
+		}
+	}
+	const failures = reports.flatMap(({ report }) => report.failures);
+	const content = {
+		version: REPORT_VERSION,
+		started: reports.map(({ report }) => report.started).sort()[0],
+		finished: reports.map(({ report }) => report.finished).sort().at(-1),
+		status: reports.some(({ report }) => report.status === 'failed') ? 'failed' : failures.length > 0 ? 'partial' : 'ok',
+		shards: count,
+		outputs,
+		skipped: reports.flatMap(({ report }) => report.skipped),
+		sources: reports.flatMap(({ report }) => report.sources ?? []),
+		failures,
+	};
+	await fs.writeFile(opts.output, `${JSON.stringify(content, null, 2)}\n`, 'utf-8');
+	console.log(`📝 Merged the reports of ${count} shards (${outputs.length} outputs, ${content.skipped.length} skipped, ${failures.length} failures): ${opts.output}`);
+}
+
+async function readRunReport(path) {
+	let report;
+	try {
+		report = JSON.parse(await fs.readFile(path, 'utf-8'));
+	} catch (err) {
+		throw new Error(`Could not read run report '${path}': ${err.message}`);
+	}
+	if (!report || report.version !== REPORT_VERSION || !['outputs', 'skipped', 'failures'].every(list => Array.isArray(report[list]))) {
+		throw new Error(`'${path}' is not a sopsify run report (version ${REPORT_VERSION}), write one with --report`);
+	}
+	return report;
+}
+
+function pairKey({ cluster, namespace, template }) {
+	return [cluster, namespace ?? 'global', template].filter(Boolean).join('/');
+}
+
+// Failures of hooks and of the run have no template, those before any
+// cluster was processed no cluster either
+function failureKey({ stage, cluster, namespace, template }) {
+	return `${stage} ${[cluster, namespace, template].filter(Boolean).join('/') || '(run)'}`;
+}
+
+function compare(before, after, key) {
+	const was = new Map(before.map(entry => [key(entry), entry]));
+	const now = new Map(after.map(entry => [key(entry), entry]));
+	return {
+		added: after.filter(entry => !was.has(key(entry))),
+		removed: before.filter(entry => !now.has(key(entry))),
+		kept: after.filter(entry => was.has(key(entry))).map(entry => [was.get(key(entry)), entry]),
+	};
+}
+
+function section(title, entries, describe) {
+	if (entries.length === 0) {
+		return;
+	}
+	console.log(`\n${title} (${entries.length})`);
+	entries.forEach(entry => console.log(`   ${describe(entry)}`));
+}
@@ -0,0 +1,145 @@
+import fs from 'fs/promises';
+import { join } from 'path';
+import { execFileSync } from 'child_process';
+import { loadConfig, isSecretEntry } from './sopsify.js';
+import { loadNamespaceVars, NAMESPACE_VARS_DIR } from './namespace-vars.js';
+import { configFile } from './config.js';
+
+const MIN_SUBSTRING_LENGTH = 6;
+const MIN_ENTROPY_LENGTH = 20;
+
+// Scans the output tree (and optionally staged git files) for plaintext that
+// should have been encrypted: values known from .sopsify.yaml (also base64
+// encoded, keys marked `secret: false` excluded) and high-entropy strings. Encrypted values (`ENC[...]`) and the
+// `sops:` metadata block are ignored.
+export async function scan(opts) {
+	const config = await loadConfig();
+	const knownValues = collectKnownValues(config, await loadNamespaceVars());
+	const threshold = opts.entropy === false ? Infinity : Number(opts.entropyThreshold);
+
+	const files = [];
+	for (const path of await listFiles(opts.dir)) {
+		files.push({ path, content: await fs.readFile(path, 'utf-8') });
+	}
+	if (opts.staged) {
+		files.push(...stagedFiles());
+	}
+
+	console.log(`🔎 Scanning ${files.length} files for plaintext secrets`);
+
+	let findings = 0;
+	for (const { path, content } of files) {
+		for (const finding of scanContent(content, knownValues, threshold)) {
+			findings++;
+			console.log(`   ❌ ${path}:${finding.line}: ${finding.reason}`);
+		}
+	}
+
+	if (findings > 0) {
+		throw new Error(`Found ${findings} potential plaintext secrets`);
+	}
+	console.log('✅ No plaintext secrets found');
+}
+
+function collectKnownValues(config, namespaceVars) {
+	const entries = [];
+	for (const { values } of namespaceVars) {
+		entries.push(...Object.entries(values));
+	}
+	for (const clusterItem of config.sopsify) {
+		for (const templateDef of Object.values(clusterItem)[0]) {
+			entries.push(...(templateDef.values ?? []).filter(isSecretEntry).map(entry => [entry.key, entry.value]));
+		}
+	}
+	return knownValues(entries);
+}
+
+// Expands `[key, value]` pairs into the plain and base64 encoded forms to look for
+export function knownValues(entries) {
+	const known = [];
+	for (const [key, rawValue] of entries) {
+		if (rawValue === undefined || rawValue === null) {
+			continue;
+		}
+		const value = String(rawValue);
+		known.push({ key, value });
+		known.push({ key, value: Buffer.from(value).toString('base64'), encoded: true });
+	}
+	return known;
+}
+
+export function scanContent(content, knownValues, threshold = Infinity) {
+	const findings = [];
+	const lines = content.split('\n');
+	let inSopsBlock = false;
+
+	lines.forEach((rawLine, index) => {
+		// Top-level keys start at column 0; the `sops:` block ends with the next one
+		if (/^\S/.test(rawLine)) {
+			inSopsBlock = /^sops:/.test(rawLine);
+		}
+		if (inSopsBlock) {
+			return;
+		}
+
+		const line = rawLine.replace(/ENC\[[^\]]*\]/g, '');
+		const scalar = line.replace(/^\s*(-\s+)?[^:]*:\s*/, '').replace(/^["']|["']$/g, '').trim();
+
+		for (const { key, value, encoded } of knownValues) {
+			const matches = value.length >= MIN_SUBSTRING_LENGTH ? line.includes(value) : scalar === value;
+			if (matches) {
+				findings.push({ line: index + 1, reason: `value of key '${key}'${encoded ? ' (base64)' : ''} in plaintext` });
+				return;
+			}
+		}
+
+		for (const token of line.match(new RegExp(`[A-Za-z0-9+/=_-]{${MIN_ENTROPY_LENGTH},}`, 'g')) || []) {
+			if (entropy(token) >= threshold) {
+				findings.push({ line: index + 1, reason: `high-entropy string (${entropy(token).toFixed(2)} bits/char)` });
+				return;
+			}
+		}
+	});
+
+	return findings;
+}
+
+function entropy(token) {
+	const counts = {};
+	for (const char of token) {
+		counts[char] = (counts[char] || 0) + 1;
+	}
+	return Object.values(counts).reduce((sum, count) => {
+		const p = count / token.length;
+		return sum - p * Math.log2(p);
+	}, 0);
+}
+
+async function listFiles(dir) {
+	let entries;
+	try {
+		entries = await fs.readdir(dir, { withFileTypes: true });
+	} catch {
+		return [];
+	}
+
+	const files = [];
+	for (const entry of entries) {
+		const path = join(dir, entry.name);
+		if (entry.isDirectory()) {
+			files.push(...await listFiles(path));
+		} else if (entry.isFile()) {
+			files.push(path);
+		}
+	}
+	return files;
+}
+
+// The config and namespace variable files hold plaintext values by design and are not scanned
+function stagedFiles() {
+	const git = args => execFileSync('git', args, { stdio: 'pipe', encoding: 'utf-8' });
+	return git(['diff', '--cached', '--name-only', '--diff-filter=ACM'])
+		.split('\n')
+		.filter(path => path && !path.endsWith(configFile()) && !path.startsWith(`${NAMESPACE_VARS_DIR}/`))
+		.map(path => ({ path: `${path} (staged)`, content: git(['show', `:${path}`]) }));
+}
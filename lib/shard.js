@@ -0,0 +1,27 @@
+import { createHash } from 'crypto';
+
+// `--shard <INDEX>/<COUNT>` splits the template/namespace pairs of a run
+// across CI jobs, e.g. `--shard 2/5` in the second of five. A pair belongs to
+// the shard given by the hash of its cluster, namespace and template, so every
+// job computes the same split without talking to the others, and adding a
+// namespace or template moves no other pair to another shard. Global
+// templates are pairs of their own.
+export function shardSelector(value) {
+	if (value === undefined) {
+		return undefined;
+	}
+	const match = /^(\d+)\/(\d+)$/.exec(String(value));
+	const [index, count] = match ? [Number(match[1]), Number(match[2])] : [];
+	if (!match || count < 1 || index < 1 || index > count) {
+		throw new Error(`--shard must be <INDEX>/<COUNT> with 1 <= INDEX <= COUNT, e.g. 2/5, got '${value}'`);
+	}
+	return {
+		index,
+		count,
+		label: `${index}/${count}`,
+		includes(cluster, namespace, template) {
+			const hash = createHash('sha256').update([cluster, namespace ?? '', template].join('\0')).digest();
+			return hash.readUInt32BE(0) % count === index - 1;
+		},
+	};
+}
@@ -0,0 +1,15 @@
+import { dumpDocuments } from './sopsify.js';
+
+// With --variable-snapshots every namespace also gets an encrypted
+// `variables.enc.yaml` holding the values its outputs were rendered with, per
+// template, to audit later which values produced a set of outputs. It lives
+// in `clusters/<cluster>/snapshots/<namespace>/` (or the namespace's output
+// root), away from the manifests a GitOps tool applies. The values sit below
+// `stringData`, so the usual `encrypted_regex: ^(data|stringData)$` covers
+// them; `metadata` stays readable.
+export const SNAPSHOT_FILE = 'variables.yaml';
+
+export function snapshotManifest(cluster, namespace, templates) {
+	const stringData = Object.fromEntries(Object.entries(templates).sort(([a], [b]) => a.localeCompare(b)));
+	return dumpDocuments([{ metadata: { cluster, namespace }, stringData }]);
+}
@@ -0,0 +1,312 @@
+import crypto from 'crypto';
+import { existsSync, readFileSync, writeFileSync } from 'fs';
+import yaml from 'js-yaml';
+import { parseRecipient, parseIdentities, encryptArmored, decryptArmored } from './age.js';
+import { defaultAgeKeyFile } from './permissions.js';
+
+// Reads and writes sops files (YAML, AES256-GCM values, data key encrypted
+// for age recipients) in-process, so no sops binary is needed. The output is
+// what `sops -e` writes for the same creation rule and can be decrypted and
+// edited with sops. Only age recipients are supported: a creation rule with
+// KMS, PGP, Vault or key groups needs the sops backend.
+const SOPS_VERSION = '3.9.0';
+const SOPS_CONFIG = '.sops.yaml';
+const OTHER_KEY_TYPES = ['kms', 'pgp', 'gcp_kms', 'azure_keyvault', 'hc_vault_transit_uri', 'key_groups'];
+const SELECTION_SETTINGS = ['unencrypted_suffix', 'encrypted_suffix', 'unencrypted_regex', 'encrypted_regex'];
+const ENCRYPTED_VALUE = /^ENC\[AES256_GCM,data:(.*),iv:(.*),tag:(.*),type:(.*)\]$/;
+
+export function builtinBackend() {
+	return {
+		name: 'rops',
+		format: 'sops',
+		suffix: '.enc.yaml',
+		engines: ['yaml'],
+		requiredFiles: [SOPS_CONFIG],
+		check() {
+			const ciphers = crypto.getCiphers();
+			if (!ciphers.includes('aes-256-gcm') || !ciphers.includes('chacha20-poly1305')) {
+				throw new Error('The rops backend needs AES-256-GCM and ChaCha20-Poly1305, which this Node.js build does not provide');
+			}
+			creationRules();
+		},
+		// The creation rule is matched against the output path, as sops
+		// does with --filename-override; `keys` replace its recipients,
+		// `encryptedRegex` what it selects for encryption
+		async encrypt(path, outputPath = path, env, { keys, encryptedRegex } = {}) {
+			const rule = creationRule(outputPath, keys);
+			if (encryptedRegex !== undefined) {
+				rule.selection = { encrypted_regex: encryptedRegex };
+			}
+			writeFileSync(path, encryptDocuments(yaml.loadAll(readFileSync(path, 'utf-8')), rule));
+		},
+		decrypt(path, env) {
+			return decryptContent(readFileSync(path, 'utf-8'), env, path);
+		},
+		decryptContent(content, env) {
+			return decryptContent(content, env, 'content');
+		},
+	};
+}
+
+function creationRules() {
+	const config = yaml.load(readFileSync(SOPS_CONFIG, 'utf-8')) || {};
+	if (!Array.isArray(config.creation_rules) || config.creation_rules.length === 0) {
+		throw new Error(`No creation_rules in ${SOPS_CONFIG}`);
+	}
+	return config.creation_rules;
+}
+
+// The first rule without `path_regex` or whose `path_regex` matches, as sops.
+// With `keys` (encryption_keys) the rule only selects what is encrypted, and
+// is not needed at all.
+function creationRule(path, keys) {
+	const rule = creationRules().find(candidate => !candidate.path_regex || goRegExp(candidate.path_regex).test(path));
+	if (keys) {
+		const others = Object.keys(keys).filter(type => type !== 'age');
+		if (others.length > 0) {
+			throw new Error(`encryption_keys for '${path}' use ${others.join(', ')}; the rops backend only encrypts for age, use --backend sops`);
+		}
+	} else if (!rule) {
+		throw new Error(`No creation rule in ${SOPS_CONFIG} matches '${path}'`);
+	} else {
+		const others = OTHER_KEY_TYPES.filter(type => rule[type] !== undefined && rule[type] !== '' && rule[type] !== null);
+		if (others.length > 0) {
+			throw new Error(`The creation rule for '${path}' uses ${others.join(', ')}; the rops backend only encrypts for age, use --backend sops`);
+		}
+	}
+	const recipients = (keys ? keys.age : Array.isArray(rule.age) ? rule.age : String(rule.age ?? '').split(','))
+		.map(recipient => recipient.trim())
+		.filter(Boolean);
+	if (recipients.length === 0) {
+		throw new Error(`The creation rule for '${path}' has no age recipients`);
+	}
+
+	const selection = Object.fromEntries(SELECTION_SETTINGS.filter(name => rule && rule[name] !== undefined).map(name => [name, rule[name]]));
+	if (Object.keys(selection).length > 1) {
+		throw new Error(`The creation rule for '${path}' sets more than one of: ${SELECTION_SETTINGS.join(', ')}`);
+	}
+	// sops' default: everything but keys ending in _unencrypted
+	return { recipients, selection: Object.keys(selection).length > 0 ? selection : { unencrypted_suffix: '_unencrypted' } };
+}
+
+// The MAC covers the values in the order they are written, which for the
+// dumped objects is their own order
+function encryptDocuments(documents, { recipients, selection }) {
+	const dataKey = crypto.randomBytes(32);
+	const hash = crypto.createHash('sha512');
+	const encrypted = documents.map(document => walk(document, [], (value, path) => {
+		const [plaintext, type] = toBytes(value, path);
+		hash.update(macBytes(value, path));
+		return isEncrypted(path, selection) ? encryptValue(plaintext, type, dataKey, `${path.join(':')}:`) : value;
+	}));
+
+	const lastmodified = new Date().toISOString().replace(/\.\d+Z$/, 'Z');
+	const metadata = {
+		age: recipients.map(recipient => ({ recipient, enc: encryptArmored(dataKey, [parseRecipient(recipient)]) })),
+		lastmodified,
+		mac: encryptValue(Buffer.from(hash.digest('hex').toUpperCase()), 'str', dataKey, lastmodified),
+		...selection,
+		version: SOPS_VERSION,
+	};
+	// Multi-document files carry the metadata in every document
+	return encrypted.map(document => yaml.dump({ ...document, sops: metadata })).join('---\n');
+}
+
+function decryptContent(content, env, what) {
+	const documents = loadDocuments(content);
+	const metadata = documents[0] && documents[0].document.sops;
+	if (!metadata) {
+		throw new Error(`${what} has no sops metadata`);
+	}
+
+	const identities = ageIdentities(env);
+	const dataKey = (metadata.age || []).map(key => decryptArmored(key.enc, identities)).find(Boolean);
+	if (!dataKey) {
+		throw new Error(`None of the age identities ($SOPS_AGE_KEY, $SOPS_AGE_KEY_FILE, ${defaultAgeKeyFile()}) can decrypt ${what}`);
+	}
+
+	const selection = Object.fromEntries(SELECTION_SETTINGS.filter(name => metadata[name] !== undefined && metadata[name] !== '').map(name => [name, metadata[name]]));
+	const hash = crypto.createHash('sha512');
+	const decrypted = documents.map(({ document: { sops, ...document }, node }) => walk(document, [], (value, path) => {
+		const encrypted = isEncrypted(path, selection);
+		const plain = encrypted && typeof value === 'string' && value !== '' ? decryptValue(value, dataKey, `${path.join(':')}:`) : value;
+		if (encrypted || !metadata.mac_only_encrypted) {
+			hash.update(macBytes(plain, path));
+		}
+		return plain;
+	}, withoutKey(node, 'sops')));
+
+	const mac = decryptValue(metadata.mac, dataKey, metadata.lastmodified);
+	if (mac !== hash.digest('hex').toUpperCase()) {
+		throw new Error(`MAC mismatch in ${what}: the file was modified without sops`);
+	}
+	return decrypted.map(document => yaml.dump(document)).join('---\n');
+}
+
+// $SOPS_AGE_KEY, $SOPS_AGE_KEY_FILE and the default key file, as sops;
+// `env` (sops_env) wins over the environment
+function ageIdentities(env = {}) {
+	const variables = { ...process.env, ...env };
+	const contents = [variables.SOPS_AGE_KEY];
+	for (const file of [variables.SOPS_AGE_KEY_FILE, defaultAgeKeyFile()]) {
+		if (file && existsSync(file)) {
+			contents.push(readFileSync(file, 'utf-8'));
+		}
+	}
+	return contents.filter(Boolean).flatMap(parseIdentities);
+}
+
+// The non-empty documents of a YAML stream, each with the tree of its nodes
+// in document order (see orderedEntries); files sops wrote are hashed in
+// that order
+function loadDocuments(content) {
+	const stack = [{ children: [] }];
+	const listener = (event, state) => {
+		if (event === 'open') {
+			stack.push({ children: [] });
+			return;
+		}
+		const node = stack.pop();
+		node.kind = state.kind;
+		node.result = state.result;
+		stack[stack.length - 1].children.push(node);
+	};
+	const documents = yaml.loadAll(content, null, { listener });
+	const roots = stack[0].children;
+	return documents
+		.map((document, index) => ({ document, node: roots.length === documents.length ? roots[index] : undefined }))
+		.filter(({ document }) => document !== null && document !== undefined);
+}
+
+// Applies `fn` to every scalar with the path of keys leading to it, in
+// document order; list items keep the path of their list, as in sops
+function walk(value, path, fn, node) {
+	if (Array.isArray(value)) {
+		const items = node && node.kind === 'sequence' && node.children.length === value.length ? node.children : [];
+		return value.map((item, index) => walk(item, path, fn, items[index]));
+	}
+	if (value && typeof value === 'object' && !(value instanceof Date)) {
+		return Object.fromEntries(orderedEntries(value, node).map(([key, item, child]) => [key, walk(item, [...path, key], fn, child)]));
+	}
+	return fn(value, path);
+}
+
+// js-yaml builds plain objects, which list integer-like keys (`"80"`) before
+// the others. The loader's node events keep the order keys were read in: a
+// mapping's nodes alternate key and value. Without them (merge keys, aliases)
+// the object's order is used.
+function orderedEntries(value, node) {
+	const keys = node && node.kind === 'mapping' ? node.children.filter((child, index) => index % 2 === 0).map(child => String(child.result)) : [];
+	const complete = node !== undefined && node.children.length === 2 * keys.length && keys.length === Object.keys(value).length
+		&& new Set(keys).size === keys.length && keys.every(key => Object.hasOwn(value, key));
+	if (!complete) {
+		return Object.entries(value).map(([key, item]) => [key, item, undefined]);
+	}
+	return keys.map((key, index) => [key, value[key], node.children[2 * index + 1]]);
+}
+
+// The node of a mapping without one of its keys, e.g. the sops metadata
+function withoutKey(node, key) {
+	if (!node || node.kind !== 'mapping') {
+		return node;
+	}
+	const index = node.children.findIndex((child, i) => i % 2 === 0 && String(child.result) === key);
+	return index < 0 ? node : { ...node, children: node.children.filter((child, i) => i !== index && i !== index + 1) };
+}
+
+function isEncrypted(path, { unencrypted_suffix, encrypted_suffix, unencrypted_regex, encrypted_regex }) {
+	if (unencrypted_suffix) {
+		return !path.some(key => key.endsWith(unencrypted_suffix));
+	}
+	if (encrypted_suffix) {
+		return path.some(key => key.endsWith(encrypted_suffix));
+	}
+	if (unencrypted_regex) {
+		return !path.some(key => goRegExp(unencrypted_regex).test(key));
+	}
+	if (encrypted_regex) {
+		return path.some(key => goRegExp(encrypted_regex).test(key));
+	}
+	return true;
+}
+
+// Values as sops encrypts them: Go's strconv formatting of ints, floats
+// (no exponent) and bools
+function toBytes(value, path) {
+	switch (typeof value) {
+		case 'string':
+			return [Buffer.from(value), 'str'];
+		case 'number':
+			return Number.isInteger(value) ? [Buffer.from(String(value)), 'int'] : [Buffer.from(formatFloat(value)), 'float'];
+		case 'boolean':
+			return [Buffer.from(String(value)), 'bool'];
+		default:
+			throw new Error(`Value of '${path.join('.')}' has a type the rops backend cannot encrypt (${value === null ? 'null' : typeof value}); quote it`);
+	}
+}
+
+// Values as sops adds them to the MAC (`ToBytes`), which writes bools as
+// `True`/`False`. Comments are not part of the MAC.
+function macBytes(value, path) {
+	if (typeof value === 'boolean') {
+		return Buffer.from(value ? 'True' : 'False');
+	}
+	return toBytes(value, path)[0];
+}
+
+// strconv.FormatFloat(value, 'f', -1, 64): the shortest digits, as JavaScript
+// prints them, without an exponent
+function formatFloat(value) {
+	const text = String(value);
+	const match = text.match(/^(-?)(\d)(?:\.(\d+))?e([+-]\d+)$/);
+	if (!match) {
+		return text;
+	}
+	const [, sign, first, rest = '', exponent] = match;
+	const digits = first + rest;
+	const point = 1 + Number(exponent);
+	if (point <= 0) {
+		return `${sign}0.${'0'.repeat(-point)}${digits}`;
+	}
+	return point >= digits.length ? `${sign}${digits}${'0'.repeat(point - digits.length)}` : `${sign}${digits.slice(0, point)}.${digits.slice(point)}`;
+}
+
+// sops leaves empty values as they are
+function encryptValue(plaintext, type, dataKey, additionalData) {
+	if (plaintext.length === 0 && type === 'str') {
+		return '';
+	}
+	const iv = crypto.randomBytes(32);
+	const cipher = crypto.createCipheriv('aes-256-gcm', dataKey, iv);
+	cipher.setAAD(Buffer.from(additionalData));
+	const data = Buffer.concat([cipher.update(plaintext), cipher.final()]);
+	return `ENC[AES256_GCM,data:${data.toString('base64')},iv:${iv.toString('base64')},tag:${cipher.getAuthTag().toString('base64')},type:${type}]`;
+}
+
+function decryptValue(value, dataKey, additionalData) {
+	const match = ENCRYPTED_VALUE.exec(value);
+	if (!match) {
+		throw new Error(`'${value.slice(0, 20)}...' is not a sops encrypted value`);
+	}
+	const [, data, iv, tag, type] = match;
+	const decipher = crypto.createDecipheriv('aes-256-gcm', dataKey, Buffer.from(iv, 'base64'));
+	decipher.setAAD(Buffer.from(additionalData));
+	decipher.setAuthTag(Buffer.from(tag, 'base64'));
+	const plaintext = Buffer.concat([decipher.update(Buffer.from(data, 'base64')), decipher.final()]).toString();
+	switch (type) {
+		case 'int':
+			return parseInt(plaintext, 10);
+		case 'float':
+			return parseFloat(plaintext);
+		case 'bool':
+			return plaintext.toLowerCase() === 'true';
+		default:
+			return plaintext;
+	}
+}
+
+// Go regexps as used in .sops.yaml; a leading (?i) becomes the i flag
+function goRegExp(pattern) {
+	const insensitive = pattern.startsWith('(?i)');
+	return new RegExp(insensitive ? pattern.slice(4) : pattern, insensitive ? 'i' : '');
+}
@@ -0,0 +1,36 @@
+import { expandBuiltins } from './sopsify.js';
+import { clusterEnvironment } from './environments.js';
+
+const VARIABLE_NAME = /^[A-Za-z_][A-Za-z0-9_]*$/;
+
+// `sops_env` in .sopsify.yaml sets environment variables for the backend
+// invocations of a namespace, e.g. its own SOPS_AGE_KEY_FILE or AWS_PROFILE in
+// multi-tenant repositories. Keys are a namespace or `<cluster>/<namespace>`;
+// both apply, the cluster-specific one wins per variable. Values may use
+// ${cluster}, ${environment}, ${namespace} and ${env:NAME}. Returns undefined when nothing is
+// configured, so the backend inherits the environment as it is.
+export function sopsEnv(config, cluster, namespace) {
+	const settings = config.sops_env || {};
+	const layers = [[namespace, settings[namespace]], [`${cluster}/${namespace}`, settings[`${cluster}/${namespace}`]]]
+		.filter(([, variables]) => variables !== undefined);
+	if (layers.length === 0) {
+		return undefined;
+	}
+
+	const env = {};
+	for (const [key, variables] of layers) {
+		if (!variables || typeof variables !== 'object' || Array.isArray(variables)) {
+			throw new Error(`'sops_env.${key}' in .sopsify.yaml must be a map of variable names to values`);
+		}
+		for (const [name, value] of Object.entries(variables)) {
+			if (!VARIABLE_NAME.test(name)) {
+				throw new Error(`'sops_env.${key}' in .sopsify.yaml sets '${name}', which is not a valid environment variable name`);
+			}
+			if (value === null || typeof value === 'object') {
+				throw new Error(`'sops_env.${key}.${name}' in .sopsify.yaml must be a string, number or boolean`);
+			}
+			env[name] = String(expandBuiltins(String(value), { cluster, environment: clusterEnvironment(config, cluster), namespace }));
+		}
+	}
+	return env;
+}
@@ -1,23 +1,274 @@
 import fs from 'fs/promises';
 import yaml from 'js-yaml';
-import { join } from 'path';
-import { execSync } from 'child_process';
+import { join, dirname, basename, normalize, isAbsolute, resolve, sep } from 'path';
+import { createHash } from 'crypto';
+import { writeGitlabDotenv } from './gitlab-dotenv.js';
+import { getBackend } from './backends.js';
+import { listClusterNamespaces, serverDryRun } from './kubernetes.js';
+import { resolveValueSources, useSourceLimits } from './sources/index.js';
+import { reportSourceCalls } from './sources/limits.js';
+import { assertPlaintextPathIgnored } from './git.js';
+import { openAuditLog, outputRecipients } from './audit.js';
+import { knownValues, scanContent } from './scan.js';
+import { openRotationState } from './rotation.js';
+import { checkPermissions, defaultKeyFiles } from './permissions.js';
+import { createStaging, temporaryPlaintextPath, findLeftovers } from './staging.js';
+import { recipientProblems, roundTripProblem } from './verify.js';
+import { createDecryptCache } from './decrypt-cache.js';
+import { resolveExtends } from './inheritance.js';
+import { isArchive, readArchive } from './archives.js';
+import { loadNamespaceVars, withNamespaceVars } from './namespace-vars.js';
+import { isRuleEntry, matchRule } from './rules.js';
+import { outputDirs, overriddenOutputDirs, globalOutputDir } from './output-roots.js';
+import { openTargets, publishOutputs } from './targets.js';
+import { sopsEnv } from './sops-env.js';
+import { encryptionKeys, encryptedFor } from './encryption-keys.js';
+import { applyTransforms } from './transforms.js';
+import { keyType, typeProblem } from './value-types.js';
+import { maxSecretBytes, splitDocuments, documentBytes, KUBERNETES_OBJECT_LIMIT } from './chunking.js';
+import { configFile } from './config.js';
+import { createHooks, reportHooks } from './hooks.js';
+import { runPool, positiveInt } from './pool.js';
+import { SNAPSHOT_FILE, snapshotManifest } from './snapshots.js';
+import { namespaceSelector } from './namespace-select.js';
+import { filesBelow, pathMatcher } from './files.js';
+import { groupBackends, groupSettings, groupEngine } from './template-groups.js';
+import { templateEngine, outputFileName } from './engines.js';
+import { createRunReport, writeRunReport } from './run-report.js';
+import { addKeyAliases } from './key-aliases.js';
+import { checkConfig, recordEntryPaths } from './config-schema.js';
+import { duplicateKeys, describeDuplicate, keepLastEntries } from './duplicate-keys.js';
+import { loadMatrix, applyMatrix } from './matrix.js';
+import { expandNamespaceGroups } from './namespace-groups.js';
+import { expandNamespacePatterns } from './namespace-patterns.js';
+import { createProvenance } from './provenance.js';
+import { shardSelector } from './shard.js';
+import { projectStateFile } from './user-config.js';
+import { applyDefaults, isDefaultEntry, usedDefaults } from './defaults.js';
+import { deprecationWarnings } from './deprecation.js';
+import { expandEnvironments, clusterEnvironment } from './environments.js';
 
 export async function main(options = {}) {
 	await run(options);
 }
 
 export async function run(opts) {
-	const templatesPath = opts.templates;
+	if (opts.check) {
+		return checkUpToDate(opts);
+	}
+	if (!opts.report) {
+		return render(opts);
+	}
+	const report = createRunReport();
+	try {
+		await render(opts, report);
+	} catch (err) {
+		await writeRunReport(opts.report, report, err);
+		throw err;
+	}
+	await writeRunReport(opts.report, report);
+	console.log(`\n📝 Run report written: ${opts.report}`);
+}
 
+async function render(opts, report) {
+	const templateFolders = [].concat(opts.templates ?? []);
+	const templateFiles = [].concat(opts.file ?? []);
+	if (templateFolders.length === 0 && templateFiles.length === 0) {
+		throw new Error('Pass the templates to render with -t <FOLDER> and/or -f <FILE>');
+	}
+
+	const backend = getBackend(opts);
+
+	// Render-only runs (`--no-encrypt`) never call the backend, e.g. in CI without keys
+	const encrypt = opts.encrypt !== false;
 	console.log('🔄 Running pre-checks...');
-	await checkRequiredFiles(['.sops.yaml', '.sopsify.yaml']);
-	checkSopsInstalled();
+	await checkRequiredFiles([...(encrypt ? backend.requiredFiles : []), configFile()]);
+	if (encrypt) {
+		await backend.check();
+		console.log(`   ✅ ${backend.name} is installed`);
+	} else {
+		console.log(`   ⏭️ Render-only: outputs are written unencrypted, ${backend.name} is not used`);
+	}
+
+	const sopsifyConfig = await loadConfig(configFile(), opts);
+	useSourceLimits(sopsifyConfig);
+	const onAllSkipped = allSkippedPolicy(sopsifyConfig, opts);
+	// Template groups may encrypt with other backends than the run
+	const groupEncryptors = new Map();
+	for (const name of encrypt ? groupBackends(sopsifyConfig).filter(name => name !== backend.name) : []) {
+		const groupBackend = getBackend({ ...opts, encryptor: undefined, backend: name });
+		await checkRequiredFiles(groupBackend.requiredFiles);
+		await groupBackend.check();
+		console.log(`   ✅ ${groupBackend.name} is installed (template groups)`);
+		groupEncryptors.set(name, { backend: groupBackend, decryptCache: createDecryptCache(groupBackend) });
+	}
+	const namespaceVars = await loadNamespaceVars();
+	await reportLeftovers(sopsifyConfig, opts.cleanTemp);
+	await checkPermissions(
+		[configFile(), ...namespaceVars.map(v => v.file), opts.matrix, opts.vaultPasswordFile, ...defaultKeyFiles()],
+		{ severity: (sopsifyConfig.permissions || {}).severity, fix: opts.fixPerms },
+	);
+
+	// Plaintext is never published, nor does it count as a rotation
+	const targets = encrypt ? await openTargets(sopsifyConfig) : [];
+
+	let namespaceFilter;
+	if (opts.namespacesFromCluster) {
+		namespaceFilter = listClusterNamespaces(opts);
+		const selector = opts.selector ? ` matching '${opts.selector}'` : '';
+		console.log(`   ✅ ${namespaceFilter.size} namespaces found in the live cluster${selector}`);
+	}
+	const selection = namespaceSelector(opts.namespace);
+	if (selection) {
+		console.log(`   ✅ Only rendering namespaces matching: ${selection.patterns.join(', ')}`);
+	}
+	const shard = shardSelector(opts.shard);
+	if (shard) {
+		// A snapshot holds every template of a namespace, which may be in other shards
+		if (opts.variableSnapshots) {
+			throw new Error('--variable-snapshots cannot be combined with --shard');
+		}
+		console.log(`   ✅ Shard ${shard.label}: only rendering its share of the template/namespace pairs`);
+		if (report) {
+			report.shard = shard.label;
+		}
+	}
+	console.log('\n');
+
+	const templateContents = await loadTemplates(templateFolders, templateFiles, { include: opts.include, exclude: opts.exclude, config: sopsifyConfig });
+
+	// An `audit_log` without `path` is kept in the XDG state directory
+	const auditConfig = sopsifyConfig.audit_log || {};
+	const auditPath = opts.auditLog || auditConfig.path || (sopsifyConfig.audit_log ? projectStateFile('audit.jsonl') : undefined);
+	const audit = auditPath
+		? await openAuditLog({ path: auditPath, maxBytes: auditConfig.max_bytes, maxFiles: auditConfig.max_files })
+		: undefined;
+
+	const rotation = encrypt && sopsifyConfig.rotation ? await openRotationState(sopsifyConfig.rotation) : undefined;
+
+	const dryRun = opts.serverDryRun ? { kubeContext: opts.kubeContext, results: [] } : undefined;
+	const decryptCache = createDecryptCache(backend);
+	const hooks = createHooks(sopsifyConfig);
+	const namespaceOverrides = [];
+	const skipped = report ? report.skipped : [];
+	if (report) {
+		report.hookResults = hooks.results;
+		report.dryRunResults = dryRun ? dryRun.results : [];
+	}
+	let outputs;
+	try {
+		outputs = await processTemplates(templateContents, sopsifyConfig, { backend, decryptCache, groupEncryptors, namespaceFilter, selection, namespaceVars, recipients: sopsifyConfig.recipients, dryRun, audit, rotation, hooks, namespaceOverrides, skipped, report, shard, provenance: opts.provenance ? createProvenance() : undefined, opts });
+	} finally {
+		decryptCache.clear();
+		groupEncryptors.forEach(encryptor => encryptor.decryptCache.clear());
+	}
+
+	if (selection && selection.unmatched().length > 0) {
+		console.warn(`\n⚠️ --namespace matched no namespace: ${selection.unmatched().join(', ')}`);
+	}
+	if (dryRun) {
+		reportDryRun(dryRun.results);
+	}
+	reportNamespaceOverrides(namespaceOverrides);
+	reportSourceCalls();
+	if (rotation) {
+		await rotation.finish();
+	}
+
+	if (targets.length > 0) {
+		console.log('\n📤 Publishing to output targets');
+		await publishOutputs(targets, outputs.filter(output => !output.renderOnly));
+	}
+
+	if (opts.gitlabDotenv) {
+		await writeGitlabDotenv(opts.gitlabDotenv, outputs, hooks.results);
+		console.log(`\n📝 GitLab dotenv report written: ${opts.gitlabDotenv}`);
+	}
+	reportHooks(hooks.results);
+	reportAllSkipped(outputs, skipped, onAllSkipped);
+}
+
+// Renders what a run would write, without writing, encrypting or running
+// hooks: [{cluster, namespace, template, path, manifest, encrypted, env,
+// backend}] for `diff --render`. `manifest` is the plaintext of `path`.
+export async function previewOutputs(opts) {
+	const templateFolders = [].concat(opts.templates ?? []);
+	const templateFiles = [].concat(opts.file ?? []);
+	if (templateFolders.length === 0 && templateFiles.length === 0) {
+		throw new Error('Pass the templates to render with -t <FOLDER> and/or -f <FILE>');
+	}
+	const backend = getBackend(opts);
+	const config = await loadConfig(configFile(), { matrix: opts.matrix, allowDuplicateKeys: opts.allowDuplicateKeys });
+	useSourceLimits(config);
+	const groupEncryptors = new Map(groupBackends(config).filter(name => name !== backend.name)
+		.map(name => [name, { backend: getBackend({ ...opts, encryptor: undefined, backend: name }) }]));
+	const namespaceVars = await loadNamespaceVars();
+	const templates = await loadTemplates(templateFolders, templateFiles, { include: opts.include, exclude: opts.exclude, config });
+
+	const preview = [];
+	await processTemplates(templates, config, {
+		backend, groupEncryptors, selection: namespaceSelector(opts.namespace), shard: shardSelector(opts.shard), namespaceVars, hooks: createHooks({}), namespaceOverrides: [], preview, provenance: opts.provenance ? createProvenance() : undefined, opts,
+	});
+	return preview;
+}
+
+// Plaintext of a crashed run would otherwise linger below the output roots
+// unnoticed; `--clean-temp` removes it
+async function reportLeftovers(config, clean) {
+	const roots = [...new Set(['clusters', ...overriddenOutputDirs(config).map(({ dir }) => dir)])];
+	const leftovers = await findLeftovers(roots);
+	if (leftovers.length === 0) {
+		return;
+	}
+	const describe = { plaintext: 'temporary plaintext', staged: 'staged output', rename: 'staged rename folder' };
+	if (clean) {
+		for (const { path, kind } of leftovers) {
+			await fs.rm(path, { recursive: true, force: true });
+			console.log(`   🧹 Removed ${describe[kind]} of an earlier run: ${path}`);
+		}
+		return;
+	}
+	console.warn(`   ⚠️ ${leftovers.length} files of earlier runs were left behind (they may contain plaintext):`);
+	leftovers.forEach(({ path, kind }) => console.warn(`      - ${path} (${describe[kind]})`));
+	console.warn('   ⚠️ Run with --clean-temp to remove them');
+}
 
-	const sopsifyConfig = await loadYamlFile('.sopsify.yaml');
-	const templates = await loadTemplateFiles(templatesPath);
-	const templateContents = await readTemplates(templatesPath, templates);
-	await processTemplates(templateContents, sopsifyConfig);
+// `--check`: fails listing every output a run would write differently,
+// comparing the decrypted outputs in place with fresh renders. Nothing is
+// written, so CI can verify committed outputs match templates and config.
+async function checkUpToDate(opts) {
+	const outputs = await previewOutputs(opts);
+	const stale = [];
+	for (const output of outputs) {
+		let current;
+		try {
+			current = await fs.readFile(output.path, 'utf-8');
+		} catch {
+			stale.push(`${output.path} (missing)`);
+			continue;
+		}
+		if (!output.encrypted) {
+			if (current !== output.manifest) {
+				stale.push(`${output.path} (changed)`);
+			}
+			continue;
+		}
+		try {
+			const { engine } = output;
+			if (engine.dump(engine.parse(output.backend.decryptContent(current, output.env, engine.sopsType), output.path)) !== output.manifest) {
+				stale.push(`${output.path} (changed)`);
+			}
+		} catch (err) {
+			stale.push(`${output.path} (cannot be decrypted: ${err.message})`);
+		}
+	}
+
+	if (stale.length > 0) {
+		console.log(`\n📝 Out of date: ${stale.length}`);
+		stale.forEach(item => console.log(`   - ${item}`));
+		throw new Error(`${stale.length} of ${outputs.length} outputs are out of date; run sopsify without --check to regenerate them`);
+	}
+	console.log(`\n✅ All ${outputs.length} outputs are up to date`);
 }
 
 async function checkRequiredFiles(filePaths) {
@@ -27,38 +278,129 @@ async function checkRequiredFiles(filePaths) {
 	}
 }
 
-function checkSopsInstalled() {
-	try {
-		execSync('sops --version', { stdio: 'pipe' });
-		console.log("   ✅ sops is installed\n\n");
-	} catch {
-		throw new Error('sops is not installed or not in PATH');
+export async function loadYamlFile(path) {
+	const content = await fs.readFile(path, 'utf-8');
+	return yaml.load(content);
+}
+
+// Loads .sopsify.yaml (or another config file) and checks it against the
+// schema of config-schema.js. All problems are reported at once, each with
+// its line and column, e.g.
+// `.sopsify.yaml:12:11: sopsify[0].production[1].values[2].value: is empty`.
+// `environment:<name>` items are merged into the clusters of the environment,
+// the rows of a `matrix` are added as value entries, then namespace groups
+// (`group:prod`) and patterns (`team-*`) are expanded. Keys given more than
+// one value for a namespace fail as well, unless `allowDuplicateKeys`: then
+// the last entry wins. `defaults` are added to every template definition last.
+export async function loadConfig(path = configFile(), { allowDuplicateKeys = false, matrix } = {}) {
+	const text = await fs.readFile(path, 'utf-8');
+	const { config, problems } = checkConfig(text, path);
+	if (problems.length > 0) {
+		const lines = problems.map(({ where, message }) => `   ${where}: ${message}`);
+		throw new Error(`${problems.length === 1 ? 'Error' : `${problems.length} errors`} in '${path}':\n${lines.join('\n')}`);
 	}
+
+	const duplicates = await expandConfig(config, text, { path, matrix });
+	if (duplicates.length > 0 && !allowDuplicateKeys) {
+		const lines = duplicates.map(duplicate => `   ${duplicate}`);
+		throw new Error(`Keys with more than one value for a namespace in '${path}':\n${lines.join('\n')}\n   Remove all but one entry, or pass --allow-duplicate-keys to use the last one`);
+	}
+	for (const duplicate of duplicates) {
+		console.warn(`   ⚠️ ${duplicate}; using the last entry`);
+	}
+	return config;
 }
 
-async function loadYamlFile(path) {
-	const content = await fs.readFile(path, 'utf-8');
-	return yaml.load(content);
+// Expands a config that passed checkConfig in place, as loadConfig and lint
+// see it. Returns the keys given more than one value for a namespace, as
+// messages locating them in the config `text`; their last entry is kept.
+export async function expandConfig(config, text, { path = configFile(), matrix } = {}) {
+	recordEntryPaths(config);
+	expandEnvironments(config);
+	if (matrix) {
+		applyMatrix(config, await loadMatrix(matrix));
+	}
+	expandNamespaceGroups(config);
+	expandNamespacePatterns(config);
+	const duplicates = duplicateKeys(config);
+	keepLastEntries(duplicates);
+	applyDefaults(config);
+	return duplicates.map(duplicate => describeDuplicate(duplicate, text, path));
 }
 
-async function loadTemplateFiles(templateDir) {
-	const entries = await fs.readdir(templateDir, { withFileTypes: true });
-	const templates = entries
-		.filter(e => e.isFile())
-		.filter(e => /\.(ya?ml)$/.test(e.name));
+// Reads and validates the templates of one or more folders and single files
+// (`-t` and `-f`, both repeatable): path -> parsed documents. Templates in
+// subfolders are named by their path below the folder, e.g.
+// `apps/foo/deploy.yaml`, and keep that layout below the namespace's outputs;
+// single files by their file name. A file given twice is read once, two
+// different files with the same name are an error. `include` and `exclude`
+// globs select the files of the folders by their name. With the `config`,
+// templates of groups with another `engine` than yaml are read too. `quiet`
+// leaves out the progress lines, for commands printing reports to stdout.
+export async function loadTemplates(folders, files = [], { include = [], exclude = [], config, quiet = false } = {}) {
+	const included = [].concat(include).length > 0 ? pathMatcher(include) : () => true;
+	const excluded = pathMatcher(exclude);
+	const engineOf = name => (config ? groupEngine(config, name) : templateEngine());
+	const selected = name => engineOf(name).extension.test(name) && included(name) && !excluded(name);
+	const engines = new Map();
+	const paths = new Map();
+	const names = new Map();
+	// Templates of archives, by their path below the archive, e.g. `bundle.tar.gz/apps/db.yaml`
+	const archived = new Map();
+	const add = (path, name) => {
+		if (paths.has(resolve(path))) {
+			return;
+		}
+		if (names.has(name)) {
+			throw new Error(`Template '${name}' is given twice: '${names.get(name)}' and '${path}'`);
+		}
+		names.set(name, path);
+		paths.set(resolve(path), path);
+		engines.set(path, engineOf(name));
+	};
 
-	console.log(`✅ Templates read: ${templates.length}`);
-	return templates;
+	for (const folder of [].concat(folders ?? [])) {
+		if (isArchive(folder)) {
+			const entries = [...(await readArchive(folder)).entries()].sort(([a], [b]) => (a < b ? -1 : 1));
+			for (const [entry, content] of entries) {
+				const name = entry.split('/').join(sep);
+				if (selected(name)) {
+					archived.set(join(folder, name), content);
+					add(join(folder, name), name);
+				}
+			}
+			continue;
+		}
+		await fs.readdir(folder); // fails for a missing folder
+		(await filesBelow(folder))
+			.filter(selected)
+			.forEach(name => add(join(folder, name), name));
+	}
+	for (const file of [].concat(files ?? [])) {
+		const engine = engineOf(basename(file));
+		if (!engine.extension.test(basename(file))) {
+			throw new Error(`Template '${file}' must have a ${engine.description} extension`);
+		}
+		add(file, basename(file));
+	}
+
+	if (!quiet) {
+		console.log(`✅ Templates read: ${paths.size}`);
+		console.log('🔄 Checking Templates');
+	}
+	return readTemplates([...paths.values()], path => (archived.has(path) ? archived.get(path) : fs.readFile(path, 'utf-8')), engines);
 }
 
-async function readTemplates(templatePath, templates) {
-	console.log('🔄 Checking Templates');
+async function readTemplates(templates, read, engines) {
 	const files = {};
 
-	for (const template of templates) {
-		const fullPath = join(templatePath, template.name);
-		const content = await fs.readFile(fullPath, 'utf-8');
-		const parsed = yaml.load(content);
+	for (const fullPath of templates) {
+		const content = await read(fullPath);
+		const engine = engines.get(fullPath);
+		const parsed = [];
+		for (const document of engine.parse(content, fullPath)) {
+			parsed.push(engine.name === 'yaml' ? await resolveExtends(document, fullPath, [], read) : document);
+		}
 
 		validateTemplate(parsed, fullPath);
 		files[fullPath] = parsed;
@@ -67,156 +409,1063 @@ async function readTemplates(templatePath, templates) {
 	return files;
 }
 
-function validateTemplate(template, filePath) {
-	if (!template || !template.kind) {
+// A template is a Secret or ConfigMap, or a multi-document file containing at
+// least one of them next to other manifests
+function validateTemplate(documents, filePath) {
+	if (documents.length === 0 || documents.some(doc => !doc || !doc.kind)) {
 		throw new Error(`Error in '${filePath}': Missing 'kind'`);
 	}
-	if (template.kind.toLowerCase() !== 'secret') {
-		throw new Error(`Error in '${filePath}': Template is not of kind 'Secret'`);
+	if (!documents.some(doc => ['secret', 'configmap'].includes(String(doc.kind).toLowerCase()))) {
+		throw new Error(`Error in '${filePath}': Template is not of kind 'Secret' or 'ConfigMap'`);
+	}
+	for (const doc of documents) {
+		if (doc.required !== undefined && !(Array.isArray(doc.required) && doc.required.every(key => typeof key === 'string' && KEY_NAME.test(key)))) {
+			throw new Error(`Error in '${filePath}': 'required' must be a list of key names`);
+		}
+		if (doc.encrypt !== undefined && typeof doc.encrypt !== 'boolean') {
+			throw new Error(`Error in '${filePath}': 'encrypt' must be true or false`);
+		}
 	}
 }
 
-async function processTemplates(templates, config) {
+async function processTemplates(templates, config, context) {
 	const clusterConfigs = config.sopsify;
+	let outputs = [];
+	const staging = createStaging();
+	// Shared output roots are caught before anything is written; namespaces of
+	// `match` rules are only known while rendering
+	const outputOwners = new Map();
+	for (const { cluster, namespace, dir } of overriddenOutputDirs(config)) {
+		claimOutputDir(outputOwners, dir, `${cluster}/${namespace}`);
+	}
 
 	for (const clusterItem of clusterConfigs) {
 		const [clusterName, templateDefs] = Object.entries(clusterItem)[0];
+		validatePathSegment(clusterName, 'Cluster');
 		console.log(`\n🌍 Processing cluster: ${clusterName}`);
+		if (context.report) {
+			context.report.cluster = clusterName;
+		}
 
-		const clusterDir = join('clusters', clusterName);
 		try {
-			const stat = await fs.stat(clusterDir);
-			if (!stat.isDirectory()) {
-				throw new Error();
+			await processClusterTemplates(clusterName, templateDefs, templates, config, { ...context, staging, outputs, outputOwners });
+		} catch (err) {
+			await staging.rollback();
+			throw err;
+		}
+		// Rolled back outputs were never written; unchanged ones are still in place
+		const rolledBack = await staging.promote();
+		outputs = outputs.filter(output => output.unchanged || !rolledBack.includes(stagingKey(output.cluster, output.namespace)));
+		if (context.report) {
+			context.report.outputs = outputs;
+		}
+		// Outputs of groups with `encrypt: false` were not encrypted
+		if (context.opts.encrypt !== false) {
+			context.hooks.postEncrypt(clusterName, outputs.filter(output => !output.renderOnly));
+		}
+	}
+
+	if (context.report) {
+		context.report.cluster = undefined;
+	}
+	return outputs;
+}
+
+// Renders and encrypts all templates of one cluster. Outputs are staged and
+// only promoted by the caller once the whole cluster succeeded.
+async function processClusterTemplates(clusterName, templateDefs, templates, config, context) {
+	const { namespaceFilter, selection, namespaceVars, dryRun, rotation, hooks, staging, outputs, outputOwners, groupEncryptors, namespaceOverrides, skipped = [], report, shard, provenance, opts } = context;
+	let clusterDirChecked = false;
+	// Strict templates ignore `on_unresolved`; every unresolved placeholder of
+	// the cluster is reported at once and nothing of the cluster is written then
+	const strictFailures = [];
+	const writes = [];
+	// Render-only runs have nothing to snapshot, nor a way to encrypt it
+	const snapshots = opts.variableSnapshots && opts.encrypt !== false ? new Map() : undefined;
+
+	for (const templateDef of templateDefs) {
+		const templateName = templateDef.template;
+		if (snapshots && templateName === SNAPSHOT_FILE) {
+			throw new Error(`Template '${templateName}' of cluster '${clusterName}' has the name of the --variable-snapshots output; rename it`);
+		}
+		const group = groupSettings(config, templateName);
+		const engine = templateEngine(group.engine);
+		const encryptor = groupEncryptors?.get(group.backend);
+		const renderOnly = opts.encrypt === false || group.encrypt === false;
+		// Only Kubernetes manifests have a namespace to inject
+		const injectNamespace = opts.namespaceInjection !== false && group.namespace_injection !== false && engine.name === 'yaml';
+		const encryptedRegex = templateEncryptedRegex(config, templateDef);
+		const maxBytes = maxSecretBytes(config, templateDef);
+
+		validateTemplateName(templateName, clusterName, engine);
+		const templatePath = findTemplate(templates, templateName);
+		if (!templatePath) {
+			console.warn(`   ⚠️ Template file not found for: ${templateName}`);
+			continue;
+		}
+
+		const documents = templates[templatePath];
+		if (!documents.some(getKeyAccessor)) {
+			throw new Error(`Template '${templateName}' must contain 'data' or 'stringData'`);
+		}
+		const scope = templateScope(templateDef, documents, clusterName, opts);
+		const entries = usedDefaults(templateDef.values ?? [], [...templatePlaceholders(documents), ...requiredKeys(documents)]);
+		const values = withNamespaceVars(await resolveValueSources(entries), namespaceVars, clusterName);
+		reportValueSources(values, clusterName, templateName, report);
+
+		// Namespaces of other shards are neither rendered nor reported as unresolved
+		const inShard = ns => !shard || shard.includes(clusterName, ns, templateName);
+		const select = selection || shard ? ns => inShard(ns) && (!selection || selection.matches(ns)) : undefined;
+		const plan = namespacePlan(templateDef, documents, values, clusterName, config, { strict: isStrict(config, opts, templateName), select });
+		const { allNamespaces, keyNamespaceValueMap, unresolved, required, aliases } = plan;
+		strictFailures.push(...plan.failures);
+		deprecationWarnings(values, [...templatePlaceholders(documents), ...required], aliases)
+			.forEach(warning => console.warn(`   ⚠️ Warning: template '${templateName}' uses ${warning}`));
+		if (report && plan.failures.length > 0) {
+			report.failures.push({ stage: 'strict', cluster: clusterName, template: templateName, error: `unresolved placeholders: ${plan.failures.length}` });
+		}
+
+		// Templates without Secrets are written unencrypted, as are documents or
+		// templates marked `encrypt: false` and the non-Secret documents with
+		// --split-documents, so they may only consume public keys
+		if (templateDef.encrypt !== undefined && typeof templateDef.encrypt !== 'boolean') {
+			throw new Error(`'encrypt' of template '${templateName}' in cluster '${clusterName}' must be true or false`);
+		}
+		const secretKeys = new Set(values.filter(isSecretEntry).map(entry => entry.key));
+		aliases.forEach((key, alias) => secretKeys.has(key) && secretKeys.add(alias));
+		const keyOf = placeholder => aliases.get(placeholder) ?? placeholder;
+		const hasSecrets = documents.some(isSecretDocument);
+		const isPlain = doc => !hasSecrets || templateDef.encrypt === false || doc.encrypt === false || (opts.splitDocuments && !isSecretDocument(doc));
+		const exposed = [...templatePlaceholders(documents.filter(isPlain))].filter(ph => secretKeys.has(ph));
+		if (exposed.length > 0) {
+			throw new Error(
+				`Unencrypted documents of template '${templateName}' for cluster '${clusterName}' use keys not marked \`secret: false\`: ${exposed.join(', ')}`
+			);
+		}
+
+		if (strictFailures.length > 0) {
+			continue;
+		}
+
+		// Render-only runs write every template as one plaintext file in place of
+		// its encrypted output. Encrypted YAML outputs larger than
+		// `max_secret_bytes` are split into `<template>.part-<i>` files.
+		const outputParts = (rendered, dirs, label) => {
+			const encryptedDocs = rendered.filter((doc, i) => !isPlain(documents[i]));
+			const plainDocs = rendered.filter((doc, i) => isPlain(documents[i]));
+			const parts = [];
+			if (renderOnly) {
+				parts.push({ dir: dirs.secrets, manifest: engine.dump(rendered), encrypt: false, renderOnly: true });
+			} else if (encryptedDocs.length > 0 && engine.name !== 'yaml') {
+				parts.push({ dir: dirs.secrets, manifest: engine.dump(encryptedDocs), encrypt: true });
+			} else if (encryptedDocs.length > 0) {
+				if (maxBytes === undefined && encryptedDocs.some(doc => documentBytes(doc) > KUBERNETES_OBJECT_LIMIT)) {
+					console.warn(`   ⚠️ ${label} has a document larger than the 1 MiB Kubernetes allows; set max_secret_bytes to split it`);
+				}
+				const files = splitDocuments(encryptedDocs, maxBytes, `template '${templateName}' for '${label}'`);
+				files.forEach((docs, index) => parts.push({
+					dir: dirs.secrets,
+					manifest: dumpDocuments(docs),
+					encrypt: true,
+					name: files.length > 1 ? templateName.replace(/\.ya?ml$/, `.part-${index + 1}$&`) : templateName,
+				}));
 			}
-		} catch {
-			throw new Error(`Cluster folder '${clusterDir}' does not exist or is not a directory. Aborting.`);
+			if (plainDocs.length > 0 && !renderOnly) {
+				// Next to the encrypted output, or in its place when there is none
+				parts.push({ dir: encryptedDocs.length > 0 ? dirs.manifests : dirs.secrets, manifest: engine.dump(plainDocs), encrypt: false });
+			}
+			return parts;
+		};
+
+		// Global templates are written once for the cluster, as they are: no
+		// namespace, no values, no hooks or server-side dry-run
+		if (scope === 'global') {
+			if (!inShard(undefined)) {
+				continue;
+			}
+			if (selection) {
+				console.log(`   ⏭️ Skipping: global/${templateName} (--namespace only selects namespaces)`);
+				skipped.push({ cluster: clusterName, template: templateName, reason: '--namespace only selects namespaces' });
+				continue;
+			}
+			if (!clusterDirChecked) {
+				await assertClusterDir(join('clusters', clusterName));
+				clusterDirChecked = true;
+			}
+			const dir = globalOutputDir(clusterName);
+			claimOutputDir(outputOwners, dir, clusterName);
+			console.log(`   🔄 Rendering: global/${templateName}`);
+			const { documents: rendered } = renderTemplate(documents, {}, undefined, { injectNamespace: false });
+			if (provenance) {
+				rendered.forEach(doc => provenance(doc, templatePath));
+			}
+			writes.push({ parts: outputParts(rendered, { secrets: dir, manifests: dir }, `${clusterName}/global`), output: { clusterName, templateName, engine, substituted: [], encryptedRegex }, encryptor });
+			continue;
 		}
 
-		for (const templateDef of templateDefs) {
-			const templateName = templateDef.template;
-			const values = templateDef.values;
+		const usedKeys = new Set(required);
 
-			const templatePath = Object.keys(templates).find(p => p.endsWith(templateName));
-			if (!templatePath) {
-				console.warn(`   ⚠️ Template file not found for: ${templateName}`);
+		for (const ns of allNamespaces) {
+			if (!inShard(ns) || (selection && !selection.matches(ns))) {
+				continue;
+			}
+			if (namespaceFilter && !namespaceFilter.has(ns)) {
+				console.log(`   ⏭️ Skipping: ${ns}/${templateName} (namespace not found in the live cluster)`);
+				skipped.push({ cluster: clusterName, namespace: ns, template: templateName, reason: 'namespace not found in the live cluster' });
+				continue;
+			}
+			if (unresolved.has(ns)) {
+				const message = `${ns}/${templateName} (no values for: ${unresolved.get(ns).join(', ')})`;
+				if (unresolvedPolicy(config, templateDef, clusterName, ns) === 'warn') {
+					console.warn(`   ⚠️ Skipping: ${message}`);
+				} else {
+					console.log(`   ⏭️ Skipping: ${message}`);
+				}
+				skipped.push({ cluster: clusterName, namespace: ns, template: templateName, reason: `no values for: ${unresolved.get(ns).join(', ')}` });
 				continue;
 			}
 
-			const originalTemplate = templates[templatePath];
-			const keySection = getKeyAccessor(originalTemplate);
-			if (!keySection) {
-				throw new Error(`Template '${templateName}' must contain 'data' or 'stringData'`);
+			hooks.preRender(clusterName, ns);
+			const { documents: rendered, substitutions, namespaceOverrides: overrides } = renderTemplate(documents, namespaceValues(keyNamespaceValueMap, ns), ns, { injectNamespace });
+			if (provenance) {
+				rendered.forEach(doc => provenance(doc, templatePath));
 			}
+			namespaceOverrides.push(...overrides.map(override => ({ cluster: clusterName, namespace: ns, template: templateName, ...override })));
+			substitutions.forEach(([placeholder]) => usedKeys.add(keyOf(placeholder)));
+			const substituted = substitutions.filter(([placeholder]) => secretKeys.has(placeholder));
 
-			// Collect all namespaces from all values (union)
-			const allNamespaces = new Set();
-			for (const entry of values) {
-				// Check for duplicate namespaces inside a single entry
-				const nsSet = new Set(entry.namespaces);
-				if (nsSet.size !== entry.namespaces.length) {
-					throw new Error(
-						`Duplicate namespaces detected in key '${entry.key}' for template '${templateName}' in cluster '${clusterName}': ${entry.namespaces}`
-					);
+			if (rotation && !renderOnly) {
+				for (const [placeholder, value] of substituted) {
+					rotation.observe(clusterName, ns, keyOf(placeholder), value);
 				}
-				entry.namespaces.forEach(ns => allNamespaces.add(ns));
 			}
 
-			// Build nested map: key -> namespace -> value
-			const keyNamespaceValueMap = {};
-			for (const entry of values) {
-				if (!keyNamespaceValueMap[entry.key]) {
-					keyNamespaceValueMap[entry.key] = {};
-				}
+			console.log(`   🔄 Rendering: ${ns}/${templateName}`);
 
-				for (const ns of entry.namespaces) {
-					if (keyNamespaceValueMap[entry.key][ns] !== undefined) {
-						throw new Error(
-							`Duplicate value for key '${entry.key}' in namespace '${ns}' for template '${templateName}' in cluster '${clusterName}'`
-						);
-					}
-					keyNamespaceValueMap[entry.key][ns] = entry.value;
+			if (dryRun && engine.name === 'yaml') {
+				const error = serverDryRun(dumpDocuments(rendered), ns, dryRun);
+				dryRun.results.push({ cluster: clusterName, namespace: ns, template: templateName, error });
+				if (error) {
+					console.warn(`   ❌ Server-side dry-run failed: ${ns}/${templateName}`);
+					staging.fail(`${clusterName}/${ns}`);
+					continue;
 				}
 			}
 
-			// Gather placeholders from template section
-			const placeholders = new Set();
-			const section = originalTemplate[keySection];
-			for (const v of Object.values(section)) {
-				if (isPlaceholder(v)) {
-					const ph = getPlaceholderName(v);
-					placeholders.add(ph);
-				}
+			// The cluster folder guards against typos in cluster names; namespaces
+			// with an output root of their own do not need it
+			const dirs = outputDirs(config, clusterName, ns, group.output_root);
+			if (!dirs.overridden && !clusterDirChecked) {
+				await assertClusterDir(join('clusters', clusterName));
+				clusterDirChecked = true;
 			}
+			claimOutputDir(outputOwners, dirs.secrets, `${clusterName}/${ns}`);
 
-			// Validate all placeholders have values for all namespaces
-			for (const ph of placeholders) {
-				if (!keyNamespaceValueMap[ph]) {
-					throw new Error(
-						`❌ Placeholder '${ph}' in template '${templateName}' for cluster '${clusterName}' has no values defined`
-					);
-				}
+			if (snapshots) {
+				const snapshot = snapshots.get(ns) || { templates: {}, substituted: [] };
+				const nsValues = namespaceValues(keyNamespaceValueMap, ns);
+				snapshot.templates[templateName] = Object.fromEntries(
+					[...new Set([...substitutions.map(([placeholder]) => placeholder), ...required])].sort().map(key => [key, nsValues[key]])
+				);
+				snapshot.substituted.push(...substituted);
+				snapshots.set(ns, snapshot);
+			}
 
-				const missingNs = [...allNamespaces].filter(ns => !(ns in keyNamespaceValueMap[ph]));
-				if (missingNs.length > 0) {
-					throw new Error(
-						`❌ Key '${ph}' in template '${templateName}' for cluster '${clusterName}' is missing namespaces: ${missingNs.join(', ')}`
-					);
-				}
+			writes.push({ parts: outputParts(rendered, dirs, `${clusterName}/${ns}`), output: { clusterName, ns, templateName, engine, substituted, env: sopsEnv(config, clusterName, ns), keys: encryptionKeys(config, clusterName, ns), encryptedRegex }, encryptor });
+		}
+
+		// Warn for unused keys; namespace variable files may hold keys for other
+		// templates, and a shard may not render the namespaces using a key
+		for (const k of shard ? [] : Object.keys(keyNamespaceValueMap)) {
+			if (!usedKeys.has(k) && values.some(entry => entry.key === k && !entry.from && !isDefaultEntry(entry))) {
+				console.warn(`   ⚠️ Warning: key '${k}' is defined in .sopsify.yaml but not used in template '${templateName}'`);
 			}
+		}
+	}
 
-			const usedKeys = new Set();
+	if (strictFailures.length > 0) {
+		throw new Error(
+			`Strict mode: unresolved placeholders in cluster '${clusterName}' (template / namespace / key):\n${strictFailures.map(failure => `   ${failure}`).join('\n')}`
+		);
+	}
 
-			for (const ns of allNamespaces) {
-				const rendered = JSON.parse(JSON.stringify(originalTemplate)); // deep copy
+	for (const [ns, { templates: snapshotTemplates, substituted }] of snapshots || []) {
+		writes.push({
+			parts: [{ dir: outputDirs(config, clusterName, ns).snapshots, manifest: snapshotManifest(clusterName, ns, snapshotTemplates), encrypt: true }],
+			output: { clusterName, ns, templateName: SNAPSHOT_FILE, substituted, env: sopsEnv(config, clusterName, ns), keys: encryptionKeys(config, clusterName, ns), encryptedRegex: templateEncryptedRegex(config) },
+		});
+	}
 
-				// Add metadata.namespace field
-				if (!rendered.metadata) {
-					rendered.metadata = {};
-				}
-				rendered.metadata.namespace = ns;
+	// Encrypting is the slow part (a KMS round trip per sops call), so up to
+	// --jobs outputs are written at once. Each logs a single line when done;
+	// the outputs are recorded in template order regardless.
+	const jobs = opts.jobs === undefined ? 1 : positiveInt(opts.jobs, '--jobs');
+	const written = await runPool(writes, jobs, async ({ parts, output, encryptor }) => {
+		const partOutputs = [];
+		for (const part of parts) {
+			await writeOutput(part, output, { ...context, ...encryptor, outputs: partOutputs });
+		}
+		if (!context.preview) {
+			await discardStaleParts(parts, output, { ...context, ...encryptor });
+		}
+		return partOutputs;
+	});
+	outputs.push(...written.flat());
+}
 
-				const section = rendered[keySection];
+// `--strict`, or `strict: true` in .sopsify.yaml or the template's group
+export function isStrict(config, opts, templateName) {
+	if (config.strict !== undefined && typeof config.strict !== 'boolean') {
+		throw new Error(`'strict' in ${configFile()} must be true or false`);
+	}
+	const group = templateName === undefined ? {} : groupSettings(config, templateName);
+	return Boolean(opts.strict || (group.strict ?? config.strict));
+}
 
-				for (const [k, v] of Object.entries(section)) {
-					if (isPlaceholder(v)) {
-						const placeholder = getPlaceholderName(v);
-						usedKeys.add(placeholder);
-						section[k] = keyNamespaceValueMap[placeholder][ns];
-					}
-				}
+// `encrypted_regex` of the template definition, else the top-level one: the
+// keys sops encrypts, in place of the creation rule's, e.g. '^(data|stringData)$'
+// to keep metadata readable for review. Undefined leaves it to .sops.yaml.
+export function templateEncryptedRegex(config, templateDef = {}) {
+	const regex = templateDef.encrypted_regex ?? config.encrypted_regex;
+	const where = templateDef.encrypted_regex !== undefined ? `'encrypted_regex' of template '${templateDef.template}'` : `'encrypted_regex' in ${configFile()}`;
+	if (regex === undefined) {
+		return undefined;
+	}
+	if (typeof regex !== 'string' || regex === '') {
+		throw new Error(`${where} must be a regular expression, e.g. '^(data|stringData)$'`);
+	}
+	try {
+		new RegExp(regex.replace(/^\(\?i\)/, ''));
+	} catch (err) {
+		throw new Error(`${where} is not a valid regular expression: ${err.message}`);
+	}
+	return regex;
+}
+
+const TEMPLATE_SCOPES = ['namespace', 'global'];
+
+// `scope: global` in a template definition, or `--global` for every template
+// without placeholders or required keys, writes a template once per cluster
+// instead of once per namespace; the template definition's scope wins
+export function templateScope(templateDef, documents, clusterName, opts) {
+	const keys = [...new Set([...templatePlaceholders(documents), ...requiredKeys(documents)])];
+	const scope = templateDef.scope ?? (opts.global && keys.length === 0 ? 'global' : 'namespace');
+	const where = `Template '${templateDef.template}' of cluster '${clusterName}'`;
+	if (!TEMPLATE_SCOPES.includes(scope)) {
+		throw new Error(`${where} has unknown scope '${scope}', expected one of: ${TEMPLATE_SCOPES.join(', ')}`);
+	}
+	if (templateDef.scope === 'global' && keys.length > 0) {
+		throw new Error(`${where} has scope: global but uses keys, which need a namespace: ${keys.join(', ')}`);
+	}
+	if (templateDef.scope === 'global' && (templateDef.namespaces !== undefined || (templateDef.values ?? []).length > 0)) {
+		throw new Error(`${where} has scope: global, which takes no 'values' or 'namespaces'`);
+	}
+	return scope;
+}
 
-				const targetDir = join(clusterDir, 'secrets', ns);
-				await fs.mkdir(targetDir, { recursive: true });
+// Which namespaces a template is rendered for, with their values: key ->
+// namespace -> value. `unresolved` maps the namespaces to skip per
+// `on_unresolved` to their missing keys; unresolved placeholders of an `error`
+// policy throw. In strict mode they are returned as `failures` instead
+// (`<template> / <namespace> / <key>`) and no namespace is skipped.
+// `values` are the entries of the template definition with their sources
+// resolved; `select` limits the checks to the namespaces a run renders. The
+// map includes the `key_aliases` of the keys, `aliases` maps them to the keys.
+export function namespacePlan(templateDef, documents, values, clusterName, config, { strict = false, select } = {}) {
+	const templateName = templateDef.template;
+	const { allNamespaces, keyNamespaceValueMap } = buildKeyNamespaceValueMap(values, templateName, clusterName, config, templateDef.namespaces);
+	const aliases = addKeyAliases(keyNamespaceValueMap, config, clusterName);
 
-				const plaintextPath = join(targetDir, templateName);
-				const encryptedPath = plaintextPath.replace(/\.ya?ml$/, '.enc.yaml');
+	// Keys declared `required` must resolve like placeholders, e.g. when only
+	// used inside a larger string value that is not substituted
+	const required = requiredKeys(documents);
+	const placeholders = new Set([...templatePlaceholders(documents), ...required]);
+
+	// Validate all placeholders have values for all namespaces; `on_unresolved`
+	// may let namespaces skip the template instead, unless the run is strict
+	const unresolved = new Map();
+	const failures = [];
+	for (const ph of placeholders) {
+		const missingNs = [...allNamespaces].filter(ns => (!select || select(ns)) && !(ph in keyNamespaceValueMap && ns in keyNamespaceValueMap[ph]));
+		if (strict && allNamespaces.size > 0) {
+			missingNs.forEach(ns => failures.push(`${templateName} / ${ns} / ${ph}`));
+			continue;
+		}
+		const failing = missingNs.filter(ns => unresolvedPolicy(config, templateDef, clusterName, ns) === 'error');
+		if (!keyNamespaceValueMap[ph] && (failing.length > 0 || allNamespaces.size === 0)) {
+			throw new Error(
+				`❌ ${required.has(ph) ? 'Required key' : 'Placeholder'} '${ph}' in template '${templateName}' for cluster '${clusterName}' has no values defined`
+			);
+		}
+		if (failing.length > 0) {
+			throw new Error(
+				`❌ Key '${ph}' in template '${templateName}' for cluster '${clusterName}' is missing namespaces: ${failing.join(', ')}${keyContext(values, ph)}`
+			);
+		}
+		missingNs.forEach(ns => unresolved.set(ns, [...(unresolved.get(ns) || []), ph]));
+	}
 
-				console.log(`   🔄 Rendering: ${ns}/${templateName}`);
-				await fs.writeFile(plaintextPath, yaml.dump(rendered), 'utf-8');
+	return { allNamespaces, keyNamespaceValueMap, unresolved, required, failures, aliases };
+}
 
-				execSync(`sops -e -i ${plaintextPath}`);
+// Substitutes the values of one namespace (key -> value) into the documents of
+// a template and sets their `metadata.namespace`, unless `injectNamespace` is
+// false. Returns the rendered documents, the substituted [key, value] pairs
+// and the documents whose own `metadata.namespace` was replaced
+// ({document, path, before, after}; `document` counts from 1).
+export function renderTemplate(documents, values, namespace, { injectNamespace = true } = {}) {
+	const substitutions = [];
+	const namespaceOverrides = [];
+	const rendered = documents.map((document, index) => {
+		const doc = JSON.parse(JSON.stringify(document)); // deep copy
+		delete doc.required;
+		delete doc.encrypt;
 
-				await fs.rename(plaintextPath, encryptedPath);
-				console.log(`   🔐 Encrypted: ${ns}/${templateName.replace(/\.ya?ml$/, '.enc.yaml')}`);
+		if (injectNamespace) {
+			if (!doc.metadata) {
+				doc.metadata = {};
+			}
+			if (doc.metadata.namespace !== undefined && doc.metadata.namespace !== namespace) {
+				namespaceOverrides.push({ document: index + 1, path: 'metadata.namespace', before: doc.metadata.namespace, after: namespace });
 			}
+			doc.metadata.namespace = namespace;
+		}
 
-			// Warn for unused keys
-			for (const k of Object.keys(keyNamespaceValueMap)) {
-				if (!usedKeys.has(k)) {
-					console.warn(`   ⚠️ Warning: key '${k}' is defined in .sopsify.yaml but not used in template '${templateName}'`);
+		const section = doc[getKeyAccessor(doc)] || {};
+		for (const [k, v] of Object.entries(section)) {
+			if (isPlaceholder(v)) {
+				const placeholder = getPlaceholderName(v);
+				if (values[placeholder] === undefined) {
+					throw new Error(`No value for placeholder '${placeholder}' in namespace '${namespace}'`);
 				}
+				section[k] = values[placeholder];
+				substitutions.push([placeholder, section[k]]);
+			}
+		}
+		return doc;
+	});
+	return { documents: rendered, substitutions, namespaceOverrides };
+}
+
+// The values of one namespace from a plan's key -> namespace -> value map
+export function namespaceValues(keyNamespaceValueMap, namespace) {
+	return Object.fromEntries(Object.entries(keyNamespaceValueMap)
+		.filter(([, byNamespace]) => byNamespace[namespace] !== undefined)
+		.map(([key, byNamespace]) => [key, byNamespace[namespace]]));
+}
+
+const UNRESOLVED_POLICIES = ['error', 'warn', 'skip'];
+const ALL_SKIPPED_POLICIES = ['error', 'warn', 'ignore'];
+
+// What happens to a namespace lacking values for placeholders of a template:
+// `error` (default) aborts, `warn` and `skip` leave the output out, loudly or
+// quietly. The template definition's `on_unresolved` wins over the one of its
+// template group and the top-level one, which is a default for everything or
+// keyed by namespace or `<cluster>/<namespace>`.
+export function unresolvedPolicy(config, templateDef, clusterName, ns) {
+	const global = config.on_unresolved;
+	const byNamespace = global && typeof global === 'object' ? global : {};
+	const policy = templateDef.on_unresolved ?? groupSettings(config, templateDef.template).on_unresolved ?? byNamespace[`${clusterName}/${ns}`] ?? byNamespace[ns] ?? (typeof global === 'string' ? global : 'error');
+	if (!UNRESOLVED_POLICIES.includes(policy)) {
+		throw new Error(`Unknown on_unresolved policy '${policy}' for '${clusterName}/${ns}', expected one of: ${UNRESOLVED_POLICIES.join(', ')}`);
+	}
+	return policy;
+}
+
+// What a run that wrote nothing because every template/namespace was skipped
+// does: `error` (default) fails, `warn` and `ignore` succeed, loudly or
+// quietly. `--on-all-skipped` wins over `on_all_skipped` of .sopsify.yaml.
+function allSkippedPolicy(config, opts) {
+	const policy = opts.onAllSkipped ?? config.on_all_skipped ?? 'error';
+	if (!ALL_SKIPPED_POLICIES.includes(policy)) {
+		throw new Error(`Unknown on_all_skipped policy '${policy}', expected one of: ${ALL_SKIPPED_POLICIES.join(', ')}`);
+	}
+	return policy;
+}
+
+// Keys a later source of their `value_from` list resolved are shown with why
+// the earlier ones did not, so falling back to a default is noticed. The run
+// report records the source of every `value_from` key.
+function reportValueSources(values, clusterName, templateName, report) {
+	for (const entry of values.filter(entry => entry.source !== undefined)) {
+		if (entry.fallbacks.length > 0) {
+			console.log(`   🔗 Key '${entry.key}' of ${templateName} from ${entry.source} (${entry.fallbacks.join('; ')})`);
+		}
+		if (report) {
+			const scope = isRuleEntry(entry) ? { match: entry.match } : { namespaces: entry.namespaces };
+			report.sources.push({ cluster: clusterName, template: templateName, key: entry.key, ...scope, source: entry.source });
+		}
+	}
+}
+
+// A run that skipped everything, or had nothing to render at all, must not
+// pass for one that worked, e.g. in CI after a rename broke every value
+function reportAllSkipped(outputs, skipped, policy) {
+	if (outputs.length > 0 || policy === 'ignore') {
+		return;
+	}
+	const summary = skipped.length > 0
+		? `Nothing was written: all ${skipped.length} template/namespace pairs were skipped`
+		: 'Nothing was written: no template/namespace pairs to render';
+	const lines = skipped.map(({ cluster, namespace, template, reason }) => `   ${cluster}/${namespace ?? 'global'}/${template}: ${reason}`);
+	if (policy === 'warn') {
+		console.warn(`\n⚠️ ${summary}${lines.length > 0 ? `:\n${lines.join('\n')}` : ''}`);
+		return;
+	}
+	if (lines.length > 0) {
+		console.error(`\n${summary}:\n${lines.join('\n')}`);
+	}
+	throw new Error(`${summary}; set on_all_skipped: warn (or --on-all-skipped warn) if that is expected`);
+}
+
+// Staged outputs are promoted per namespace, and those of global templates per cluster
+function stagingKey(clusterName, ns) {
+	return ns === undefined ? clusterName : `${clusterName}/${ns}`;
+}
+
+function claimOutputDir(owners, dir, namespaceKey) {
+	const owner = owners.get(dir);
+	if (owner && owner !== namespaceKey) {
+		throw new Error(`Outputs of '${namespaceKey}' and '${owner}' would both be written to '${dir}'; use \${cluster} or \${namespace} in 'output_roots'`);
+	}
+	owners.set(dir, namespaceKey);
+}
+
+async function assertClusterDir(clusterDir) {
+	try {
+		const stat = await fs.stat(clusterDir);
+		if (!stat.isDirectory()) {
+			throw new Error();
+		}
+	} catch {
+		throw new Error(`Cluster folder '${clusterDir}' does not exist or is not a directory. Aborting.`);
+	}
+}
+
+// Writes one rendered output of a namespace to its staged path: encrypted in
+// the namespace's secrets folder, or as plain manifest. Outputs of global
+// templates have no `ns`.
+async function writeOutput(part, { clusterName, ns, templateName, engine = templateEngine(), substituted, env, keys, encryptedRegex }, context) {
+	const { dir, manifest, encrypt, renderOnly, name = templateName } = part;
+	const { backend, decryptCache, recipients, audit, staging, outputs, preview, opts } = context;
+	if (encrypt && backend.engines && !backend.engines.includes(engine.name)) {
+		throw new Error(`Template '${templateName}' uses the ${engine.name} engine, which the ${backend.name} backend does not encrypt; use another backend for its group`);
+	}
+	if (encrypt && keys && backend.format !== 'sops') {
+		throw new Error(`'encryption_keys' of '${clusterName}/${ns}' only apply to sops files, not to the ${backend.name} backend`);
+	}
+	if (encrypt && encryptedRegex !== undefined && backend.format !== 'sops') {
+		throw new Error(`'encrypted_regex' of template '${templateName}' only applies to sops files, not to the ${backend.name} backend`);
+	}
+
+	const outputName = encrypt ? outputFileName(name, backend.suffix, engine) : name;
+	const outputPath = join(dir, outputName);
+	const label = `${ns ?? 'global'}/${outputName}`;
+	if (preview) {
+		preview.push({ cluster: clusterName, namespace: ns, template: templateName, path: outputPath, manifest, encrypted: encrypt, env, backend, engine });
+		return;
+	}
+	await fs.mkdir(dirname(outputPath), { recursive: true });
+
+	if (opts.skipUnchanged && await isUnchanged(outputPath, manifest, encrypt ? decryptCache : undefined, env, engine, encrypt ? { keys, encryptedRegex } : {})) {
+		outputs.push({
+			cluster: clusterName,
+			namespace: ns,
+			template: templateName,
+			file: outputName,
+			path: outputPath,
+			sha256: createHash('sha256').update(await fs.readFile(outputPath)).digest('hex'),
+			unchanged: true,
+		});
+		console.log(`   ⏸️ Unchanged: ${label}`);
+		return;
+	}
+
+	const stagedPath = staging.path(stagingKey(clusterName, ns), outputPath);
+	if (encrypt) {
+		const plaintextPath = temporaryPlaintextPath(dirname(outputPath), basename(name));
+		assertPlaintextPathIgnored(plaintextPath, opts);
+		await fs.writeFile(plaintextPath, manifest, 'utf-8');
+		try {
+			await backend.encrypt(plaintextPath, outputPath, env, backend.format === 'sops' ? { keys, encryptedRegex, type: engine.sopsType } : undefined);
+		} catch (err) {
+			await fs.rm(plaintextPath, { force: true });
+			throw err;
+		}
+
+		await fs.rename(plaintextPath, stagedPath);
+		await assertNoPlaintextLeft(stagedPath, outputPath, substituted);
+		// sops keeps the metadata of dotenv files in variables, not as YAML
+		if (recipients && backend.format === 'sops' && engine.name !== 'dotenv') {
+			await assertExpectedRecipients(stagedPath, outputPath, recipients, { cluster: clusterName, namespace: ns });
+		}
+		if (opts.verify) {
+			await assertRoundTrip(stagedPath, outputPath, manifest, backend, env, engine);
+		}
+	} else {
+		if (renderOnly) {
+			assertPlaintextPathIgnored(outputPath, opts);
+		}
+		await fs.writeFile(stagedPath, manifest, 'utf-8');
+	}
+	outputs.push({
+		cluster: clusterName,
+		namespace: ns,
+		template: templateName,
+		file: outputName,
+		path: outputPath,
+		sha256: createHash('sha256').update(await fs.readFile(stagedPath)).digest('hex'),
+		...(renderOnly ? { renderOnly: true } : {}),
+	});
+	if (audit) {
+		await audit.record({
+			action: encrypt ? 'encrypt' : 'render',
+			backend: encrypt ? backend.name : 'none',
+			cluster: clusterName,
+			namespace: ns,
+			template: templateName,
+			output: outputPath,
+			recipients: encrypt ? await outputRecipients(stagedPath, backend) : [],
+		});
+	}
+	console.log(encrypt ? `   🔐 Encrypted: ${label}` : `   📝 Written (unencrypted): ${outputPath}`);
+}
+
+// Encrypted files of the template from an earlier run that this one did not
+// write, i.e. the parts of a split that has fewer parts or none now, or the
+// unsplit file once it is split; removed when the namespace is promoted
+async function discardStaleParts(parts, { clusterName, ns, templateName }, { backend, staging }) {
+	const stem = basename(templateName).replace(/\.ya?ml$/, '');
+	for (const dir of new Set(parts.filter(part => part.encrypt).map(part => part.dir))) {
+		const folder = join(dir, dirname(templateName));
+		const written = new Set(parts.filter(part => part.encrypt && part.dir === dir).map(part => join(dir, (part.name ?? templateName).replace(/\.ya?ml$/, backend.suffix))));
+		const files = await fs.readdir(folder).catch(() => []);
+		for (const file of files.filter(candidate => candidate.endsWith(backend.suffix))) {
+			const middle = file.slice(stem.length, -backend.suffix.length);
+			if (file.startsWith(stem) && (middle === '' || /^\.part-\d+$/.test(middle)) && !written.has(join(folder, file))) {
+				staging.discard(stagingKey(clusterName, ns), join(folder, file));
 			}
 		}
 	}
 }
 
+export function dumpDocuments(documents) {
+	return templateEngine().dump(documents);
+}
+
+function isSecretDocument(doc) {
+	return String(doc.kind).toLowerCase() === 'secret';
+}
+
+// With partial encryption (`encrypted_regex`) a misconfigured rule silently
+// leaves values readable. A leaking output is removed before failing.
+export async function assertNoPlaintextLeft(stagedPath, encryptedPath, substituted) {
+	const content = await fs.readFile(stagedPath, 'utf-8');
+	const [finding] = scanContent(content, knownValues(substituted));
+	if (!finding) {
+		return;
+	}
+
+	await fs.rm(stagedPath);
+	throw new Error(
+		`Output '${encryptedPath}' still contains the ${finding.reason} at line ${finding.line} after encryption. ` +
+		`Check encrypted_regex in .sopsify.yaml or the encrypted_regex/unencrypted_regex of the matching creation rule in .sops.yaml; the file was removed`
+	);
+}
+
+// Creation rules are matched by path regex, so a rule change or a new file
+// layout can silently select other keys. A wrongly encrypted output is removed.
+export async function assertExpectedRecipients(stagedPath, outputPath, rules, output) {
+	const [document] = yaml.loadAll(await fs.readFile(stagedPath, 'utf-8'));
+	const problems = recipientProblems((document && document.sops) || {}, rules, output);
+	if (problems.length === 0) {
+		return;
+	}
+
+	await fs.rm(stagedPath);
+	throw new Error(
+		`Output '${outputPath}' was not encrypted for the recipients required by .sopsify.yaml (${problems.join('; ')}). ` +
+		`Check which creation rule in .sops.yaml matches the path; the file was removed`
+	);
+}
+
+// `--verify`: a fresh output that does not decrypt to what was rendered (a
+// truncated write, a broken backend) is removed before it is promoted
+async function assertRoundTrip(stagedPath, outputPath, manifest, backend, env, engine) {
+	const problem = roundTripProblem(backend, await fs.readFile(stagedPath, 'utf-8'), manifest, env, engine);
+	if (!problem) {
+		return;
+	}
+	await fs.rm(stagedPath);
+	throw new Error(`Output '${outputPath}' ${problem}; the file was removed`);
+}
+
+// An existing output is unchanged when its decrypted content renders to the
+// same YAML (or dotenv, tfvars). Re-encrypting it would only churn the sops
+// `lastmodified`/MAC. Unencrypted outputs (no decrypt cache) are compared as
+// they are.
+async function isUnchanged(encryptedPath, manifest, decryptCache, env, engine, { keys, encryptedRegex } = {}) {
+	try {
+		await fs.access(encryptedPath);
+	} catch {
+		return false;
+	}
+
+	if (!decryptCache) {
+		return await fs.readFile(encryptedPath, 'utf-8') === manifest;
+	}
+	// Outputs encrypted for other keys or keys selected by another regex than
+	// configured now are re-encrypted
+	if (keys || encryptedRegex !== undefined) {
+		const [document] = yaml.loadAll(await fs.readFile(encryptedPath, 'utf-8'));
+		const metadata = (document && document.sops) || {};
+		if ((keys && !encryptedFor(metadata, keys)) || (encryptedRegex !== undefined && metadata.encrypted_regex !== encryptedRegex)) {
+			return false;
+		}
+	}
+
+	try {
+		return engine.dump(engine.parse(await decryptCache.decrypt(encryptedPath, env, engine.sopsType), encryptedPath)) === manifest;
+	} catch {
+		console.warn(`   ⚠️ Could not decrypt existing '${encryptedPath}', re-encrypting it`);
+		return false;
+	}
+}
+
+// Templates setting a namespace of their own are rendered into every
+// namespace listed for them anyway, which is rarely intended
+function reportNamespaceOverrides(overrides) {
+	if (overrides.length === 0) {
+		return;
+	}
+	console.warn(`\n⚠️ Namespace overrides: ${overrides.length} documents had another ${overrides[0].path}`);
+	for (const { cluster, namespace, template, document, path, before, after } of overrides) {
+		console.warn(`   ${cluster}/${namespace} ${template} (document ${document}) ${path}: '${before}' → '${after}'`);
+	}
+	console.warn('   Use --no-namespace-injection or `namespace_injection: false` in a template group for templates that manage their own namespace');
+}
+
+function reportDryRun(results) {
+	console.log('\n🧪 Server-side dry-run results');
+
+	const byNamespace = {};
+	for (const result of results) {
+		const ns = `${result.cluster}/${result.namespace}`;
+		(byNamespace[ns] = byNamespace[ns] || []).push(result);
+	}
+
+	for (const [ns, nsResults] of Object.entries(byNamespace)) {
+		const failed = nsResults.filter(r => r.error);
+		if (failed.length === 0) {
+			console.log(`   ✅ ${ns}: ${nsResults.length} manifests accepted`);
+			continue;
+		}
+		console.log(`   ❌ ${ns}: ${failed.length} of ${nsResults.length} manifests rejected`);
+		for (const result of failed) {
+			console.log(`      - ${result.template}: ${result.error}`);
+		}
+	}
+
+	const failedCount = results.filter(r => r.error).length;
+	if (failedCount > 0) {
+		throw new Error(`Server-side dry-run rejected ${failedCount} manifests; they were not encrypted`);
+	}
+}
+
+// `namespaces` are those the template definition lists itself, e.g. for
+// templates without placeholders
+export function buildKeyNamespaceValueMap(values, templateName, clusterName, config = {}, namespaces = []) {
+	if (!Array.isArray(namespaces) || !namespaces.every(ns => typeof ns === 'string')) {
+		throw new Error(`'namespaces' of template '${templateName}' in cluster '${clusterName}' must be a list of namespace names`);
+	}
+	if (new Set(namespaces).size !== namespaces.length) {
+		throw new Error(`Duplicate namespaces detected in 'namespaces' of template '${templateName}' in cluster '${clusterName}': ${namespaces}`);
+	}
+	namespaces.forEach(ns => validateNamespaceName(ns, config.namespace_names));
+	const known = knownNamespaces(config, clusterName);
+	assertKnownNamespaces(known, namespaces, `'namespaces' of template '${templateName}' in cluster '${clusterName}'`);
+
+	// Collect all namespaces from all values (union)
+	const allNamespaces = new Set(namespaces);
+	const entries = values.filter(entry => !isRuleEntry(entry));
+	for (const entry of entries) {
+		entry.namespaces.forEach(ns => validateNamespaceName(ns, config.namespace_names));
+		assertKnownNamespaces(known, entry.namespaces, `key '${entry.key}' of template '${templateName}' in cluster '${clusterName}'`, keyContext(values, entry.key));
+
+		// Check for duplicate namespaces inside a single entry
+		const nsSet = new Set(entry.namespaces);
+		if (nsSet.size !== entry.namespaces.length) {
+			throw new Error(
+				`Duplicate namespaces detected in key '${entry.key}' for template '${templateName}' in cluster '${clusterName}': ${entry.namespaces}${keyContext(values, entry.key)}`
+			);
+		}
+		entry.namespaces.forEach(ns => allNamespaces.add(ns));
+	}
+
+	// Build nested map: key -> namespace -> value
+	const keyNamespaceValueMap = {};
+	for (const entry of entries) {
+		if (!keyNamespaceValueMap[entry.key]) {
+			keyNamespaceValueMap[entry.key] = {};
+		}
+
+		for (const ns of entry.namespaces) {
+			if (keyNamespaceValueMap[entry.key][ns] !== undefined) {
+				throw new Error(
+					`Duplicate value for key '${entry.key}' in namespace '${ns}' for template '${templateName}' in cluster '${clusterName}'${keyContext(values, entry.key)}`
+				);
+			}
+			// Values fetched from secret managers are not expanded, but transformed
+			keyNamespaceValueMap[entry.key][ns] = applyTransforms(entry.value_from === undefined
+				? expandBuiltins(entry.value, { namespace: ns, cluster: clusterName, environment: clusterEnvironment(config, clusterName), template: templateName })
+				: entry.value, entry);
+		}
+	}
+
+	// Rules fill in namespaces without an explicit value; the first matching rule wins
+	for (const entry of values.filter(isRuleEntry)) {
+		const nsValues = keyNamespaceValueMap[entry.key] = keyNamespaceValueMap[entry.key] || {};
+		for (const ns of allNamespaces) {
+			const match = matchRule(entry, ns);
+			if (match && nsValues[ns] === undefined) {
+				nsValues[ns] = applyTransforms(entry.value_from === undefined
+					? expandBuiltins(entry.value, { namespace: ns, cluster: clusterName, environment: clusterEnvironment(config, clusterName), template: templateName, match })
+					: entry.value, entry);
+			}
+		}
+	}
+
+	// Declared types are checked on the final values, before anything is rendered
+	for (const [key, nsValues] of Object.entries(keyNamespaceValueMap)) {
+		const type = keyType(values, key, `of template '${templateName}' in cluster '${clusterName}'`);
+		for (const [ns, value] of Object.entries(type ? nsValues : {})) {
+			const problem = typeProblem(type, value);
+			if (problem) {
+				throw new Error(
+					`Value of key '${key}' for namespace '${ns}' of template '${templateName}' in cluster '${clusterName}' does not match type '${type}': ${problem}${keyContext(values, key)}`
+				);
+			}
+		}
+	}
+
+	return { allNamespaces, keyNamespaceValueMap };
+}
+
+// The template definitions of a cluster in the config
+export function clusterTemplates(config, clusterName) {
+	const clusterItem = config.sopsify.find(item => Object.keys(item)[0] === clusterName);
+	if (!clusterItem) {
+		throw new Error(`Cluster '${clusterName}' is not defined in .sopsify.yaml`);
+	}
+	return Object.values(clusterItem)[0];
+}
+
+export async function resolveNamespaceValues(config, clusterName, namespace, templateName) {
+	const templateDefs = clusterTemplates(config, clusterName)
+		.filter(def => !templateName || def.template === templateName);
+	if (templateName && templateDefs.length === 0) {
+		throw new Error(`Template '${templateName}' is not defined for cluster '${clusterName}'`);
+	}
+
+	useSourceLimits(config);
+	const namespaceVars = await loadNamespaceVars();
+	const result = {};
+	let namespaceFound = false;
+
+	for (const templateDef of templateDefs) {
+		const values = withNamespaceVars(await resolveValueSources(templateDef.values ?? [], { namespace }), namespaceVars, clusterName);
+		const { allNamespaces, keyNamespaceValueMap } = buildKeyNamespaceValueMap(values, templateDef.template, clusterName, config, templateDef.namespaces);
+		if (!allNamespaces.has(namespace)) {
+			continue;
+		}
+		namespaceFound = true;
+
+		for (const [key, nsValues] of Object.entries(keyNamespaceValueMap)) {
+			if (!(namespace in nsValues)) {
+				continue;
+			}
+
+			// Values are always exposed as strings (Terraform, docker secrets, ...)
+			const value = String(nsValues[namespace]);
+			if (key in result && result[key] !== value) {
+				throw new Error(
+					`Key '${key}' has conflicting values across templates for namespace '${namespace}' in cluster '${clusterName}'; pass 'template' to disambiguate`
+				);
+			}
+			result[key] = value;
+		}
+	}
+
+	if (!namespaceFound) {
+		throw new Error(`Namespace '${namespace}' is not defined for cluster '${clusterName}'`);
+	}
+
+	return result;
+}
+
+// The loaded template a name in .sopsify.yaml refers to: its path below the
+// templates folder. Of several files ending in that path the one nearest to
+// the templates folder wins.
+export function findTemplate(templates, templateName) {
+	return Object.keys(templates)
+		.filter(path => path === templateName || path.endsWith(`${sep}${normalize(templateName)}`))
+		.sort((a, b) => a.length - b.length)[0];
+}
+
+// Template names may be paths into subfolders, which are mirrored below the
+// output folder of every namespace, so they must stay inside it
+function validateTemplateName(templateName, clusterName, engine = templateEngine()) {
+	const normalized = typeof templateName === 'string' ? normalize(templateName) : '';
+	if (!engine.extension.test(normalized) || isAbsolute(normalized) || normalized.split(sep).includes('..')) {
+		throw new Error(`Template '${templateName}' of cluster '${clusterName}' must be a ${engine.description} path inside the templates folder`);
+	}
+}
+
+// Cluster and namespace names become output directories, so they must not
+// be able to escape the output root
+export function validatePathSegment(name, what) {
+	if (typeof name !== 'string' || name === '' || name === '.' || name === '..'
+		|| /[/\\]/.test(name) || /[\x00-\x1f\x7f]/.test(name)) {
+		throw new Error(`${what} name ${JSON.stringify(name)} is not allowed: it must not be empty, '.', '..' or contain path separators or control characters`);
+	}
+}
+
+const DNS_1123_LABEL = /^[a-z0-9]([-a-z0-9]*[a-z0-9])?$/;
+
+export function validateNamespaceName(name, rule = 'path-safe') {
+	validatePathSegment(name, 'Namespace');
+
+	if (rule === 'dns-1123') {
+		if (name.length > 63 || !DNS_1123_LABEL.test(name)) {
+			throw new Error(`Namespace '${name}' is not a valid DNS-1123 label (lowercase alphanumerics and '-', max 63 characters)`);
+		}
+	} else if (rule !== 'path-safe') {
+		throw new Error(`Unknown namespace_names rule '${rule}', expected 'path-safe' or 'dns-1123'`);
+	}
+}
+
+// `known_namespaces` in .sopsify.yaml: a list of the namespaces of every
+// cluster, or a map of cluster names to their lists. Namespaces listed by a
+// template must be known, so a typo like `prdo` fails instead of producing
+// another output folder. Undefined when there is no registry (for the cluster).
+export function knownNamespaces(config, clusterName) {
+	const registry = config.known_namespaces;
+	if (registry === undefined) {
+		return undefined;
+	}
+	const isList = list => Array.isArray(list) && list.every(ns => typeof ns === 'string');
+	if (isList(registry)) {
+		return new Set(registry);
+	}
+	if (!registry || typeof registry !== 'object' || !Object.values(registry).every(isList)) {
+		throw new Error(`'known_namespaces' in ${configFile()} must be a list of namespaces or a map of cluster names to lists of namespaces`);
+	}
+	return registry[clusterName] === undefined ? undefined : new Set(registry[clusterName]);
+}
+
+export function assertKnownNamespaces(known, namespaces, where, context = '') {
+	const unknown = known ? namespaces.filter(ns => !known.has(ns)) : [];
+	if (unknown.length > 0) {
+		const hints = unknown.map(ns => {
+			const similar = [...known].find(candidate => editDistance(ns, candidate) <= 2);
+			return similar ? `'${ns}' (did you mean '${similar}'?)` : `'${ns}'`;
+		});
+		throw new Error(`Unknown namespaces in ${where}: ${hints.join(', ')}; add them to 'known_namespaces' in ${configFile()} if they are new${context}`);
+	}
+}
+
+export function editDistance(a, b) {
+	let previous = Array.from({ length: b.length + 1 }, (_, j) => j);
+	for (let i = 1; i <= a.length; i++) {
+		const current = [i];
+		for (let j = 1; j <= b.length; j++) {
+			current[j] = Math.min(previous[j] + 1, current[j - 1] + 1, previous[j - 1] + (a[i - 1] === b[j - 1] ? 0 : 1));
+		}
+		previous = current;
+	}
+	return previous[b.length];
+}
+
+const BUILTIN_REFERENCE = /\$(\$?)\{(namespace|cluster|environment|template|env:([A-Za-z_][A-Za-z0-9_]*)|match:([A-Za-z0-9_]+))\}/g;
+
+// Expands built-in references in string values, e.g. "acme-${namespace}-artifacts":
+// ${namespace}, ${cluster}, ${environment}, ${template}, ${env:NAME} and, for
+// rule entries, ${match:<group>}. Other `${...}` are left as they are, as is
+// ${environment} where `builtins` has none; `$${namespace}` escapes a reference.
+export function expandBuiltins(value, builtins) {
+	if (typeof value !== 'string') {
+		return value;
+	}
+	return value.replace(BUILTIN_REFERENCE, (match, escaped, name, envName, group) => {
+		if (escaped) {
+			return match.slice(1);
+		}
+		if (group !== undefined) {
+			const captured = builtins.match && ((builtins.match.groups || {})[group] ?? builtins.match[group]);
+			if (captured === undefined) {
+				throw new Error(`'${match}' does not refer to a capture group of a 'match' rule`);
+			}
+			return captured;
+		}
+		if (!envName) {
+			if (name === 'environment' && !('environment' in builtins)) {
+				return match;
+			}
+			if (name === 'environment' && builtins.environment === undefined) {
+				throw new Error(`'${match}' refers to the environment of cluster '${builtins.cluster}', which is in none of 'environments' in ${configFile()}`);
+			}
+			return builtins[name];
+		}
+		if (process.env[envName] === undefined) {
+			throw new Error(`Environment variable '${envName}' referenced as '${match}' is not set`);
+		}
+		return process.env[envName];
+	});
+}
+
+// Describes a key for humans in error messages, e.g. " — owned by team-payments (Primary database password)"
+function keyContext(values, key) {
+	const entry = values.find(e => e.key === key && (e.owner || e.description));
+	if (!entry) {
+		return '';
+	}
+	const owner = entry.owner ? ` — owned by ${entry.owner}` : ' —';
+	return entry.description ? `${owner} (${entry.description})` : owner;
+}
+
+// Keys are secret unless every config entry marks them `secret: false`
+export function isSecretEntry(entry) {
+	return entry.secret !== false;
+}
+
+// Gather placeholders from the data/stringData sections of a template's documents
+export function templatePlaceholders(template) {
+	const placeholders = new Set();
+	for (const document of [].concat(template)) {
+		const section = document[getKeyAccessor(document)] || {};
+		for (const v of Object.values(section)) {
+			if (isPlaceholder(v)) {
+				placeholders.add(getPlaceholderName(v));
+			}
+		}
+	}
+	return placeholders;
+}
+
+// Keys a template declares with a top-level `required: [key, ...]`
+export function requiredKeys(template) {
+	return new Set([].concat(template).flatMap(document => document.required || []));
+}
+
 function getKeyAccessor(file) {
 	if (file.data) return 'data';
 	if (file.stringData) return 'stringData';
 	return undefined;
 }
 
+// Config keys, placeholder names and key aliases
+export const KEY_NAME = /^[a-zA-Z_][a-zA-Z0-9_-]*$/;
+
 function isPlaceholder(value) {
 	return typeof value === 'string' && /^\$\{[a-zA-Z_][a-zA-Z0-9_-]*\}$/.test(value);
 }
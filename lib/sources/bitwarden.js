@@ -0,0 +1,79 @@
+import { cliError, runCli } from './cli.js';
+
+// Bitwarden / Vaultwarden via the `bw` CLI. The vault is unlocked at most once
+// per run: an existing BW_SESSION is reused, otherwise BW_PASSWORD is used to
+// unlock it. All items are listed with a single call and looked up by name or id.
+let session;
+let items;
+
+export async function resolveBitwarden(settings) {
+	const { item, field } = settings || {};
+	if (!item || !field) {
+		throw new Error(`bitwarden source requires 'item' and 'field'`);
+	}
+
+	const found = (await listItems()).filter(i => i.id === item || i.name === item);
+	if (found.length === 0) {
+		throw new Error(`item '${item}' does not exist`);
+	}
+	if (found.length > 1) {
+		throw new Error(`item name '${item}' is ambiguous, use the item id instead`);
+	}
+
+	const value = fieldValue(found[0], field);
+	if (value === undefined || value === null) {
+		throw new Error(`item '${item}' has no field '${field}'`);
+	}
+	return value;
+}
+
+function fieldValue(item, field) {
+	if (field === 'notes') {
+		return item.notes;
+	}
+	if (item.login && (field === 'username' || field === 'password')) {
+		return item.login[field];
+	}
+	const custom = (item.fields || []).find(f => f.name === field);
+	return custom ? custom.value : undefined;
+}
+
+// Lookups running at the same time share the calls
+function listItems() {
+	if (!items) {
+		items = getSession().then(sessionKey => bw(['list', 'items'], sessionKey)).then(JSON.parse);
+	}
+	return items;
+}
+
+function getSession() {
+	if (!session) {
+		session = unlock();
+	}
+	return session;
+}
+
+async function unlock() {
+	if (process.env.BW_SESSION) {
+		return process.env.BW_SESSION;
+	}
+
+	const { status } = JSON.parse(await bw(['status']));
+	if (status === 'unauthenticated') {
+		throw new Error('not logged in, run `bw login` first');
+	}
+	if (!process.env.BW_PASSWORD) {
+		throw new Error('vault is locked, export BW_SESSION (`bw unlock --raw`) or BW_PASSWORD');
+	}
+
+	return (await bw(['unlock', '--passwordenv', 'BW_PASSWORD', '--raw'])).trim();
+}
+
+async function bw(args, sessionKey) {
+	const env = sessionKey ? { ...process.env, BW_SESSION: sessionKey } : process.env;
+	try {
+		return await runCli('bitwarden', 'bw', [...args, '--nointeraction'], { env });
+	} catch (err) {
+		throw new Error(cliError(err));
+	}
+}
@@ -0,0 +1,20 @@
+import { execFile } from 'child_process';
+import { promisify } from 'util';
+import { limitedCall } from './limits.js';
+
+const execFileAsync = promisify(execFile);
+
+// Parses the error of a failed CLI call into a one-line message
+export function cliError(err) {
+	if (err.code === 'ENOENT') {
+		return `\`${err.path}\` is not installed or not in PATH`;
+	}
+	return err.stderr ? err.stderr.toString().trim() : err.message;
+}
+
+// Runs the CLI of a source within its `source_limits`, without blocking the
+// other lookups of the run, and returns its output
+export function runCli(source, command, args, options = {}) {
+	return limitedCall(source, () => execFileAsync(command, args, { encoding: 'utf-8', maxBuffer: 64 * 1024 * 1024, ...options }), cliError)
+		.then(({ stdout }) => stdout);
+}
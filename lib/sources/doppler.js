@@ -0,0 +1,30 @@
+import { cliError, runCli } from './cli.js';
+
+// Doppler: all secrets of a project/config are downloaded with one CLI call
+// and reused for every key referencing the same config. Authentication is
+// left to the CLI (`doppler login` or DOPPLER_TOKEN).
+const dopplerConfigs = new Map();
+
+export async function resolveDoppler(settings) {
+	const { project, config, secret } = settings || {};
+	if (!project || !config || !secret) {
+		throw new Error(`doppler source requires 'project', 'config' and 'secret'`);
+	}
+
+	const configKey = `${project}/${config}`;
+	if (!dopplerConfigs.has(configKey)) {
+		dopplerConfigs.set(configKey, runCli(
+			'doppler',
+			'doppler',
+			['secrets', 'download', '--no-file', '--format', 'json', '--project', project, '--config', config],
+		).then(JSON.parse, err => {
+			throw new Error(cliError(err));
+		}));
+	}
+
+	const secrets = await dopplerConfigs.get(configKey);
+	if (!(secret in secrets)) {
+		throw new Error(`secret '${secret}' does not exist in ${configKey}`);
+	}
+	return secrets[secret];
+}
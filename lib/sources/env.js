@@ -0,0 +1,12 @@
+// `env: NAME` reads the value from an environment variable, e.g. a masked CI
+// variable. Unset and empty variables do not resolve, so further sources of a
+// `value_from` list are tried.
+export async function resolveEnv(name) {
+	if (typeof name !== 'string' || name === '') {
+		throw new Error(`env source requires the name of a variable, e.g. { env: DB_PASSWORD }`);
+	}
+	if (process.env[name] === undefined || process.env[name] === '') {
+		throw new Error(`\`${name}\` is not set`);
+	}
+	return process.env[name];
+}
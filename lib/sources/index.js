@@ -0,0 +1,120 @@
+import { resolveDoppler } from './doppler.js';
+import { resolveBitwarden } from './bitwarden.js';
+import { resolveInfisical } from './infisical.js';
+import { resolveKeyring } from './keyring.js';
+import { resolveEnv } from './env.js';
+import { appliesTo } from '../rules.js';
+import { configureSourceLimits } from './limits.js';
+
+// Resolvers for `value_from` entries. Each resolver receives the source
+// settings and returns the secret value. Every distinct source spec is
+// resolved once per process.
+const resolvers = {
+	doppler: resolveDoppler,
+	bitwarden: resolveBitwarden,
+	infisical: resolveInfisical,
+	keyring: resolveKeyring,
+};
+
+const cache = new Map();
+
+// Sources read without calling out, so without `source_limits`
+const local = {
+	env: resolveEnv,
+};
+
+// Applies `source_limits` of .sopsify.yaml to the calls of the sources
+export function useSourceLimits(config) {
+	configureSourceLimits(config.source_limits, Object.keys(resolvers));
+}
+
+// Returns a copy of `values` where every `value_from` entry has its `value`
+// resolved, and `source` names the source it came from. With `namespace`,
+// only entries scoped to that namespace are resolved, so commands working on
+// one namespace don't need credentials for all of them. Entries are looked
+// up at the same time, within the `source_limits` of their source.
+export async function resolveValueSources(values, { namespace } = {}) {
+	return Promise.all(values.map(async entry => {
+		if (entry.value_from === undefined) {
+			return entry;
+		}
+		if (entry.value !== undefined) {
+			throw new Error(`Key '${entry.key}' must define either 'value' or 'value_from', not both`);
+		}
+		if (namespace && !appliesTo(entry, namespace)) {
+			return entry;
+		}
+
+		return { ...entry, ...(await resolveChain(entry.key, valueSources(entry.key, entry.value_from))) };
+	}));
+}
+
+// `value_from` names one source or lists several, tried in order until one
+// resolves, so the same config works in CI, locally and in production:
+//
+//   value_from:
+//     - doppler: { project: api, config: prd, secret: DB_PASSWORD }
+//     - env: DB_PASSWORD
+//     - default: dev-dummy
+//
+// `default` is a literal value and can only come last. Returns the sources
+// as `{ name, settings }`; mistakes in the list fail before any is called.
+export function valueSources(key, valueFrom) {
+	const list = Array.isArray(valueFrom);
+	const sources = list ? valueFrom : [valueFrom];
+	if (sources.length === 0) {
+		throw new Error(`'value_from' of key '${key}' lists no sources`);
+	}
+	return sources.map((source, index) => {
+		const names = source && typeof source === 'object' && !Array.isArray(source) ? Object.keys(source) : [];
+		if (names.length !== 1) {
+			throw new Error(`'value_from' of key '${key}' must name exactly one source${list ? ' per list item' : ''}, e.g. { doppler: {...} }`);
+		}
+		const [name] = names;
+		if (name === 'default') {
+			if (index !== sources.length - 1) {
+				throw new Error(`'default' must be the last source in 'value_from' of key '${key}'; the sources after it are never tried`);
+			}
+			if (typeof source.default !== 'string') {
+				throw new Error(`'default' of key '${key}' must be a string; quote it: default: "${source.default}"`);
+			}
+		} else if (!resolvers[name] && !local[name]) {
+			throw new Error(`Unknown source '${name}' for key '${key}', expected one of: ${[...Object.keys(resolvers), ...Object.keys(local), 'default'].join(', ')}`);
+		}
+		return { name, settings: source[name] };
+	});
+}
+
+// The names of the sources of a `value_from`, for reports; any shape is accepted
+export function sourceNames(valueFrom) {
+	return [].concat(valueFrom ?? []).map(source => Object.keys(source || {})[0]).filter(Boolean);
+}
+
+// The first source that resolves wins; `fallbacks` are the errors of those
+// tried before it
+async function resolveChain(key, sources) {
+	const fallbacks = [];
+	for (const { name, settings } of sources) {
+		if (name === 'default') {
+			return { value: settings, source: name, fallbacks };
+		}
+		try {
+			return { value: await resolveSource(key, name, settings), source: name, fallbacks };
+		} catch (err) {
+			fallbacks.push(`${name}: ${err.message}`);
+		}
+	}
+	if (sources.length === 1) {
+		throw new Error(`Could not resolve key '${key}' from ${fallbacks[0]}`);
+	}
+	throw new Error(`Could not resolve key '${key}' from any of its sources:\n   ${fallbacks.join('\n   ')}`);
+}
+
+async function resolveSource(key, name, settings) {
+	const resolver = resolvers[name] || local[name];
+	const cacheKey = JSON.stringify({ [name]: settings });
+	if (!cache.has(cacheKey)) {
+		cache.set(cacheKey, Promise.resolve().then(() => resolver(settings, key)));
+	}
+	return cache.get(cacheKey);
+}
@@ -0,0 +1,58 @@
+import { cliError, runCli } from './cli.js';
+
+// Infisical via the `infisical` CLI with machine identity (universal auth).
+// A token is obtained once per run from INFISICAL_UNIVERSAL_AUTH_CLIENT_ID /
+// INFISICAL_UNIVERSAL_AUTH_CLIENT_SECRET unless INFISICAL_TOKEN is already set.
+// Each project/env/path is exported with a single call.
+let token;
+const folders = new Map();
+
+export async function resolveInfisical(settings) {
+	const { project, env, key, path = '/' } = settings || {};
+	if (!project || !env || !key) {
+		throw new Error(`infisical source requires 'project', 'env' and 'key'`);
+	}
+
+	const folderKey = `${project}/${env}${path}`;
+	if (!folders.has(folderKey)) {
+		folders.set(folderKey, getToken()
+			.then(token => infisical(['export', '--projectId', project, '--env', env, '--path', path, '--format', 'json', '--token', token]))
+			.then(json => Object.fromEntries(JSON.parse(json).map(s => [s.key, s.value]))));
+	}
+
+	const secrets = await folders.get(folderKey);
+	if (!(key in secrets)) {
+		throw new Error(`secret '${key}' does not exist in ${folderKey}`);
+	}
+	return secrets[key];
+}
+
+function getToken() {
+	if (!token) {
+		token = login();
+	}
+	return token;
+}
+
+async function login() {
+	if (process.env.INFISICAL_TOKEN) {
+		return process.env.INFISICAL_TOKEN;
+	}
+
+	const clientId = process.env.INFISICAL_UNIVERSAL_AUTH_CLIENT_ID;
+	const clientSecret = process.env.INFISICAL_UNIVERSAL_AUTH_CLIENT_SECRET;
+	if (!clientId || !clientSecret) {
+		throw new Error('no machine identity, export INFISICAL_UNIVERSAL_AUTH_CLIENT_ID/INFISICAL_UNIVERSAL_AUTH_CLIENT_SECRET or INFISICAL_TOKEN');
+	}
+
+	// The client secret is passed via the environment, never as an argument
+	return (await infisical(['login', '--method=universal-auth', '--silent', '--plain'])).trim();
+}
+
+async function infisical(args) {
+	try {
+		return await runCli('infisical', 'infisical', args);
+	} catch (err) {
+		throw new Error(cliError(err));
+	}
+}
@@ -0,0 +1,65 @@
+import { cliError, runCli } from './cli.js';
+
+// OS credential stores through their native tooling, so no native module is needed:
+// - macOS Keychain: `security find-generic-password`
+// - Linux Secret Service (GNOME Keyring, KWallet, KeePassXC): `secret-tool lookup`
+// - Windows Credential Manager: CredRead via PowerShell, generic credential
+//   with target name `<service>/<account>` (the convention used by keytar)
+export async function resolveKeyring(settings) {
+	const { service, account } = settings || {};
+	if (!service || !account) {
+		throw new Error(`keyring source requires 'service' and 'account'`);
+	}
+
+	switch (process.platform) {
+		case 'darwin':
+			return (await run('security', ['find-generic-password', '-s', service, '-a', account, '-w'])).replace(/\n$/, '');
+		case 'win32':
+			return run('powershell.exe', ['-NoProfile', '-NonInteractive', '-Command', WINDOWS_CRED_READ], {
+				SOPSIFY_KEYRING_TARGET: `${service}/${account}`,
+			});
+		default:
+			return run('secret-tool', ['lookup', 'service', service, 'account', account]);
+	}
+}
+
+const WINDOWS_CRED_READ = `
+$ErrorActionPreference = 'Stop'
+Add-Type -TypeDefinition @"
+using System;
+using System.Runtime.InteropServices;
+public static class SopsifyCred {
+	[StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
+	public struct CREDENTIAL {
+		public int Flags; public int Type; public string TargetName; public string Comment;
+		public long LastWritten; public int CredentialBlobSize; public IntPtr CredentialBlob;
+		public int Persist; public int AttributeCount; public IntPtr Attributes;
+		public string TargetAlias; public string UserName;
+	}
+	[DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+	public static extern bool CredRead(string target, int type, int flags, out IntPtr credential);
+	[DllImport("advapi32.dll")]
+	public static extern void CredFree(IntPtr credential);
+	public static string Read(string target) {
+		IntPtr ptr;
+		if (!CredRead(target, 1, 0, out ptr)) { throw new Exception("credential '" + target + "' not found"); }
+		try {
+			var cred = (CREDENTIAL)Marshal.PtrToStructure(ptr, typeof(CREDENTIAL));
+			return Marshal.PtrToStringUni(cred.CredentialBlob, cred.CredentialBlobSize / 2);
+		} finally { CredFree(ptr); }
+	}
+}
+"@
+[Console]::Out.Write([SopsifyCred]::Read($env:SOPSIFY_KEYRING_TARGET))
+`;
+
+async function run(command, args, env = {}) {
+	try {
+		return await runCli('keyring', command, args, { env: { ...process.env, ...env } });
+	} catch (err) {
+		if (typeof err.code === 'number' && !String(err.stderr || '').trim()) {
+			throw new Error('no matching entry in the OS credential store');
+		}
+		throw new Error(cliError(err));
+	}
+}
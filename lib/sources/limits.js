@@ -0,0 +1,129 @@
+import { configFile } from '../config.js';
+
+// `source_limits` in .sopsify.yaml keeps a run within what a secret manager
+// allows, per source:
+//
+//   source_limits:
+//     doppler: { rate: 5, concurrency: 2, retries: 2 }
+//
+// - `rate`: calls started per second at most
+// - `concurrency`: calls in flight at most
+// - `retries`: how often a failed call is repeated, waiting 0.5 s, 1 s, 2 s, ...
+//
+// Only calls to the source count, not lookups of values it already returned.
+const SETTINGS = {
+	rate: value => typeof value === 'number' && value > 0,
+	concurrency: value => Number.isInteger(value) && value > 0,
+	retries: value => Number.isInteger(value) && value >= 0,
+};
+const RETRY_DELAY_MS = 500;
+
+let limits = {};
+const states = new Map();
+
+export function configureSourceLimits(settings, sources) {
+	if (settings === undefined || settings === null) {
+		limits = {};
+		return;
+	}
+	if (typeof settings !== 'object' || Array.isArray(settings)) {
+		throw new Error(`'source_limits' in ${configFile()} must be a map of sources to limits`);
+	}
+	for (const [source, sourceLimits] of Object.entries(settings)) {
+		if (!sources.includes(source)) {
+			throw new Error(`Unknown source '${source}' in 'source_limits', expected one of: ${sources.join(', ')}`);
+		}
+		if (!sourceLimits || typeof sourceLimits !== 'object' || Array.isArray(sourceLimits)) {
+			throw new Error(`'source_limits.${source}' in ${configFile()} must be a map of ${Object.keys(SETTINGS).join(' / ')}`);
+		}
+		for (const [name, value] of Object.entries(sourceLimits)) {
+			if (!SETTINGS[name]) {
+				throw new Error(`Unknown limit '${name}' in 'source_limits.${source}', expected one of: ${Object.keys(SETTINGS).join(', ')}`);
+			}
+			if (!SETTINGS[name](value)) {
+				throw new Error(`'source_limits.${source}.${name}' in ${configFile()} must be a ${name === 'rate' ? 'positive number' : name === 'retries' ? 'non-negative integer' : 'positive integer'}, got '${value}'`);
+			}
+		}
+	}
+	limits = settings;
+}
+
+// Runs one call to a source within its limits, repeating it on failure;
+// `describe` turns an error into the message of the retry warning
+export async function limitedCall(source, fn, describe = err => err.message) {
+	const { retries = 0 } = limits[source] || {};
+	const state = stateOf(source);
+	state.calls++;
+	for (let attempt = 0; ; attempt++) {
+		await acquire(source, state);
+		try {
+			return await fn();
+		} catch (err) {
+			if (attempt >= retries) {
+				state.failed++;
+				throw err;
+			}
+			state.retries++;
+			console.warn(`   🔁 ${source} call failed, retrying (${attempt + 1}/${retries}): ${describe(err)}`);
+		} finally {
+			release(state);
+		}
+		await sleep(RETRY_DELAY_MS * 2 ** attempt);
+	}
+}
+
+function stateOf(source) {
+	if (!states.has(source)) {
+		states.set(source, { calls: 0, retries: 0, failed: 0, waitedMs: 0, active: 0, queue: [], nextStart: 0 });
+	}
+	return states.get(source);
+}
+
+async function acquire(source, state) {
+	const { rate, concurrency = Infinity } = limits[source] || {};
+	const started = Date.now();
+	while (state.active >= concurrency) {
+		await new Promise(resolve => state.queue.push(resolve));
+	}
+	state.active++;
+	if (rate) {
+		const now = Date.now();
+		const start = Math.max(now, state.nextStart);
+		state.nextStart = start + 1000 / rate;
+		await sleep(start - now);
+	}
+	state.waitedMs += Date.now() - started;
+}
+
+function release(state) {
+	state.active--;
+	const next = state.queue.shift();
+	if (next) {
+		next();
+	}
+}
+
+function sleep(ms) {
+	return ms > 0 ? new Promise(resolve => setTimeout(resolve, ms)) : Promise.resolve();
+}
+
+// Calls, retries and time spent waiting for the limits, for sources with
+// limits or retried calls
+export function sourceCallStats() {
+	return [...states.entries()]
+		.filter(([source, state]) => limits[source] || state.retries > 0)
+		.map(([source, { calls, retries, failed, waitedMs }]) => ({ source, calls, retries, failed, waitedMs, limits: limits[source] || {} }));
+}
+
+export function reportSourceCalls() {
+	const stats = sourceCallStats();
+	if (stats.length === 0) {
+		return;
+	}
+	console.log('\n📡 Secret source calls');
+	for (const { source, calls, retries, failed, waitedMs, limits: { rate, concurrency } } of stats) {
+		const limit = [rate && `${rate}/s`, concurrency && `${concurrency} at a time`].filter(Boolean).join(', ');
+		const line = `${source}: ${calls} calls, ${retries} retries, ${(waitedMs / 1000).toFixed(1)} s waiting${limit ? ` (limit ${limit})` : ''}`;
+		console.log(failed > 0 ? `   ❌ ${line}, ${failed} failed` : `   ✅ ${line}`);
+	}
+}
@@ -0,0 +1,148 @@
+import fs from 'fs/promises';
+import { join, dirname, basename } from 'path';
+import { randomBytes } from 'crypto';
+
+// Encrypted outputs are first written to hidden staged files next to their
+// final path (`.<file>.sopsify-<run>`), so sops still sees the real output
+// layout for creation rules. Once every output of a namespace has been
+// produced, they are promoted together; on failure the staged files are
+// removed and the namespace keeps its previous outputs. Outputs the namespace
+// no longer has (`discard`) are only removed once it is promoted.
+export function createStaging(runId = `${Date.now()}-${process.pid}`) {
+	const staged = new Map();
+	const discarded = new Map();
+	const failed = new Set();
+
+	return {
+		path(namespaceKey, finalPath) {
+			const stagedPath = join(dirname(finalPath), `.${basename(finalPath)}.sopsify-${runId}`);
+			if (!staged.has(namespaceKey)) {
+				staged.set(namespaceKey, []);
+			}
+			staged.get(namespaceKey).push({ stagedPath, finalPath });
+			return stagedPath;
+		},
+
+		discard(namespaceKey, finalPath) {
+			discarded.set(namespaceKey, [...(discarded.get(namespaceKey) || []), finalPath]);
+		},
+
+		fail(namespaceKey) {
+			failed.add(namespaceKey);
+		},
+
+		// Returns the keys of the namespaces that were rolled back
+		async promote() {
+			const rolledBack = [];
+			// Namespaces whose outputs were all unchanged may still discard some
+			for (const namespaceKey of new Set([...staged.keys(), ...discarded.keys()])) {
+				const files = staged.get(namespaceKey) || [];
+				if (failed.has(namespaceKey)) {
+					console.warn(`   ↩️ Rolled back: ${namespaceKey} (not all outputs could be produced)`);
+					await removeAll(files);
+					rolledBack.push(namespaceKey);
+					continue;
+				}
+				for (const { stagedPath, finalPath } of files) {
+					await fs.rename(stagedPath, finalPath);
+				}
+				for (const path of discarded.get(namespaceKey) || []) {
+					await fs.rm(path, { force: true });
+				}
+			}
+			staged.clear();
+			discarded.clear();
+			failed.clear();
+			return rolledBack;
+		},
+
+		async rollback() {
+			for (const files of staged.values()) {
+				await removeAll(files);
+			}
+			staged.clear();
+			discarded.clear();
+			failed.clear();
+		},
+	};
+}
+
+async function removeAll(files) {
+	for (const { stagedPath } of files) {
+		await fs.rm(stagedPath, { force: true });
+	}
+}
+
+// Plaintext is rendered next to its output (so sops without
+// --filename-override still matches the creation rules of the folder) under a
+// unique hidden name with the pid of the run, e.g.
+// `.db-secret.sopsify-4711-3f9c2a1b7e4d.yaml`. Concurrent runs and namespaces
+// never share an intermediate file, and it still matches `*.yaml` ignore rules.
+export function temporaryPlaintextPath(dir, templateName) {
+	return join(dir, `.${templateName.replace(/\.ya?ml$/, '')}.sopsify-${process.pid}-${randomBytes(6).toString('hex')}.yaml`);
+}
+
+// The staging folder of `rename-namespace`, with the pid of the run like the
+// other intermediate files, e.g. `.payments-v2.sopsify-rename-4711`
+export function renameStagingPath(dir, newName) {
+	return join(dir, `.${newName}.sopsify-rename-${process.pid}`);
+}
+
+// The first group of a pattern is the pid of the run, if the name has one.
+// Runs before temporary files got hidden names rendered `<template>.yaml`
+// next to its `<template>.enc.yaml` (`besideOutput`) or `*.tmp.yaml`.
+const LEFTOVERS = [
+	{ kind: 'plaintext', pattern: /^\..+\.sopsify-(?:(\d+)-)?[0-9a-f]{12}\.yaml$/, directory: false },
+	{ kind: 'plaintext', pattern: /^[^.].*\.tmp\.ya?ml$/, directory: false },
+	{ kind: 'plaintext', pattern: /^[^.].*(?<!\.enc)\.ya?ml$/, directory: false, besideOutput: true },
+	{ kind: 'staged', pattern: /^\..+\.sopsify-\d+-(\d+)$/, directory: false },
+	{ kind: 'rename', pattern: /^\..+\.sopsify-rename(?:-(\d+))?$/, directory: true },
+];
+
+// Intermediate files a crashed or killed run left below the output roots:
+// temporary plaintext, staged outputs and staged folders of `rename-namespace`.
+// Files of a run that is still going (its pid is alive) are not.
+export async function findLeftovers(roots) {
+	const leftovers = [];
+	const seen = new Set();
+	const walk = async dir => {
+		let entries;
+		try {
+			entries = await fs.readdir(dir, { withFileTypes: true });
+		} catch {
+			return;
+		}
+		const names = new Set(entries.map(entry => entry.name));
+		const matches = (candidate, entry) => candidate.directory === entry.isDirectory() && candidate.pattern.test(entry.name)
+			&& (!candidate.besideOutput || names.has(entry.name.replace(/\.ya?ml$/, '.enc.yaml')));
+		for (const entry of entries) {
+			const path = join(dir, entry.name);
+			const leftover = LEFTOVERS.find(candidate => matches(candidate, entry));
+			if (leftover && !seen.has(path)) {
+				seen.add(path);
+				const pid = leftover.pattern.exec(entry.name)[1];
+				if (pid === undefined || !isRunning(Number(pid))) {
+					leftovers.push({ path, kind: leftover.kind });
+				}
+			} else if (entry.isDirectory()) {
+				await walk(path);
+			}
+		}
+	};
+	for (const root of roots) {
+		await walk(root);
+	}
+	return leftovers;
+}
+
+function isRunning(pid) {
+	if (pid === process.pid) {
+		return true;
+	}
+	try {
+		process.kill(pid, 0);
+		return true;
+	} catch (err) {
+		return err.code === 'EPERM';
+	}
+}
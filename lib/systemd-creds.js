@@ -0,0 +1,52 @@
+import fs from 'fs/promises';
+import { join } from 'path';
+import { execFileSync } from 'child_process';
+import { loadConfig, resolveNamespaceValues } from './sopsify.js';
+import { assertPlaintextPathIgnored } from './git.js';
+
+// Emits one credential file per key of a namespace, either encrypted with
+// `systemd-creds encrypt` (for LoadCredentialEncrypted=) or as plain files in
+// a LoadCredential= compatible directory.
+export async function systemdCreds(opts) {
+	if (!opts.cluster || !opts.namespace) {
+		throw new Error(`systemd-creds requires --cluster and --namespace`);
+	}
+
+	const config = await loadConfig();
+	const values = await resolveNamespaceValues(config, opts.cluster, opts.namespace, opts.template);
+	const encrypt = opts.encrypt !== false;
+
+	const targetDir = opts.output || join('credentials', opts.cluster, opts.namespace);
+	await fs.mkdir(targetDir, { recursive: true, mode: 0o700 });
+
+	console.log(`🔑 Writing systemd credentials for ${opts.cluster}/${opts.namespace} to ${targetDir}`);
+
+	for (const [key, value] of Object.entries(values)) {
+		if (!/^[a-zA-Z0-9_.-]+$/.test(key)) {
+			throw new Error(`Key '${key}' is not a valid credential name`);
+		}
+
+		if (encrypt) {
+			const credPath = join(targetDir, `${key}.cred`);
+			try {
+				execFileSync('systemd-creds', ['encrypt', `--name=${key}`, '-', credPath], { input: value, stdio: ['pipe', 'pipe', 'pipe'] });
+			} catch (err) {
+				const stderr = err.stderr ? err.stderr.toString().trim() : err.message;
+				throw new Error(`\`systemd-creds encrypt\` failed for key '${key}': ${stderr}`);
+			}
+			console.log(`   🔐 Encrypted: ${credPath}`);
+		} else {
+			const credPath = join(targetDir, key);
+			assertPlaintextPathIgnored(credPath, opts);
+			await fs.writeFile(credPath, value, { encoding: 'utf-8', mode: 0o600 });
+			console.log(`   📄 Written: ${credPath}`);
+		}
+	}
+
+	const [firstKey] = Object.keys(values);
+	if (firstKey) {
+		const directive = encrypt ? 'LoadCredentialEncrypted' : 'LoadCredential';
+		const credPath = join(targetDir, encrypt ? `${firstKey}.cred` : firstKey);
+		console.log(`\n   ℹ️ Unit file usage, e.g.: ${directive}=${firstKey}:${credPath}`);
+	}
+}
@@ -0,0 +1,110 @@
+import fs from 'fs/promises';
+import { execFileSync, execFile } from 'child_process';
+import { promisify } from 'util';
+import { dirname, join, normalize, isAbsolute, basename, sep } from 'path';
+
+const execFileAsync = promisify(execFile);
+
+// `outputs:` in .sopsify.yaml mirrors every output of a run to further
+// targets after the regular output tree has been written, e.g. a second
+// repository with another layout or an S3 bucket. `path` lays out the files
+// within a target with ${cluster}, ${namespace} and ${file} (the output's path
+// below its namespace folder); by default the path of the regular output is kept.
+// Outputs of global templates have no namespace and use `global_path` with
+// ${cluster} and ${file} instead, or `path` if it does not use ${namespace}.
+const targetTypes = {
+	dir: dir => ({
+		name: dir,
+		async check() {},
+		async publish(source, path) {
+			const destination = join(dir, path);
+			const content = await fs.readFile(source);
+			try {
+				if ((await fs.readFile(destination)).equals(content)) {
+					return false;
+				}
+			} catch {
+				// not mirrored yet
+			}
+			await fs.mkdir(dirname(destination), { recursive: true });
+			await fs.writeFile(destination, content);
+			return true;
+		},
+	}),
+
+	s3: url => {
+		if (!/^s3:\/\/[^/]+/.test(url)) {
+			throw new Error(`Output target 's3: ${url}' must be an s3://<bucket>[/<prefix>] URL`);
+		}
+		return {
+			name: url,
+			async check() {
+				try {
+					execFileSync('aws', ['--version'], { stdio: 'pipe' });
+				} catch {
+					throw new Error(`aws is not installed or not in PATH, but required for output target '${url}'`);
+				}
+			},
+			async publish(source, path) {
+				await execFileAsync('aws', ['s3', 'cp', '--only-show-errors', source, `${url.replace(/\/+$/, '')}/${path.split(sep).join('/')}`]);
+				return true;
+			},
+		};
+	},
+};
+
+export async function openTargets(config) {
+	const targets = (config.outputs || []).map((target, index) => {
+		const types = Object.keys(targetTypes).filter(type => target && target[type] !== undefined);
+		if (types.length !== 1) {
+			throw new Error(`Output target ${index + 1} in 'outputs' needs exactly one of: ${Object.keys(targetTypes).join(', ')}`);
+		}
+		const type = types[0];
+		return { ...targetTypes[type](target[type]), layout: target.path, globalLayout: target.global_path };
+	});
+
+	for (const target of targets) {
+		await target.check();
+	}
+	return targets;
+}
+
+// Copies every output of the run, unchanged ones included, so a target that
+// was added later or missed a run catches up
+export async function publishOutputs(targets, outputs) {
+	for (const target of targets) {
+		const paths = new Map();
+		for (const output of outputs) {
+			const path = targetPath(target, output);
+			if (paths.has(path)) {
+				throw new Error(`Outputs '${paths.get(path)}' and '${output.path}' would both be written to '${path}' of output target '${target.name}'`);
+			}
+			paths.set(path, output.path);
+		}
+
+		let published = 0;
+		for (const [path, source] of paths) {
+			if (await target.publish(source, path)) {
+				published++;
+			}
+		}
+		console.log(`   📤 ${target.name}: ${published} of ${outputs.length} outputs ${published === 1 ? 'was' : 'were'} updated`);
+	}
+}
+
+function targetPath(target, output) {
+	const global = output.namespace === undefined;
+	const layout = global && target.globalLayout ? target.globalLayout : target.layout;
+	if (!layout) {
+		return normalize(output.path);
+	}
+	if (global && layout.includes('${namespace}')) {
+		throw new Error(`Output target path '${layout}' of '${target.name}' uses \${namespace}, which global output '${output.path}' has none of; set a global_path for it`);
+	}
+	const builtins = { cluster: output.cluster, namespace: output.namespace, file: output.file ?? basename(output.path) };
+	const path = normalize(layout.replace(/\$\{(cluster|namespace|file)\}/g, (match, name) => builtins[name]));
+	if (isAbsolute(path) || path === '..' || path.startsWith(`..${sep}`)) {
+		throw new Error(`Output target path '${layout}' of '${target.name}' must stay inside the target`);
+	}
+	return path;
+}
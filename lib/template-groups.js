@@ -0,0 +1,82 @@
+import { pathMatcher } from './files.js';
+import { configFile } from './config.js';
+import { templateEngine, ENGINE_NAMES } from './engines.js';
+
+const SETTINGS = ['engine', 'strict', 'on_unresolved', 'backend', 'encrypt', 'output_root', 'namespace_injection'];
+
+// `template_groups` in .sopsify.yaml give the templates matching a glob on
+// their name settings of their own, so templates needing different handling
+// are processed in one run:
+//
+//   template_groups:
+//     - match: 'apps/**'
+//       strict: true
+//     - match: ['legacy/*.yaml', 'vm/*.yaml']
+//       backend: ansible-vault
+//       output_root: 'ansible/${cluster}/${namespace}'
+//     - match: 'preview/**'
+//       encrypt: false
+//     - match: 'operators/*.yaml'
+//       namespace_injection: false
+//     - match: 'dotenv/*.env'
+//       engine: dotenv
+//
+// - `engine`: how the templates are read and written, `yaml` (the default),
+//   `dotenv` or `tfvars`, see engines.js
+// - `strict`, `on_unresolved`: as the top-level settings; `--strict` still
+//   applies to every template, a template's own `on_unresolved` still wins
+// - `backend`: encrypts with another backend than `--backend`
+// - `encrypt: false`: written unencrypted, as with `--no-encrypt`
+// - `output_root`: as `output_roots`, for every namespace of the templates
+// - `namespace_injection: false`: `metadata.namespace` is left as in the
+//   template, as with `--no-namespace-injection`
+//
+// The first group matching a template applies.
+export function templateGroups(config) {
+	const groups = config.template_groups ?? [];
+	if (!Array.isArray(groups)) {
+		throw new Error(`'template_groups' in ${configFile()} must be a list of groups with a 'match' glob`);
+	}
+	return groups.map((group, index) => {
+		const where = `template group ${index + 1} in ${configFile()}`;
+		if (!group || typeof group !== 'object' || Array.isArray(group)) {
+			throw new Error(`The ${where} must be a map with a 'match' glob and settings`);
+		}
+		const { match, ...settings } = group;
+		const patterns = [].concat(match ?? []);
+		if (patterns.length === 0 || !patterns.every(pattern => typeof pattern === 'string' && pattern.trim() !== '')) {
+			throw new Error(`The ${where} needs 'match': a glob or a list of globs on template names`);
+		}
+		for (const [name, value] of Object.entries(settings)) {
+			if (!SETTINGS.includes(name)) {
+				throw new Error(`Unknown setting '${name}' in ${where}, expected one of: match, ${SETTINGS.join(', ')}`);
+			}
+			if (['strict', 'encrypt', 'namespace_injection'].includes(name) && typeof value !== 'boolean') {
+				throw new Error(`'${name}' of ${where} must be true or false`);
+			}
+			if (['engine', 'backend', 'on_unresolved'].includes(name) && typeof value !== 'string') {
+				throw new Error(`'${name}' of ${where} must be a name`);
+			}
+			if (name === 'engine' && !ENGINE_NAMES.includes(value)) {
+				throw new Error(`Unknown engine '${value}' in ${where}, expected one of: ${ENGINE_NAMES.join(', ')}`);
+			}
+		}
+		return { patterns, matches: pathMatcher(patterns), settings };
+	});
+}
+
+// The settings of the first group matching the template, `{}` for none
+export function groupSettings(config, templateName) {
+	const group = templateGroups(config).find(candidate => candidate.matches(templateName));
+	return group ? group.settings : {};
+}
+
+// Backends named by groups, besides the one of the run
+export function groupBackends(config) {
+	return [...new Set(templateGroups(config).map(group => group.settings.backend).filter(Boolean))];
+}
+
+// The engine of the template's group
+export function groupEngine(config, templateName) {
+	return templateEngine(groupSettings(config, templateName).engine);
+}
@@ -0,0 +1,26 @@
+import { loadConfig, resolveNamespaceValues } from './sopsify.js';
+
+// Implements Terraform's `external` data source protocol: a JSON object is
+// read from stdin and a flat JSON object of strings is written to stdout.
+// Nothing else may be printed to stdout, so this command never logs.
+export async function tfSource() {
+	const query = JSON.parse(await readStdin() || '{}');
+	const { cluster, namespace, template } = query;
+
+	if (!cluster || !namespace) {
+		throw new Error(`tf-source query requires 'cluster' and 'namespace'`);
+	}
+
+	const config = await loadConfig();
+	const result = await resolveNamespaceValues(config, cluster, namespace, template);
+
+	process.stdout.write(JSON.stringify(result));
+}
+
+async function readStdin() {
+	const chunks = [];
+	for await (const chunk of process.stdin) {
+		chunks.push(chunk);
+	}
+	return Buffer.concat(chunks).toString('utf-8');
+}
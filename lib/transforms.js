@@ -0,0 +1,37 @@
+import { createHash } from 'crypto';
+
+// Transformations a value entry can list under `transform`, applied in order
+// after built-in references are expanded and before the value is substituted,
+// e.g. `transform: [trim, b64encode]` for a `data` field fed by a secret manager.
+const transforms = {
+	trim: value => value.trim(),
+	lower: value => value.toLowerCase(),
+	upper: value => value.toUpperCase(),
+	b64encode: value => Buffer.from(value, 'utf-8').toString('base64'),
+	b64decode: value => {
+		if (!/^[A-Za-z0-9+/]*={0,2}$/.test(value.replace(/\s/g, ''))) {
+			throw new Error('value is not valid base64');
+		}
+		return Buffer.from(value, 'base64').toString('utf-8');
+	},
+	sha256: value => createHash('sha256').update(value).digest('hex'),
+	json: value => JSON.stringify(value),
+};
+
+export function applyTransforms(value, entry) {
+	if (entry.transform === undefined) {
+		return value;
+	}
+	const names = [].concat(entry.transform);
+	return names.reduce((current, name) => {
+		const transform = transforms[name];
+		if (!transform) {
+			throw new Error(`Unknown transform '${name}' for key '${entry.key}', expected one of: ${Object.keys(transforms).join(', ')}`);
+		}
+		try {
+			return transform(String(current));
+		} catch (err) {
+			throw new Error(`Transform '${name}' failed for key '${entry.key}': ${err.message}`);
+		}
+	}, value);
+}
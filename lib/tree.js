@@ -0,0 +1,105 @@
+import { loadConfig } from './sopsify.js';
+import { loadNamespaceVars, withNamespaceVars } from './namespace-vars.js';
+import { isRuleEntry, matchRule } from './rules.js';
+import { isDefaultEntry } from './defaults.js';
+import { groupSettings } from './template-groups.js';
+import { namespaceSelector } from './namespace-select.js';
+import { configFile } from './config.js';
+
+// Prints the configuration as a tree of clusters, namespaces and the
+// templates rendered into each, with how many keys a namespace gets and where
+// they come from: its own entries, entries shared with other namespaces,
+// `match` rules and namespace variable files. Templates and values are never
+// read.
+export async function tree(opts) {
+	const config = await loadConfig();
+	const namespaceVars = await loadNamespaceVars();
+	const selection = namespaceSelector(opts.namespace);
+	const clusters = config.sopsify
+		.map(item => Object.entries(item)[0])
+		.filter(([cluster]) => !opts.cluster || cluster === opts.cluster);
+	if (opts.cluster && clusters.length === 0) {
+		throw new Error(`Cluster '${opts.cluster}' is not defined in ${configFile()}`);
+	}
+
+	console.log(`🌳 ${configFile()}`);
+	clusters.forEach(([cluster, templateDefs], index) => {
+		const last = index === clusters.length - 1;
+		const namespaces = clusterNamespaces(cluster, templateDefs, config, namespaceVars)
+			.filter(({ namespace }) => !selection || selection.matches(namespace));
+		const globals = templateDefs.filter(def => def.scope === 'global');
+		const keyCount = new Set(namespaces.flatMap(({ templates }) => templates.flatMap(({ keys }) => keys.map(({ key }) => key)))).size;
+		console.log(`${last ? '└─' : '├─'} 🌍 ${cluster} (${count(namespaces.length, 'namespace')}, ${count(keyCount, 'key')})`);
+
+		const children = [
+			...globals.map(def => ({ label: `🌐 global: ${def.template}${groupLabel(config, def.template)}`, children: [] })),
+			...namespaces.map(({ namespace, templates }) => ({
+				label: `📁 ${namespace} (${count(new Set(templates.flatMap(({ keys }) => keys.map(({ key }) => key))).size, 'key')})`,
+				children: templates.map(({ template, keys }) => `📄 ${template}${groupLabel(config, template)}: ${describeKeys(keys)}`),
+			})),
+		];
+		printChildren(children, last ? '   ' : '│  ');
+	});
+}
+
+// The namespaces of a cluster in the order they are first listed, each with
+// the templates rendered into it and the keys those get
+function clusterNamespaces(cluster, templateDefs, config, namespaceVars) {
+	const byNamespace = new Map();
+	for (const templateDef of templateDefs.filter(def => def.scope !== 'global')) {
+		const values = withNamespaceVars(templateDef.values ?? [], namespaceVars, cluster);
+		const explicit = values.filter(entry => !isRuleEntry(entry));
+		const namespaces = new Set([...(templateDef.namespaces || []), ...explicit.flatMap(entry => entry.namespaces)]);
+		for (const namespace of namespaces) {
+			const keys = [...new Set(values.map(entry => entry.key))]
+				.map(key => ({ key, source: keySource(values, key, namespace) }))
+				.filter(({ source }) => source);
+			if (!byNamespace.has(namespace)) {
+				byNamespace.set(namespace, []);
+			}
+			byNamespace.get(namespace).push({ template: templateDef.template, keys });
+		}
+	}
+	return [...byNamespace].map(([namespace, templates]) => ({ namespace, templates }));
+}
+
+// An explicit entry wins over rules, as when rendering
+function keySource(values, key, namespace) {
+	const entry = values.find(candidate => candidate.key === key && !isRuleEntry(candidate) && candidate.namespaces.includes(namespace));
+	if (entry) {
+		return entry.from ? 'vars' : entry.namespaces.length > 1 ? 'shared' : 'own';
+	}
+	const rule = values.find(candidate => candidate.key === key && isRuleEntry(candidate) && matchRule(candidate, namespace));
+	if (!rule) {
+		return undefined;
+	}
+	return isDefaultEntry(rule) ? 'default' : 'rule';
+}
+
+const SOURCES = { own: 'own', shared: 'shared', rule: 'from rules', default: 'from defaults', vars: 'from namespace variables' };
+
+function describeKeys(keys) {
+	const parts = Object.entries(SOURCES)
+		.map(([source, label]) => [keys.filter(key => key.source === source).length, label])
+		.filter(([number]) => number > 0)
+		.map(([number, label]) => `${number} ${label}`);
+	return `${count(keys.length, 'key')}${parts.length > 0 ? ` (${parts.join(', ')})` : ''}`;
+}
+
+function groupLabel(config, template) {
+	const settings = Object.entries(groupSettings(config, template));
+	return settings.length > 0 ? ` [group: ${settings.map(([name, value]) => `${name}: ${value}`).join(', ')}]` : '';
+}
+
+function count(number, noun) {
+	return `${number} ${noun}${number === 1 ? '' : 's'}`;
+}
+
+function printChildren(children, indent) {
+	children.forEach((child, index) => {
+		const last = index === children.length - 1;
+		const { label, children: grandchildren } = typeof child === 'string' ? { label: child, children: [] } : child;
+		console.log(`${indent}${last ? '└─' : '├─'} ${label}`);
+		printChildren(grandchildren, `${indent}${last ? '   ' : '│  '}`);
+	});
+}
@@ -0,0 +1,76 @@
+import fs from 'fs/promises';
+import os from 'os';
+import yaml from 'js-yaml';
+import { join, resolve } from 'path';
+import { createHash } from 'crypto';
+
+const XDG_DEFAULTS = {
+	config: ['XDG_CONFIG_HOME', ['.config']],
+	state: ['XDG_STATE_HOME', ['.local', 'state']],
+	cache: ['XDG_CACHE_HOME', ['.cache']],
+};
+
+// sopsify's folder below an XDG base directory: `config`, `state` or `cache`
+export function xdgDir(kind) {
+	const [variable, fallback] = XDG_DEFAULTS[kind];
+	return join(process.env[variable] || join(os.homedir(), ...fallback), 'sopsify');
+}
+
+// State of the project in the working directory, kept out of the project
+// itself, e.g. `~/.local/state/sopsify/projects/<hash of the path>/rotation.json`
+export function projectStateFile(name) {
+	const project = createHash('sha256').update(resolve('.')).digest('hex').slice(0, 16);
+	return join(xdgDir('state'), 'projects', project, name);
+}
+
+// `~/.config/sopsify/config.yaml` (or $SOPSIFY_USER_CONFIG; empty to disable)
+export function userConfigPath() {
+	return process.env.SOPSIFY_USER_CONFIG ?? join(xdgDir('config'), 'config.yaml');
+}
+
+export async function loadUserConfig() {
+	const path = userConfigPath();
+	if (!path) {
+		return {};
+	}
+	let config;
+	try {
+		config = yaml.load(await fs.readFile(path, 'utf-8')) || {};
+	} catch (err) {
+		if (err.code === 'ENOENT') {
+			return {};
+		}
+		throw new Error(`Could not read user config '${path}': ${err.message}`);
+	}
+	if (typeof config !== 'object' || Array.isArray(config) || (config.defaults !== undefined && (typeof config.defaults !== 'object' || Array.isArray(config.defaults)))) {
+		throw new Error(`Error in '${path}': expected a map with a 'defaults' map of option names to values`);
+	}
+	return config;
+}
+
+// Applies `defaults` of the user config to every option of the command that
+// was not given on the command line. Options a command does not have are
+// ignored, as the defaults are shared by all commands.
+export async function applyUserDefaults(command) {
+	setOptionValues(command, (await loadUserConfig()).defaults || {}, 'config');
+}
+
+// Sets options not given on the command line from a map keyed by the long
+// option name without dashes or "no-", e.g. `backend: ansible-vault`,
+// `skip-unchanged: true` or `encrypt: false`. Returns the names the command
+// has no option for.
+export function setOptionValues(command, values, source) {
+	const unknown = new Set(Object.keys(values));
+	for (const option of command.options) {
+		const name = option.long.replace(/^--(no-)?/, '');
+		const key = option.attributeName();
+		if (!(name in values)) {
+			continue;
+		}
+		unknown.delete(name);
+		if (command.getOptionValueSource(key) !== 'cli') {
+			command.setOptionValueWithSource(key, values[name], source);
+		}
+	}
+	return [...unknown];
+}
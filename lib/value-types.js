@@ -0,0 +1,59 @@
+// Types a value entry can declare under `type`, checked for every namespace
+// before anything is rendered, e.g. `type: url` for an endpoint a secret
+// manager returns. Each check returns why a value does not match, or nothing.
+const PEM_BLOCK = /-----BEGIN ([A-Z0-9 ]+)-----\r?\n([\s\S]*?)\r?\n-----END ([A-Z0-9 ]+)-----/g;
+
+const types = {
+	url: value => {
+		let url;
+		try {
+			url = new URL(value);
+		} catch {
+			return 'not an absolute URL';
+		}
+		return url.host === '' && !['file:', 'mailto:'].includes(url.protocol) ? 'URL has no host' : undefined;
+	},
+	email: value => (/^[^\s@]+@[^\s@]+\.[^\s@]+$/.test(value) ? undefined : 'not an email address'),
+	port: value => (/^\d+$/.test(value) && Number(value) >= 1 && Number(value) <= 65535 ? undefined : 'not a port number (1-65535)'),
+	base64: value => {
+		const compact = value.replace(/\s/g, '');
+		return /^[A-Za-z0-9+/]*={0,2}$/.test(compact) && compact.length % 4 === 0 ? undefined : 'not valid base64';
+	},
+	pem: value => {
+		const blocks = [...value.matchAll(PEM_BLOCK)];
+		if (blocks.length === 0) {
+			return 'no PEM block (-----BEGIN ...----- / -----END ...-----)';
+		}
+		if (value.replace(PEM_BLOCK, '').trim() !== '') {
+			return 'text outside of the PEM blocks';
+		}
+		for (const [, begin, body, end] of blocks) {
+			if (begin !== end) {
+				return `PEM block BEGIN ${begin} ends with END ${end}`;
+			}
+			if (types.base64(body.split(/\r?\n/).filter(line => !line.includes(':')).join(''))) {
+				return `PEM block ${begin} is not valid base64`;
+			}
+		}
+		return undefined;
+	},
+};
+
+export const VALUE_TYPES = Object.keys(types);
+
+// The declared type of a key: entries of one key must not declare different types
+export function keyType(values, key, where) {
+	const declared = [...new Set(values.filter(entry => entry.key === key && entry.type !== undefined).map(entry => entry.type))];
+	if (declared.length > 1) {
+		throw new Error(`Key '${key}' ${where} declares different types: ${declared.join(', ')}`);
+	}
+	if (declared.length === 1 && !types[declared[0]]) {
+		throw new Error(`Unknown type '${declared[0]}' for key '${key}' ${where}, expected one of: ${VALUE_TYPES.join(', ')}`);
+	}
+	return declared[0];
+}
+
+// Why the value does not match the type, or undefined; values are never quoted
+export function typeProblem(type, value) {
+	return types[type](String(value));
+}
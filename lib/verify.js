@@ -0,0 +1,189 @@
+import fs from 'fs/promises';
+import yaml from 'js-yaml';
+import { join } from 'path';
+import { loadConfig, dumpDocuments } from './sopsify.js';
+import { overriddenOutputDirs } from './output-roots.js';
+import { filesBelow } from './files.js';
+import { getBackend } from './backends.js';
+import { sopsEnv } from './sops-env.js';
+
+// Statically checks the sops metadata of every output against the
+// `recipients:` rules of .sopsify.yaml, e.g. that every production file can
+// be decrypted with the break-glass KMS key. With `roundTrip` every output is
+// also decrypted in memory and must parse as YAML, catching corrupted or
+// partially written files.
+export async function verify(opts) {
+	const config = await loadConfig();
+	const rules = config.recipients || [];
+	if (rules.length === 0 && !opts.roundTrip) {
+		console.warn(`⚠️ No 'recipients' rules defined in .sopsify.yaml, nothing to verify`);
+		return;
+	}
+
+	const outputs = await listOutputs(opts.dir, config);
+	const backend = opts.roundTrip ? getBackend(opts) : undefined;
+	const checks = [rules.length > 0 && 'recipients', backend && 'decryption'].filter(Boolean);
+	console.log(`🔎 Verifying ${checks.join(' and ')} of ${outputs.length} outputs`);
+
+	let failed = 0;
+	for (const output of outputs) {
+		const [document] = yaml.loadAll(await fs.readFile(output.path, 'utf-8'));
+		if (!document || !document.sops) {
+			failed++;
+			console.log(`   ❌ ${output.path}: no sops metadata, file is not encrypted`);
+			continue;
+		}
+
+		const problems = recipientProblems(document.sops, rules, output);
+		if (backend) {
+			const { problem } = decryptYaml(backend, await fs.readFile(output.path, 'utf-8'), sopsEnv(config, output.cluster, output.namespace));
+			if (problem) {
+				problems.push(problem);
+			}
+		}
+		if (problems.length > 0) {
+			failed++;
+			console.log(`   ❌ ${output.path}: ${problems.join('; ')}`);
+		} else {
+			console.log(`   ✅ ${output.path}`);
+		}
+	}
+
+	if (failed > 0) {
+		throw new Error(`${failed} of ${outputs.length} outputs ${backend ? 'failed verification' : 'are not encrypted for all expected recipients'}`);
+	}
+}
+
+// Compares the recipients in sops metadata with the rules matching the output
+// ({cluster, namespace}). Rules with `exclusive: true` also reject recipients
+// no matching rule requires, e.g. a test key on a production file.
+export function recipientProblems(metadata, rules, output) {
+	const present = sopsRecipients(metadata);
+	const matching = rules.filter(r => ruleMatches(r, output));
+	const expected = {};
+	const missing = [];
+
+	for (const rule of matching) {
+		for (const [type, recipients] of Object.entries(rule.required || {})) {
+			if (!(type in present)) {
+				throw new Error(`Unknown recipient type '${type}' in 'recipients', expected one of: ${Object.keys(present).join(', ')}`);
+			}
+			for (const recipient of recipients) {
+				(expected[type] = expected[type] || new Set()).add(recipient);
+				if (!present[type].has(recipient)) {
+					missing.push(`${type} ${recipient}`);
+				}
+			}
+		}
+	}
+
+	const problems = [];
+	if (missing.length > 0) {
+		problems.push(`missing recipients: ${missing.join(', ')}`);
+	}
+	if (matching.some(rule => rule.exclusive)) {
+		const unexpected = Object.entries(present)
+			.flatMap(([type, recipients]) => [...recipients].filter(r => !(expected[type] && expected[type].has(r))).map(r => `${type} ${r}`));
+		if (unexpected.length > 0) {
+			problems.push(`unexpected recipients: ${unexpected.join(', ')}`);
+		}
+	}
+	return problems;
+}
+
+function ruleMatches(rule, output) {
+	return (!rule.clusters || rule.clusters.includes(output.cluster))
+		&& (!rule.namespaces || rule.namespaces.includes(output.namespace));
+}
+
+// Collects recipients from the flat sops layout and from `key_groups`
+export function sopsRecipients(metadata) {
+	const present = { age: new Set(), pgp: new Set(), kms: new Set(), gcp_kms: new Set(), azure_kv: new Set(), hc_vault: new Set() };
+	const groups = [metadata, ...(metadata.key_groups || [])];
+
+	for (const group of groups) {
+		(group.age || []).forEach(k => present.age.add(k.recipient));
+		(group.pgp || []).forEach(k => present.pgp.add(k.fp));
+		(group.kms || []).forEach(k => present.kms.add(k.arn));
+		(group.gcp_kms || []).forEach(k => present.gcp_kms.add(k.resource_id));
+		(group.azure_kv || []).forEach(k => present.azure_kv.add(`${k.vault_url}/keys/${k.name}`));
+		(group.hc_vault || []).forEach(k => present.hc_vault.add(`${k.vault_address}/v1/${k.engine_path}/keys/${k.key_name}`));
+	}
+
+	return present;
+}
+
+// Outputs are laid out as <dir>/<cluster>/{secrets,snapshots}/<namespace>/<file>
+// and <dir>/<cluster>/global/<file> (no namespace), plus the folders of
+// namespaces with an output root of their own
+export async function listOutputs(dir, config = {}) {
+	const outputs = [];
+	for (const cluster of await readDirs(dir)) {
+		outputs.push(...await encryptedFiles(join(dir, cluster, 'global'), cluster, undefined));
+		for (const kind of ['secrets', 'snapshots']) {
+			const kindDir = join(dir, cluster, kind);
+			for (const namespace of await readDirs(kindDir)) {
+				outputs.push(...await encryptedFiles(join(kindDir, namespace), cluster, namespace));
+			}
+		}
+	}
+	for (const { cluster, namespace, dir: nsDir } of overriddenOutputDirs(config)) {
+		outputs.push(...await encryptedFiles(nsDir, cluster, namespace));
+	}
+	return outputs;
+}
+
+async function encryptedFiles(nsDir, cluster, namespace) {
+	const files = await filesBelow(nsDir, file => /\.enc\.ya?ml$/.test(file));
+	return files.map(file => ({ cluster, namespace, file, path: join(nsDir, file) }));
+}
+
+async function readDirs(dir) {
+	try {
+		const entries = await fs.readdir(dir, { withFileTypes: true });
+		return entries.filter(e => e.isDirectory()).map(e => e.name);
+	} catch {
+		return [];
+	}
+}
+
+// Decrypts content in memory: the plaintext, or why it does not decrypt
+function decryptOutput(backend, content, env, type) {
+	try {
+		return { plaintext: backend.decryptContent(content, env, type) };
+	} catch (err) {
+		return { problem: `cannot be decrypted: ${err.stderr ? err.stderr.toString().trim() : err.message}` };
+	}
+}
+
+// Decrypts content in memory: the plaintext, or why it does not decrypt to YAML
+function decryptYaml(backend, content, env) {
+	const { plaintext, problem } = decryptOutput(backend, content, env);
+	if (problem) {
+		return { problem };
+	}
+	try {
+		if (yaml.loadAll(plaintext).filter(document => document !== null && document !== undefined).length === 0) {
+			return { problem: 'decrypts to no YAML documents' };
+		}
+	} catch (err) {
+		return { problem: `decrypts to invalid YAML: ${err.reason || err.message}` };
+	}
+	return { plaintext };
+}
+
+// `--verify` of a run: why a freshly encrypted output does not decrypt to the
+// rendered plaintext, or undefined. sops re-formats what it decrypts, so
+// content that differs is compared as dumped by sopsify. Outputs of other
+// template engines than yaml must decrypt to what was rendered as it is.
+export function roundTripProblem(backend, content, manifest, env, engine) {
+	if (engine && engine.name !== 'yaml') {
+		const { plaintext, problem } = decryptOutput(backend, content, env, engine.sopsType);
+		return problem || (plaintext === manifest ? undefined : 'decrypts to other content than was rendered');
+	}
+	const { plaintext, problem } = decryptYaml(backend, content, env);
+	if (problem) {
+		return problem;
+	}
+	return plaintext === manifest || dumpDocuments(yaml.loadAll(plaintext)) === manifest ? undefined : 'decrypts to other content than was rendered';
+}
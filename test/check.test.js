@@ -0,0 +1,73 @@
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { project, sopsify, writeFiles } from './helpers.js';
+
+const APP = `apiVersion: v1
+kind: Secret
+metadata:
+  name: app
+stringData:
+  password: \${db-password}
+`;
+
+const CONFIG = `sopsify:
+  - production:
+    - template: app.yaml
+      values:
+        - key: db-password
+          value: hunter2
+          namespaces: [payments, billing]
+`;
+
+function rendered(t, config = CONFIG, files = {}) {
+	const dir = project(t, { '.sopsify.yaml': config, 'secrets/app.yaml': APP, 'clusters/production/.keep': '', ...files });
+	const run = sopsify(dir, ['-t', 'secrets']);
+	assert.equal(run.status, 0, run.output);
+	return dir;
+}
+
+test('check passes when the outputs match the config', t => {
+	const dir = rendered(t);
+
+	const run = sopsify(dir, ['check']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /2 outputs match/);
+});
+
+test('check reports outputs of namespaces no longer configured', t => {
+	const dir = rendered(t);
+	writeFiles(dir, { '.sopsify.yaml': CONFIG.replace('[payments, billing]', '[payments]') });
+
+	const run = sopsify(dir, ['check']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /Orphaned outputs.*: 1/);
+	assert.match(run.output, /clusters\/production\/secrets\/billing\/app\.enc\.yaml/);
+});
+
+test('check reports templates without outputs', t => {
+	const dir = rendered(t);
+	writeFiles(dir, { '.sopsify.yaml': CONFIG.replace('    - template: app.yaml', '    - template: db.yaml\n      values:\n        - key: db-password\n          value: x\n          namespaces: [payments]\n    - template: app.yaml') });
+
+	const run = sopsify(dir, ['check']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /production\/db\.yaml \(no output in any namespace\)/);
+});
+
+test('check expects outputs in the output_root of their template group', t => {
+	const groups = `template_groups:
+  - match: app.yaml
+    output_root: 'deploy/\${cluster}/\${namespace}'
+`;
+	const dir = rendered(t, `${groups}${CONFIG}`);
+
+	let run = sopsify(dir, ['check']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /2 outputs match/);
+
+	// A copy left in the default layout is not where the template writes it
+	writeFiles(dir, { 'clusters/production/secrets/payments/app.enc.yaml': 'stale' });
+	run = sopsify(dir, ['check']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /Orphaned outputs.*: 1/);
+	assert.match(run.output, /clusters\/production\/secrets\/payments\/app\.enc\.yaml/);
+});
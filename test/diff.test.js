@@ -0,0 +1,125 @@
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { cpSync } from 'fs';
+import { join } from 'path';
+import { project, sopsify, writeFiles } from './helpers.js';
+
+const APP = `apiVersion: v1
+kind: Secret
+metadata:
+  name: app
+stringData:
+  password: \${db-password}
+  user: admin
+`;
+
+function config(password) {
+	return `sopsify:
+  - production:
+    - template: app.yaml
+      values:
+        - key: db-password
+          value: ${password}
+          namespaces: [payments]
+`;
+}
+
+// The output tree rendered with `before`, kept in `old/`, and rendered again
+// with `after`
+function renderTwice(t, before, after) {
+	const dir = project(t, { '.sopsify.yaml': before['.sopsify.yaml'], 'clusters/production/.keep': '', ...before.files });
+	let run = sopsify(dir, ['-t', 'secrets']);
+	assert.equal(run.status, 0, run.output);
+	cpSync(join(dir, 'clusters'), join(dir, 'old'), { recursive: true });
+	writeFiles(dir, { '.sopsify.yaml': after['.sopsify.yaml'], ...after.files });
+	run = sopsify(dir, ['-t', 'secrets']);
+	assert.equal(run.status, 0, run.output);
+	return dir;
+}
+
+test('diff of two output trees lists changed keys and added files, values redacted', t => {
+	const dir = renderTwice(t,
+		{ '.sopsify.yaml': config('old-password'), files: { 'secrets/app.yaml': APP } },
+		{ '.sopsify.yaml': `${config('new-password')}    - template: db.yaml
+      values:
+        - key: db-password
+          value: new-password
+          namespaces: [payments]
+`, files: { 'secrets/db.yaml': APP.replace('name: app', 'name: db') } });
+
+	const run = sopsify(dir, ['diff', 'old', 'clusters']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /~ production\/secrets\/payments\/app\.enc\.yaml/);
+	assert.match(run.output, /~ stringData\.password \(changed\)/);
+	assert.doesNotMatch(run.output, /stringData\.user/);
+	assert.match(run.output, /\+ production\/secrets\/payments\/db\.enc\.yaml \(added\)/);
+	assert.doesNotMatch(run.output, /old-password|new-password/);
+});
+
+test('diff --show-values prints the values of changed keys', t => {
+	const dir = renderTwice(t,
+		{ '.sopsify.yaml': config('old-password'), files: { 'secrets/app.yaml': APP } },
+		{ '.sopsify.yaml': config('new-password'), files: {} });
+	const file = 'production/secrets/payments/app.enc.yaml';
+
+	const run = sopsify(dir, ['diff', join('old', file), join('clusters', file), '--show-values']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /old-password/);
+	assert.match(run.output, /new-password/);
+});
+
+test('diff reports identical trees as such', t => {
+	const dir = renderTwice(t,
+		{ '.sopsify.yaml': config('password'), files: { 'secrets/app.yaml': APP } },
+		{ '.sopsify.yaml': config('password'), files: {} });
+
+	const run = sopsify(dir, ['diff', 'old', 'clusters']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /No differences/);
+});
+
+test('diff compares multi-document outputs document by document', t => {
+	const TWO = `${APP}---
+apiVersion: v1
+kind: Secret
+metadata:
+  name: db
+stringData:
+  password: \${db-password}
+`;
+	const dir = renderTwice(t,
+		{ '.sopsify.yaml': config('old-password'), files: { 'secrets/app.yaml': TWO } },
+		{ '.sopsify.yaml': config('old-password'), files: { 'secrets/app.yaml': TWO.replace('  password: ${db-password}\n', '  password: static\n') } });
+
+	const run = sopsify(dir, ['diff', 'old', 'clusters']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /~ Secret\/app\.stringData\.password \(changed\)/);
+	assert.doesNotMatch(run.output, /Secret\/db/);
+});
+
+test('diff compares the parts of split outputs as one output', t => {
+	const big = (limit, c) => `sopsify:
+  - production:
+    - template: big.yaml
+      max_secret_bytes: ${limit}
+      values:
+        - key: ka
+          value: ${'a'.repeat(200)}
+          namespaces: [payments]
+        - key: kb
+          value: ${'b'.repeat(200)}
+          namespaces: [payments]
+        - key: kc
+          value: ${c.repeat(200)}
+          namespaces: [payments]
+`;
+	const files = { 'secrets/big.yaml': 'apiVersion: v1\nkind: Secret\nmetadata:\n  name: big\nstringData:\n  a: ${ka}\n  b: ${kb}\n  c: ${kc}\n' };
+	const dir = renderTwice(t, { '.sopsify.yaml': big(600, 'c'), files }, { '.sopsify.yaml': big(450, 'd'), files: {} });
+
+	const run = sopsify(dir, ['diff', 'old', 'clusters']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /~ production\/secrets\/payments\/big\.enc\.yaml/);
+	assert.match(run.output, /~ stringData\.c \(changed\)/);
+	assert.doesNotMatch(run.output, /stringData\.[ab]|part-/);
+	assert.match(run.output, /1 of 1 files differ/);
+});
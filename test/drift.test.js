@@ -0,0 +1,114 @@
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { project, sopsify, liveSecrets } from './helpers.js';
+
+const CONFIG = `sopsify:
+  - production:
+    - template: app.yaml
+      values:
+        - key: db-password
+          value: hunter2
+          namespaces: [payments]
+        - key: api-token
+          value: token-1
+          namespaces: [payments]
+`;
+
+const APP = `apiVersion: v1
+kind: Secret
+metadata:
+  name: app
+stringData:
+  password: \${db-password}
+  token: \${api-token}
+`;
+
+function rendered(t) {
+	const dir = project(t, { '.sopsify.yaml': CONFIG, 'secrets/app.yaml': APP, 'clusters/production/.keep': '' });
+	const run = sopsify(dir, ['-t', 'secrets']);
+	assert.equal(run.status, 0, run.output);
+	return dir;
+}
+
+test('drift reports outputs matching the live Secrets as in sync', t => {
+	const dir = rendered(t);
+	liveSecrets(dir, 'payments', { app: { password: 'hunter2', token: 'token-1' } });
+
+	const run = sopsify(dir, ['drift', '--namespace', 'payments']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /app\.enc\.yaml: in sync/);
+});
+
+test('drift reports changed, missing and extra keys without their values', t => {
+	const dir = rendered(t);
+	liveSecrets(dir, 'payments', { app: { password: 'hand-edited', extra: 'x' } });
+
+	const run = sopsify(dir, ['drift', '--namespace', 'payments']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /password: hash mismatch/);
+	assert.match(run.output, /token: missing in cluster/);
+	assert.match(run.output, /extra: only in cluster/);
+	for (const value of ['hunter2', 'hand-edited', 'token-1']) {
+		assert.doesNotMatch(run.output, new RegExp(value));
+	}
+});
+
+test('drift reports Secrets missing in the cluster', t => {
+	const dir = rendered(t);
+
+	const run = sopsify(dir, ['drift', '--namespace', 'payments']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /'app' does not exist in the cluster/);
+});
+
+test('drift checks every Secret of a multi-document output', t => {
+	const dir = project(t, {
+		'.sopsify.yaml': CONFIG,
+		'secrets/app.yaml': `${APP}---
+apiVersion: v1
+kind: Secret
+metadata:
+  name: app-token
+stringData:
+  token: \${api-token}
+`,
+		'clusters/production/.keep': '',
+	});
+	let run = sopsify(dir, ['-t', 'secrets']);
+	assert.equal(run.status, 0, run.output);
+	liveSecrets(dir, 'payments', { app: { password: 'hunter2', token: 'token-1' }, 'app-token': { token: 'token-2' } });
+
+	run = sopsify(dir, ['drift', '--namespace', 'payments']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /app\.enc\.yaml \(app\): in sync/);
+	assert.match(run.output, /app\.enc\.yaml \(app-token\): Secret 'app-token' has drifted/);
+	assert.match(run.output, /token: hash mismatch/);
+});
+
+test('drift compares split outputs with all of their part Secrets', t => {
+	const value = letter => letter.repeat(200);
+	const dir = project(t, {
+		'.sopsify.yaml': `sopsify:
+  - production:
+    - template: big.yaml
+      max_secret_bytes: 450
+      values:
+${['a', 'b', 'c'].map(letter => `        - key: k${letter}\n          value: ${value(letter)}\n          namespaces: [payments]\n`).join('')}`,
+		'secrets/big.yaml': 'apiVersion: v1\nkind: Secret\nmetadata:\n  name: big\nstringData:\n  a: ${ka}\n  b: ${kb}\n  c: ${kc}\n',
+		'clusters/production/.keep': '',
+	});
+	let run = sopsify(dir, ['-t', 'secrets']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /big\.part-3\.enc\.yaml/);
+	liveSecrets(dir, 'payments', { 'big-part-1': { a: value('a') }, 'big-part-2': { b: value('b') } });
+
+	run = sopsify(dir, ['drift', '--namespace', 'payments']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /big\.enc\.yaml: Secret 'big-part-3' does not exist in the cluster/);
+
+	// The keys may be spread over the parts in the cluster differently
+	liveSecrets(dir, 'payments', { 'big-part-2': { b: value('b'), c: value('c') }, 'big-part-3': {} });
+	run = sopsify(dir, ['drift', '--namespace', 'payments']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /big\.enc\.yaml: in sync/);
+});
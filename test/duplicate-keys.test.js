@@ -0,0 +1,75 @@
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { project, sopsify, decrypted } from './helpers.js';
+
+const APP = `apiVersion: v1
+kind: Secret
+metadata:
+  name: app
+stringData:
+  password: \${db-password}
+`;
+
+// One entry of db-password for payments per value
+function config(...values) {
+	return `sopsify:
+  - production:
+    - template: app.yaml
+      values:
+${values.map(value => `        - key: db-password\n          value: ${value}\n          namespaces: [payments]\n`).join('')}`;
+}
+
+function app(t, sopsifyYaml) {
+	return project(t, { '.sopsify.yaml': sopsifyYaml, 'secrets/app.yaml': APP, 'clusters/production/.keep': '' });
+}
+
+test('keys with conflicting values fail, naming both entries', t => {
+	const dir = app(t, config('one', 'two'));
+
+	const run = sopsify(dir, ['-t', 'secrets']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /Key 'db-password' of production\/app\.yaml has conflicting values for namespace payments/);
+	assert.match(run.output, /\.sopsify\.yaml:5:11 \(production\[0\]\.values\[0\]\), \.sopsify\.yaml:8:11 \(production\[0\]\.values\[1\]\)/);
+	assert.doesNotMatch(run.output, /Encrypted/);
+});
+
+test('keys given the same value twice fail too', t => {
+	const dir = app(t, config('one', 'one'));
+
+	const run = sopsify(dir, ['-t', 'secrets']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /is given the same value more than once for namespace payments/);
+});
+
+test('--allow-duplicate-keys warns and uses the last entry', t => {
+	const dir = app(t, config('one', 'two'));
+
+	const run = sopsify(dir, ['-t', 'secrets', '--allow-duplicate-keys']);
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /has conflicting values for namespace payments: .*; using the last entry/);
+	assert.match(decrypted(dir, 'clusters/production/secrets/payments/app.enc.yaml'), /password: two/);
+});
+
+test('a matrix row giving a key a value of .sopsify.yaml again fails, naming the row', t => {
+	const dir = project(t, {
+		'.sopsify.yaml': config('one'),
+		'secrets/app.yaml': APP,
+		'secrets.csv': 'key,template,cluster,payments\ndb-password,app.yaml,production,three\n',
+		'clusters/production/.keep': '',
+	});
+
+	const run = sopsify(dir, ['-t', 'secrets', '--matrix', 'secrets.csv']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /\.sopsify\.yaml:5:11 \(production\[0\]\.values\[0\]\), row secrets\.csv:2/);
+});
+
+test('duplicates in an environment item are located in that item', t => {
+	const dir = app(t, `environments:
+  prod: [production]
+
+${config('one', 'two').replace('  - production:', '  - environment:prod:')}`);
+
+	const run = sopsify(dir, ['-t', 'secrets']);
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /\.sopsify\.yaml:8:11 \(environment:prod\[0\]\.values\[0\]\), \.sopsify\.yaml:11:11 \(environment:prod\[0\]\.values\[1\]\)/);
+});
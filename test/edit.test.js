@@ -0,0 +1,115 @@
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { project, sopsify, edit, readFile } from './helpers.js';
+
+const APP = `apiVersion: v1
+kind: Secret
+metadata:
+  name: app
+stringData:
+  password: \${db-password}
+  token: \${api-token}
+  host: \${host}
+  url: https://db.internal/
+`;
+
+const CONFIG = `sopsify:
+  - production:
+    - template: app.yaml
+      values:
+        # rotated by hand
+        - key: db-password
+          value: hunter2
+          namespaces: [payments]
+        - key: api-token
+          value: shared-token
+          namespaces: [payments, billing]
+        - key: host
+          value: db.internal
+          namespaces: [payments]
+        - key: db-password
+          value: billing-password
+          namespaces: [billing]
+        - key: host
+          value: billing.internal
+          namespaces: [billing]
+`;
+
+const OUTPUT = 'clusters/production/secrets/payments/app.enc.yaml';
+
+function rendered(t, config = CONFIG, files = {}) {
+	const dir = project(t, { '.sopsify.yaml': config, 'secrets/app.yaml': APP, 'clusters/production/.keep': '', ...files });
+	const run = sopsify(dir, ['-t', 'secrets']);
+	assert.equal(run.status, 0, run.output);
+	return dir;
+}
+
+test('edit reports changed fields without their values', t => {
+	const dir = rendered(t);
+
+	const run = edit(dir, [OUTPUT], { password: 'rotated' });
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /1 fields changed: Secret\/app stringData\.password/);
+	assert.match(run.output, /pass --sync/);
+	assert.doesNotMatch(run.output, /rotated|hunter2/);
+	assert.equal(readFile(dir, '.sopsify.yaml'), CONFIG);
+});
+
+test('edit --sync writes values back to the entry of the namespace, keeping the comments', t => {
+	const dir = rendered(t);
+
+	const run = edit(dir, [OUTPUT, '--sync', '-t', 'secrets'], { password: 'rotated' });
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /Written back to \.sopsify\.yaml: db-password/);
+	assert.equal(readFile(dir, '.sopsify.yaml'), CONFIG.replace('value: hunter2', 'value: "rotated"'));
+});
+
+test('edit --sync leaves shared entries and fields that are not a placeholder', t => {
+	const dir = rendered(t);
+
+	const run = edit(dir, [OUTPUT, '--sync', '-t', 'secrets'], { token: 'other', url: 'https://elsewhere/' });
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /Not synced: Secret\/app stringData\.token: the entry of 'api-token' is shared with billing/);
+	assert.match(run.output, /Not synced: Secret\/app stringData\.url: not a \$\{key\} placeholder/);
+	assert.equal(readFile(dir, '.sopsify.yaml'), CONFIG);
+});
+
+test('edit --sync maps the parts of split outputs to their template', t => {
+	const password = 'p'.repeat(150);
+	const config = CONFIG.replace('    - template: app.yaml\n', '    - template: app.yaml\n      max_secret_bytes: 400\n')
+		.replace('hunter2', password).replace('value: db.internal', `value: ${'h'.repeat(150)}`);
+	const dir = rendered(t, config);
+
+	const run = edit(dir, ['clusters/production/secrets/payments/app.part-1.enc.yaml', '--sync', '-t', 'secrets'], { password: 'rotated' });
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /Secret\/app-part-1 stringData\.password/);
+	assert.match(run.output, /Written back to \.sopsify\.yaml: db-password/);
+	assert.equal(readFile(dir, '.sopsify.yaml'), config.replace(`value: ${password}`, 'value: "rotated"'));
+});
+
+test('edit refuses outputs of backends other than sops', t => {
+	const dir = rendered(t);
+
+	const run = edit(dir, [OUTPUT, '--backend', 'rops'], { password: 'rotated' });
+	assert.notEqual(run.status, 0);
+	assert.match(run.output, /encrypted with the rops backend/);
+});
+
+test('edit --sync writes to the entry of the cluster, not to one of its environment', t => {
+	const config = `environments:
+  prod: [production]
+sopsify:
+  - environment:prod:
+    - template: app.yaml
+      values:
+        - key: api-token
+          value: env-token
+          namespaces: [payments]
+${CONFIG.replace('sopsify:\n', '').replace('          value: shared-token\n          namespaces: [payments, billing]', '          value: shared-token\n          namespaces: [billing]')}`;
+	const dir = rendered(t, config);
+
+	const run = edit(dir, [OUTPUT, '--sync', '-t', 'secrets'], { token: 'other', password: 'rotated' });
+	assert.equal(run.status, 0, run.output);
+	assert.match(run.output, /Not synced: Secret\/app stringData\.token: .*environment:<name> item/);
+	assert.equal(readFile(dir, '.sopsify.yaml'), config.replace('value: hunter2', 'value: "rotated"'));
+});
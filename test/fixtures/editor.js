@@ -0,0 +1,11 @@
+// Stand-in for $EDITOR in tests: sets the fields of $EDIT_FIELDS
+// (`<field>=<value>`, comma-separated) in the YAML file it is given
+import { readFileSync, writeFileSync } from 'fs';
+
+const [path] = process.argv.slice(2);
+let content = readFileSync(path, 'utf-8');
+for (const assignment of process.env.EDIT_FIELDS.split(',')) {
+	const [field, value] = assignment.split('=');
+	content = content.replace(new RegExp(`^(\\s+${field}:).*$`, 'm'), `$1 ${value}`);
+}
+writeFileSync(path, content);
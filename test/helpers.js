@@ -0,0 +1,71 @@
+import { mkdtempSync, mkdirSync, writeFileSync, readFileSync, rmSync } from 'fs';
+import { spawnSync } from 'child_process';
+import { tmpdir } from 'os';
+import { dirname, join } from 'path';
+import { fileURLToPath } from 'url';
+
+// Tests run the CLI in a temporary project, with the stand-ins for sops and
+// kubectl of test/fixtures/bin first on PATH
+const BIN = fileURLToPath(new URL('../bin/sopsify.js', import.meta.url));
+const FIXTURES_BIN = fileURLToPath(new URL('./fixtures/bin', import.meta.url));
+const EDITOR = fileURLToPath(new URL('./fixtures/editor.js', import.meta.url));
+
+// An age key pair for tests of the rops backend, never used for anything else
+export const AGE_IDENTITY = 'AGE-SECRET-KEY-17PQE9LVREYLFHCCV3C4AU5KY8LYSTLF6AGP3LWFE2W6ZFNE88DQSYMXVTR';
+export const AGE_RECIPIENT = 'age1ktuy5unnm8ghk9gpdd8ps4m2gxs0q423nz8c2z595w3aawrmfqlsrnupq2';
+
+// A project with the given files (path -> content), removed after the test
+export function project(t, files) {
+	const dir = mkdtempSync(join(tmpdir(), 'sopsify-test-'));
+	t.after(() => rmSync(dir, { recursive: true, force: true }));
+	writeFiles(dir, { '.sops.yaml': 'creation_rules:\n  - path_regex: .*\n', ...files });
+	return dir;
+}
+
+export function writeFiles(dir, files) {
+	for (const [path, content] of Object.entries(files)) {
+		mkdirSync(dirname(join(dir, path)), { recursive: true });
+		writeFileSync(join(dir, path), content);
+	}
+}
+
+export function readFile(dir, path) {
+	return readFileSync(join(dir, path), 'utf-8');
+}
+
+// Runs sopsify in the project; `output` is stdout and stderr together
+export function sopsify(dir, args, env = {}) {
+	const home = join(dir, '.home');
+	const { XDG_CONFIG_HOME, XDG_STATE_HOME, SOPS_AGE_KEY_FILE, ...inherited } = process.env;
+	const result = spawnSync(process.execPath, [BIN, ...args], {
+		cwd: dir,
+		encoding: 'utf-8',
+		env: {
+			...inherited,
+			HOME: home,
+			PATH: `${FIXTURES_BIN}:${process.env.PATH}`,
+			FAKE_KUBECTL_SECRETS: join(dir, '.kubectl'),
+			...env,
+		},
+	});
+	return { status: result.status, stdout: result.stdout, stderr: result.stderr, output: result.stdout + result.stderr };
+}
+
+// Runs `sopsify edit` with an editor setting the given fields
+export function edit(dir, args, fields) {
+	const assignments = Object.entries(fields).map(([field, value]) => `${field}=${value}`).join(',');
+	return sopsify(dir, ['edit', ...args], { EDITOR: `${process.execPath} ${EDITOR}`, EDIT_FIELDS: assignments });
+}
+
+// Secrets the stand-in kubectl returns for a namespace: name -> stringData
+export function liveSecrets(dir, namespace, secrets) {
+	for (const [name, stringData] of Object.entries(secrets)) {
+		const data = Object.fromEntries(Object.entries(stringData).map(([key, value]) => [key, Buffer.from(value).toString('base64')]));
+		writeFiles(dir, { [join('.kubectl', namespace, `${name}.json`)]: JSON.stringify({ kind: 'Secret', metadata: { name, namespace }, data }) });
+	}
+}
+
+// The plaintext of an output the stand-in sops encrypted
+export function decrypted(dir, path) {
+	return spawnSync(process.execPath, [join(FIXTURES_BIN, 'sops'), '-d', join(dir, path)], { encoding: 'utf-8' }).stdout;
+}
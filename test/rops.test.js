@@ -0,0 +1,99 @@
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { spawnSync } from 'child_process';
+import { mkdtempSync, rmSync } from 'fs';
+import { tmpdir } from 'os';
+import { join } from 'path';
+import yaml from 'js-yaml';
+import { project, sopsify, readFile, AGE_IDENTITY, AGE_RECIPIENT } from './helpers.js';
+
+const SOPS_CONFIG = `creation_rules:
+  - path_regex: .*
+    encrypted_regex: ^(data|stringData)$
+    age: ${AGE_RECIPIENT}
+`;
+
+const CONFIG = `sopsify:
+  - production:
+    - template: app.yaml
+      values:
+        - key: db-password
+          value: hunter2
+          namespaces: [payments]
+`;
+
+const APP = `apiVersion: v1
+kind: Secret
+metadata:
+  name: app
+stringData:
+  password: \${db-password}
+  port: 5432
+---
+apiVersion: v1
+kind: Secret
+metadata:
+  name: app-admin
+stringData:
+  password: \${db-password}
+`;
+
+const OUTPUT = 'clusters/production/secrets/payments/app.enc.yaml';
+const ENCRYPTED = /^ENC\[AES256_GCM,data:[A-Za-z0-9+/=]+,iv:[A-Za-z0-9+/=]+,tag:[A-Za-z0-9+/=]+,type:(str|int)\]$/;
+// The real sops, when installed, checks the files are what sops writes
+const hasSops = spawnSync('sops', ['--version']).status === 0;
+
+function rendered(t, backend = 'rops') {
+	const dir = project(t, { '.sops.yaml': SOPS_CONFIG, '.sopsify.yaml': CONFIG, 'secrets/app.yaml': APP, 'clusters/production/.keep': '' });
+	// Without the stand-in sops on PATH: rops needs no sops binary
+	const run = sopsify(dir, ['-t', 'secrets', '--backend', backend], { PATH: process.env.PATH });
+	assert.equal(run.status, 0, run.output);
+	return dir;
+}
+
+test('rops writes sops files encrypted for the age recipients of .sops.yaml', t => {
+	const dir = rendered(t);
+	const content = readFile(dir, OUTPUT);
+	const documents = yaml.loadAll(content);
+
+	assert.equal(documents.length, 2);
+	for (const document of documents) {
+		assert.equal(document.kind, 'Secret');
+		assert.ok(document.metadata.name.startsWith('app'));
+		for (const value of Object.values(document.stringData)) {
+			assert.match(value, ENCRYPTED);
+		}
+		assert.equal(document.sops.age[0].recipient, AGE_RECIPIENT);
+		assert.match(document.sops.age[0].enc, /BEGIN AGE ENCRYPTED FILE/);
+		assert.match(document.sops.mac, /^ENC\[AES256_GCM,/);
+		assert.equal(document.sops.encrypted_regex, '^(data|stringData)$');
+	}
+	assert.doesNotMatch(content, /hunter2/);
+});
+
+test('rops decrypts its outputs with the identities of SOPS_AGE_KEY', t => {
+	const dir = rendered(t);
+	const out = mkdtempSync(join(tmpdir(), 'sopsify-decrypted-'));
+	t.after(() => rmSync(out, { recursive: true, force: true }));
+
+	const run = sopsify(dir, ['decrypt', '-o', join(out, 'plain'), '--backend', 'rops'], { SOPS_AGE_KEY: AGE_IDENTITY });
+	assert.equal(run.status, 0, run.output);
+	const [app, admin] = yaml.loadAll(readFile(out, 'plain/production/secrets/payments/app.yaml'));
+	assert.deepEqual(app.stringData, { password: 'hunter2', port: 5432 });
+	assert.deepEqual(admin.stringData, { password: 'hunter2' });
+	assert.equal(app.sops, undefined);
+});
+
+test('builtin is an alias of the rops backend', t => {
+	const dir = rendered(t, 'builtin');
+	assert.match(readFile(dir, OUTPUT), /ENC\[AES256_GCM,/);
+});
+
+test('sops decrypts the outputs of rops', { skip: !hasSops && 'sops is not installed' }, t => {
+	const dir = rendered(t);
+	const result = spawnSync('sops', ['-d', OUTPUT], { cwd: dir, encoding: 'utf-8', env: { ...process.env, SOPS_AGE_KEY: AGE_IDENTITY } });
+	assert.equal(result.status, 0, result.stderr);
+	const [app, admin] = yaml.loadAll(result.stdout);
+	assert.deepEqual(app.stringData, { password: 'hunter2', port: 5432 });
+	assert.deepEqual(admin.stringData, { password: 'hunter2' });
+});